@@ -0,0 +1,211 @@
+use std::sync::Arc;
+
+use crate::hittable::HitRecord;
+use crate::material::{Material, Medium, ScatterResult};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+
+// step in u and v over which bump maps are differentiated
+const BUMP_DELTA: f64 = 5e-4;
+
+// Shades a material with normals read from a tangent-space normal map,
+// whose red, green and blue channels hold the normal along dpdu, along the
+// bitangent and out of the surface, each mapped from [-1, 1] to [0, 1].
+pub struct NormalMap {
+    material: Box<dyn Material>,
+    map: Arc<dyn Texture>,
+}
+
+impl NormalMap {
+    pub fn new(material: Box<dyn Material>, map: Arc<dyn Texture>) -> Self {
+        Self { material, map }
+    }
+
+    // the hit with the normal read from the map
+    fn shaded<'a>(&self, hit_record: &HitRecord<'a>) -> HitRecord<'a> {
+        let outward = outward_normal(hit_record);
+        let tangent = hit_record.dpdu - Vec3::dot(hit_record.dpdu, outward) * outward;
+        if tangent.near_zero() {
+            return hit_record.clone();
+        }
+        let tangent = tangent.unit_vector();
+        let bitangent = Vec3::cross(outward, tangent);
+        let bitangent = if Vec3::dot(bitangent, hit_record.dpdv) < 0.0 {
+            -bitangent
+        } else {
+            bitangent
+        };
+
+        let encoded = self.map.value(hit_record.u, hit_record.v, hit_record.point);
+        let normal = (2.0 * encoded.x() - 1.0) * tangent
+            + (2.0 * encoded.y() - 1.0) * bitangent
+            + (2.0 * encoded.z() - 1.0) * outward;
+        shade(hit_record, normal)
+    }
+}
+
+impl Material for NormalMap {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterResult> {
+        self.material
+            .scatter(ray, &self.shaded(hit_record), sampler)
+    }
+
+    fn shading_normal(&self, hit_record: &HitRecord) -> Vec3 {
+        self.material.shading_normal(&self.shaded(hit_record))
+    }
+
+    fn medium(&self) -> Option<Medium<'_>> {
+        self.material.medium()
+    }
+}
+
+// Shades a material as if its surface were displaced along the normal by
+// the first channel of a height texture times scale, without moving it.
+pub struct BumpMap {
+    material: Box<dyn Material>,
+    height: Arc<dyn Texture>,
+    scale: f64,
+}
+
+impl BumpMap {
+    pub fn new(material: Box<dyn Material>, height: Arc<dyn Texture>, scale: f64) -> Self {
+        Self {
+            material,
+            height,
+            scale,
+        }
+    }
+
+    // the hit with the normal of the bumped surface
+    fn shaded<'a>(&self, hit_record: &HitRecord<'a>) -> HitRecord<'a> {
+        let (u, v, point) = (hit_record.u, hit_record.v, hit_record.point);
+        let (dpdu, dpdv) = (hit_record.dpdu, hit_record.dpdv);
+        let height = |u: f64, v: f64, point| self.scale * self.height.value(u, v, point).x();
+
+        // the derivatives of the displaced point p + h(u, v) n, leaving out
+        // the change of the normal for the small displacements of bumps
+        let h = height(u, v, point);
+        let dhdu = (height(u + BUMP_DELTA, v, point + BUMP_DELTA * dpdu) - h) / BUMP_DELTA;
+        let dhdv = (height(u, v + BUMP_DELTA, point + BUMP_DELTA * dpdv) - h) / BUMP_DELTA;
+        let outward = outward_normal(hit_record);
+        let normal = Vec3::cross(dpdu + dhdu * outward, dpdv + dhdv * outward);
+        if normal.near_zero() {
+            return hit_record.clone();
+        }
+        let normal = if Vec3::dot(normal, outward) < 0.0 {
+            -normal
+        } else {
+            normal
+        };
+        shade(hit_record, normal)
+    }
+}
+
+impl Material for BumpMap {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterResult> {
+        self.material
+            .scatter(ray, &self.shaded(hit_record), sampler)
+    }
+
+    fn shading_normal(&self, hit_record: &HitRecord) -> Vec3 {
+        self.material.shading_normal(&self.shaded(hit_record))
+    }
+
+    fn medium(&self) -> Option<Medium<'_>> {
+        self.material.medium()
+    }
+}
+
+fn outward_normal(hit_record: &HitRecord) -> Vec3 {
+    if hit_record.front_face {
+        hit_record.normal
+    } else {
+        -hit_record.normal
+    }
+}
+
+// The hit with the given outward shading normal facing the ray like the
+// geometric one. Normals tilted past the surface are left out.
+fn shade<'a>(hit_record: &HitRecord<'a>, outward: Vec3) -> HitRecord<'a> {
+    let normal = if hit_record.front_face {
+        outward
+    } else {
+        -outward
+    };
+    let mut shaded = hit_record.clone();
+    if !normal.near_zero() && Vec3::dot(normal, hit_record.normal) > 0.0 {
+        shaded.normal = normal.unit_vector();
+    }
+    shaded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable::Hittable;
+    use crate::material::Metal;
+    use crate::math::interval::Interval;
+    use crate::point::Point3;
+    use crate::sampler::SamplerKind;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+
+    // a mirror whose normals tilt towards dpdu
+    fn tilted_mirror() -> Box<dyn Material> {
+        let mirror = Box::new(Metal::new(Color::new(1.0, 1.0, 1.0), 0.0));
+        let map = SolidColor::new(Color::new(0.8, 0.5, 0.9));
+        Box::new(NormalMap::new(mirror, Arc::new(map)))
+    }
+
+    #[test]
+    fn shading_normals_are_the_normals_surfaces_scatter_with() {
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, -2.0), 1.0, tilted_mirror());
+        let ray = Ray::new(Point3::new(0.2, 0.3, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit_record = sphere
+            .hit(&ray, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        let normal = hit_record.material.shading_normal(&hit_record);
+        assert!(Vec3::dot(normal, hit_record.normal) < 0.99);
+        assert!((normal.length() - 1.0).abs() < 1e-9);
+
+        let mut sampler = SamplerKind::Independent.create(1, 0);
+        sampler.start_pixel_sample(0, 0, 0);
+        let scattered = hit_record
+            .material
+            .scatter(&ray, &hit_record, sampler.as_mut())
+            .unwrap()
+            .scattered;
+        let reflected = Vec3::reflect(ray.direction(), normal);
+        assert!((scattered.direction() - reflected).length() < 1e-9);
+    }
+
+    #[test]
+    fn bump_maps_keep_the_normal_of_flat_heights() {
+        let mirror = Box::new(Metal::new(Color::new(1.0, 1.0, 1.0), 0.0));
+        let flat = Arc::new(SolidColor::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Sphere::new(
+            Point3::new(0.0, 0.0, -2.0),
+            1.0,
+            Box::new(BumpMap::new(mirror, flat, 0.1)),
+        );
+        let ray = Ray::new(Point3::new(0.2, 0.3, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit_record = sphere
+            .hit(&ray, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        let normal = hit_record.material.shading_normal(&hit_record);
+        assert!((normal - hit_record.normal).length() < 1e-9);
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use crate::hittable::{HitRecord, Hittable};
+use crate::math::interval::Interval;
+use crate::ray::Ray;
+use crate::texture::Texture;
+
+// An object with holes cut into it where the first channel of an opacity
+// texture is below 1, like leaves and fences modelled as textured quads.
+// Partially opaque hits are kept with the probability of their opacity,
+// decided by the alpha sample of the ray, which blends the object over what
+// lies behind it on average. Rays without one see partially opaque hits
+// where the opacity is above one half. Overlapping cutouts share the sample
+// of a ray, so one only shows through the holes of the other where it is
+// more opaque. Every ray testing the object skips the holes, whatever it is
+// traced for, except on materials filled with a medium, whose surfaces are
+// all kept so that paths inside always find their way out.
+pub struct Cutout {
+    object: Box<dyn Hittable>,
+    opacity: Arc<dyn Texture>,
+}
+
+impl Cutout {
+    pub fn new(object: Box<dyn Hittable>, opacity: Arc<dyn Texture>) -> Self {
+        Self { object, opacity }
+    }
+}
+
+impl Hittable for Cutout {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord<'_>> {
+        let mut min = interval.min;
+        let mut alpha_sample = ray.alpha_sample().unwrap_or(0.5);
+        loop {
+            let hit_record = self.object.hit(ray, Interval::new(min, interval.max))?;
+            if hit_record.material.medium().is_some() {
+                return Some(hit_record);
+            }
+            let opacity = self
+                .opacity
+                .value(hit_record.u, hit_record.v, hit_record.point)
+                .x();
+            if opacity >= 1.0 || (opacity > 0.0 && opacity > alpha_sample) {
+                return Some(hit_record);
+            }
+            // Past the hole, the sample is uniform over [opacity, 1), and
+            // stretched back over [0, 1) it decides the next hit.
+            if opacity > 0.0 {
+                alpha_sample = (alpha_sample - opacity) / (1.0 - opacity);
+            }
+            // look for the next hit behind the hole
+            min = hit_record.t;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::{Dielectric, Lambertian, Material};
+    use crate::point::Point3;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+    use crate::vec3::Vec3;
+
+    fn cutout(material: Box<dyn Material>, opacity: f64) -> Cutout {
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, -3.0), 1.0, material);
+        let opacity = SolidColor::new(Color::new(opacity, opacity, opacity));
+        Cutout::new(Box::new(sphere), Arc::new(opacity))
+    }
+
+    // front and back hits and misses of stratified alpha samples
+    fn hit_counts(cutout: &Cutout) -> (u32, u32, u32) {
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut counts = (0, 0, 0);
+        for i in 0..1000 {
+            let ray = ray.clone().with_alpha_sample((i as f64 + 0.5) / 1000.0);
+            match cutout.hit(&ray, Interval::new(0.001, f64::INFINITY)) {
+                Some(hit_record) if hit_record.front_face => counts.0 += 1,
+                Some(_) => counts.1 += 1,
+                None => counts.2 += 1,
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn partially_opaque_surfaces_are_hit_by_their_opacity() {
+        let lambertian = Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        // the back is hit by 0.4 of the 0.6 of samples passing the front
+        assert_eq!(hit_counts(&cutout(lambertian, 0.4)), (400, 240, 360));
+    }
+
+    #[test]
+    fn surfaces_of_media_are_never_skipped() {
+        let glass = Box::new(Dielectric::new(1.5));
+        assert_eq!(hit_counts(&cutout(glass, 0.0)), (1000, 0, 0));
+    }
+}
@@ -0,0 +1,225 @@
+use crate::color::Color;
+use crate::scene::adaptive::luminance;
+
+// row-major matrices from linear sRGB (Rec.709 primaries, D65) to the other
+// output primaries
+const SRGB_TO_DISPLAY_P3: [[f64; 3]; 3] = [
+    [0.8224621, 0.1775380, 0.0000000],
+    [0.0331941, 0.9668058, 0.0000000],
+    [0.0170827, 0.0723974, 0.9105199],
+];
+const SRGB_TO_REC2020: [[f64; 3]; 3] = [
+    [0.6274040, 0.3292820, 0.0433136],
+    [0.0690970, 0.9195400, 0.0113612],
+    [0.0163916, 0.0880132, 0.8955950],
+];
+
+// Stephen Hill's fit of the ACES reference rendering and output transforms,
+// which work in their own primaries
+const ACES_INPUT: [[f64; 3]; 3] = [
+    [0.59719, 0.35458, 0.04823],
+    [0.07600, 0.90834, 0.01566],
+    [0.02840, 0.13383, 0.83777],
+];
+const ACES_OUTPUT: [[f64; 3]; 3] = [
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+];
+
+// Troy Sobotka's AgX inset and outset matrices, and its log2 exposure range
+const AGX_INSET: [[f64; 3]; 3] = [
+    [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+    [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+    [0.0423756549057051, 0.0784336, 0.879142973793104],
+];
+const AGX_OUTSET: [[f64; 3]; 3] = [
+    [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+    [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+    [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+];
+const AGX_MIN_EV: f64 = -12.47393;
+const AGX_MAX_EV: f64 = 4.026069;
+
+// Operators compressing the unbounded scene colors into the [0, 1] range of
+// displays.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMap {
+    // clips everything above 1
+    Clamp,
+    // L / (1 + L) on the luminance, which never quite reaches white
+    Reinhard,
+    // Reinhard reaching white at the given luminance
+    ExtendedReinhard { white: f64 },
+    // fitted ACES filmic curve
+    Aces,
+    // AgX, which desaturates highlights towards white instead of skewing hues
+    Agx,
+    // John Hable's Uncharted 2 filmic curve
+    Hable,
+}
+
+// Color spaces of display referred output, all with a D65 white point.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    // DCI-P3 primaries with the sRGB transfer function
+    DisplayP3,
+    // Rec.2020 primaries with the Rec.2020 transfer function
+    Rec2020,
+}
+
+// How linear scene colors are turned into the encoded values of 8-bit
+// images. Colors are exposed, tone mapped in linear sRGB, converted to the
+// primaries of the output color space and encoded with its transfer
+// function.
+#[derive(Clone, Copy, Debug)]
+pub struct DisplayTransform {
+    // in stops, each doubling the brightness
+    pub exposure: f64,
+    pub tone_map: ToneMap,
+    pub color_space: ColorSpace,
+}
+
+impl Default for DisplayTransform {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            tone_map: ToneMap::Clamp,
+            color_space: ColorSpace::Srgb,
+        }
+    }
+}
+
+impl DisplayTransform {
+    // Maps a linear sRGB scene color to encoded display values in [0, 1].
+    pub fn apply(&self, color: Color) -> Color {
+        let exposed = 2f64.powf(self.exposure) * clamp_negative(color);
+        let display = self.tone_map.apply(exposed);
+
+        let display = match self.color_space {
+            ColorSpace::Srgb => display,
+            ColorSpace::DisplayP3 => transform(&SRGB_TO_DISPLAY_P3, display),
+            ColorSpace::Rec2020 => transform(&SRGB_TO_REC2020, display),
+        };
+
+        let encode = |c: f64| {
+            let c = c.clamp(0.0, 1.0);
+            match self.color_space {
+                ColorSpace::Srgb | ColorSpace::DisplayP3 => srgb_transfer(c),
+                ColorSpace::Rec2020 => rec2020_transfer(c),
+            }
+        };
+        Color::new(
+            encode(display.x()),
+            encode(display.y()),
+            encode(display.z()),
+        )
+    }
+}
+
+impl ToneMap {
+    // Maps non-negative linear colors to linear display colors, which may
+    // still exceed 1 slightly for the luminance based operators.
+    pub fn apply(self, color: Color) -> Color {
+        match self {
+            ToneMap::Clamp => color,
+            ToneMap::Reinhard => scale_luminance(color, |l| l / (1.0 + l)),
+            ToneMap::ExtendedReinhard { white } => {
+                scale_luminance(color, |l| l * (1.0 + l / (white * white)) / (1.0 + l))
+            }
+            ToneMap::Aces => {
+                let v = transform(&ACES_INPUT, color);
+                let fit = |v: f64| {
+                    let a = v * (v + 0.0245786) - 0.000090537;
+                    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+                    a / b
+                };
+                transform(&ACES_OUTPUT, map_channels(v, fit))
+            }
+            ToneMap::Agx => {
+                let v = transform(&AGX_INSET, color);
+                let v = map_channels(v, |c| {
+                    let ev = c.max(1e-10).log2().clamp(AGX_MIN_EV, AGX_MAX_EV);
+                    agx_contrast((ev - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV))
+                });
+                // the curve output is display encoded with a 2.2 gamma
+                map_channels(transform(&AGX_OUTSET, v), |c| c.max(0.0).powf(2.2))
+            }
+            ToneMap::Hable => {
+                const WHITE: f64 = 11.2;
+                const EXPOSURE_BIAS: f64 = 2.0;
+                let scale = 1.0 / hable(WHITE);
+                map_channels(color, |c| hable(EXPOSURE_BIAS * c) * scale)
+            }
+        }
+    }
+}
+
+fn hable(x: f64) -> f64 {
+    const A: f64 = 0.15;
+    const B: f64 = 0.50;
+    const C: f64 = 0.10;
+    const D: f64 = 0.20;
+    const E: f64 = 0.02;
+    const F: f64 = 0.30;
+    (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+}
+
+// polynomial approximation of the AgX base contrast curve, x is in [0, 1]
+fn agx_contrast(x: f64) -> f64 {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+        - 0.00232
+}
+
+fn scale_luminance(color: Color, curve: impl Fn(f64) -> f64) -> Color {
+    let l = luminance(color);
+    if l <= 0.0 {
+        return color;
+    }
+    (curve(l) / l) * color
+}
+
+fn clamp_negative(color: Color) -> Color {
+    map_channels(color, |c| c.max(0.0))
+}
+
+fn map_channels(color: Color, f: impl Fn(f64) -> f64) -> Color {
+    Color::new(f(color.x()), f(color.y()), f(color.z()))
+}
+
+fn transform(m: &[[f64; 3]; 3], c: Color) -> Color {
+    let row = |r: &[f64; 3]| r[0] * c.x() + r[1] * c.y() + r[2] * c.z();
+    Color::new(row(&m[0]), row(&m[1]), row(&m[2]))
+}
+
+// the piecewise sRGB encoding, c is in [0, 1]
+pub fn srgb_transfer(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// decodes sRGB encoded values in [0, 1] to linear
+pub fn srgb_inverse_transfer(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// the Rec.2020 (and Rec.709) camera encoding, c is in [0, 1]
+pub fn rec2020_transfer(c: f64) -> f64 {
+    const ALPHA: f64 = 1.09929682680944;
+    const BETA: f64 = 0.018053968510807;
+    if c < BETA {
+        4.5 * c
+    } else {
+        ALPHA * c.powf(0.45) - (ALPHA - 1.0)
+    }
+}
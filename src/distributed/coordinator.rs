@@ -0,0 +1,289 @@
+use std::collections::VecDeque;
+use std::env;
+use std::io::{self, BufRead, BufReader, BufWriter};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::distributed::protocol::{
+    receive_hello, receive_tile_rendered, send_hello, send_render_tile, send_scene,
+};
+use crate::scene::description::SceneDescription;
+use crate::scene::film::Film;
+use crate::scene::tile::Region;
+
+// Tiles waiting for a worker, and the number of tiles currently being
+// rendered. A worker that fails puts its tile back, so the remaining workers
+// keep waiting for work until nothing is in flight.
+struct Queue {
+    pending: VecDeque<Region>,
+    in_flight: usize,
+}
+
+struct Schedule {
+    queue: Mutex<Queue>,
+    changed: Condvar,
+}
+
+impl Schedule {
+    // Blocks until a tile is available, or returns None once every tile has
+    // been rendered.
+    fn take(&self) -> Option<Region> {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            if let Some(tile) = queue.pending.pop_front() {
+                queue.in_flight += 1;
+                return Some(tile);
+            }
+            if queue.in_flight == 0 {
+                return None;
+            }
+            queue = self.changed.wait(queue).unwrap();
+        }
+    }
+
+    fn finish(&self) {
+        self.queue.lock().unwrap().in_flight -= 1;
+        self.changed.notify_all();
+    }
+
+    fn requeue(&self, tile: Region) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.in_flight -= 1;
+        queue.pending.push_front(tile);
+        self.changed.notify_all();
+    }
+}
+
+// Renders tiles of the scene on the workers at the given addresses and
+// merges the results. Every worker renders one tile at a time, and tiles of
+// workers that fail are handed to the remaining ones. A worker that takes
+// longer than timeout to connect, or to answer any message, counts as
+// failed, so a hung worker can't hold on to its tile.
+pub fn render_distributed(
+    description: &SceneDescription,
+    workers: &[String],
+    tiles: Vec<Region>,
+    timeout: Duration,
+) -> io::Result<Film> {
+    let tile_count = tiles.len();
+    let schedule = Schedule {
+        queue: Mutex::new(Queue {
+            pending: tiles.into(),
+            in_flight: 0,
+        }),
+        changed: Condvar::new(),
+    };
+    let film = Mutex::new(Film::new(
+        description.image_width,
+        description.image_height,
+        description.filter,
+    ));
+
+    thread::scope(|scope| {
+        for address in workers {
+            let schedule = &schedule;
+            let film = &film;
+            scope.spawn(move || {
+                if let Err(err) = run_worker(address, description, timeout, schedule, film) {
+                    eprintln!("worker {} failed: {}", address, err);
+                }
+            });
+        }
+    });
+
+    let remaining = schedule.queue.into_inner().unwrap().pending.len();
+    if remaining > 0 {
+        return Err(io::Error::other(format!(
+            "all workers failed with {} of {} tiles left to render",
+            remaining, tile_count
+        )));
+    }
+
+    Ok(film.into_inner().unwrap())
+}
+
+fn run_worker(
+    address: &str,
+    description: &SceneDescription,
+    timeout: Duration,
+    schedule: &Schedule,
+    film: &Mutex<Film>,
+) -> io::Result<()> {
+    let stream = connect(address, timeout)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    send_hello(&mut writer)?;
+    receive_hello(&mut reader)?;
+    send_scene(&mut writer, description)?;
+
+    let samples = 0..description.samples_per_pixel;
+    while let Some(tile) = schedule.take() {
+        let rendered = send_render_tile(&mut writer, tile, &samples)
+            .and_then(|_| receive_tile_rendered(&mut reader, description.filter));
+        match rendered {
+            Ok(tile_film) => {
+                film.lock().unwrap().add(&tile_film);
+                schedule.finish();
+            }
+            Err(err) => {
+                schedule.requeue(tile);
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Connects to the first of the addresses a host:port resolves to that
+// accepts the connection within timeout.
+fn connect(address: &str, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for socket_address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_error = Some(err),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} resolves to no addresses", address),
+        )
+    }))
+}
+
+// Worker processes running on this machine, started from the current
+// executable. They are killed when this is dropped.
+pub struct LocalWorkers {
+    children: Vec<Child>,
+    addresses: Vec<String>,
+}
+
+impl LocalWorkers {
+    pub fn spawn(count: u32) -> io::Result<Self> {
+        let executable = env::current_exe()?;
+        let mut workers = LocalWorkers {
+            children: Vec::new(),
+            addresses: Vec::new(),
+        };
+
+        for _ in 0..count {
+            let mut child = Command::new(&executable)
+                .args(["serve-worker", "--port", "0"])
+                .stdout(Stdio::piped())
+                .spawn()?;
+
+            // the worker announces the address it listens on as its first line
+            let mut line = String::new();
+            let stdout = child.stdout.take().expect("worker stdout is piped");
+            BufReader::new(stdout).read_line(&mut line)?;
+            workers.children.push(child);
+
+            match line.trim().strip_prefix("listening on ") {
+                Some(address) => workers.addresses.push(address.to_string()),
+                None => {
+                    return Err(io::Error::other(format!(
+                        "local worker did not report its address: {:?}",
+                        line
+                    )))
+                }
+            }
+        }
+
+        Ok(workers)
+    }
+
+    pub fn addresses(&self) -> &[String] {
+        &self.addresses
+    }
+}
+
+impl Drop for LocalWorkers {
+    fn drop(&mut self) {
+        for child in &mut self.children {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::time::Instant;
+
+    use super::*;
+    use crate::color::Color;
+    use crate::refractive_index::RefractiveIndex;
+    use crate::sampler::SamplerKind;
+    use crate::scene::filter::Filter;
+
+    fn description() -> SceneDescription {
+        SceneDescription {
+            seed: 1,
+            image_width: 16,
+            image_height: 8,
+            samples_per_pixel: 1,
+            max_depth: 2,
+            filter: Filter::box_filter(None),
+            sampler: SamplerKind::Independent,
+            lens: None,
+            spectral: false,
+            glass: RefractiveIndex::Constant(1.5),
+            glass_absorption: Color::new(0.0, 0.0, 0.0),
+            thin_film: None,
+            subsurface: None,
+            clearcoat: None,
+            rust: false,
+            bump: None,
+            displacement: None,
+            normal_map: None,
+            cutout: false,
+            toon: None,
+        }
+    }
+
+    #[test]
+    fn hung_workers_give_back_their_tiles() {
+        // greets the coordinator, then never renders the tile it asks for
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let worker = thread::spawn(move || {
+            let mut stream = listener.accept().unwrap().0;
+            send_hello(&mut stream).unwrap();
+            // holds the connection open until the coordinator gives up
+            let _ = io::copy(&mut stream, &mut io::sink());
+        });
+        let tile = Region::new(0, 0, 16, 8);
+        let schedule = Schedule {
+            queue: Mutex::new(Queue {
+                pending: vec![tile].into(),
+                in_flight: 0,
+            }),
+            changed: Condvar::new(),
+        };
+        let film = Mutex::new(Film::new(16, 8, Filter::box_filter(None)));
+
+        let start = Instant::now();
+        let result = run_worker(
+            &address,
+            &description(),
+            Duration::from_millis(200),
+            &schedule,
+            &film,
+        );
+        assert!(result.is_err());
+        assert!(start.elapsed() < Duration::from_secs(10));
+        let queue = schedule.queue.into_inner().unwrap();
+        assert_eq!((queue.pending.len(), queue.in_flight), (1, 0));
+        worker.join().unwrap();
+    }
+}
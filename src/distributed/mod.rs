@@ -0,0 +1,6 @@
+mod coordinator;
+mod protocol;
+mod worker;
+
+pub use coordinator::{render_distributed, LocalWorkers};
+pub use worker::serve;
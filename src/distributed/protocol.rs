@@ -0,0 +1,249 @@
+use std::io::{self, Read, Write};
+use std::ops::Range;
+
+use crate::encoding::{read_string, read_u32, write_string, write_u32};
+use crate::scene::description::SceneDescription;
+use crate::scene::film::Film;
+use crate::scene::filter::Filter;
+use crate::scene::tile::Region;
+
+// A connection starts with both sides greeting each other with the
+// protocol version they speak, then the coordinator sends the scene,
+// followed by any number of tile requests that the worker answers one at a
+// time.
+const MAGIC: &[u8; 8] = b"RRAYNODE";
+// bumped whenever a message or the scene description changes, since
+// processes built from different versions would misread each other
+const VERSION: u32 = 2;
+
+const SCENE: u32 = 1;
+const RENDER_TILE: u32 = 2;
+const TILE_RENDERED: u32 = 3;
+const FAILED: u32 = 4;
+
+pub fn send_hello(writer: &mut impl Write) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    write_u32(writer, VERSION)?;
+    writer.flush()
+}
+
+// Reads the greeting of the other side, failing unless it speaks the same
+// protocol version.
+pub fn receive_hello(reader: &mut impl Read) -> io::Result<()> {
+    let mut magic = [0; 8];
+    reader
+        .read_exact(&mut magic)
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "peer closed the connection without a greeting, it may speak an older protocol",
+            ),
+            _ => err,
+        })?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "peer is not an rray process",
+        ));
+    }
+    let version = read_u32(reader)?;
+    if version != VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "peer speaks protocol version {}, this process version {}",
+                version, VERSION
+            ),
+        ));
+    }
+    Ok(())
+}
+
+pub fn send_scene(writer: &mut impl Write, description: &SceneDescription) -> io::Result<()> {
+    write_u32(writer, SCENE)?;
+    description.write_state(writer)?;
+    writer.flush()
+}
+
+pub fn receive_scene(reader: &mut impl Read) -> io::Result<SceneDescription> {
+    expect_message(reader, SCENE)?;
+    SceneDescription::read_state(reader)
+}
+
+pub fn send_render_tile(
+    writer: &mut impl Write,
+    tile: Region,
+    samples: &Range<u32>,
+) -> io::Result<()> {
+    write_u32(writer, RENDER_TILE)?;
+    write_u32(writer, tile.x0)?;
+    write_u32(writer, tile.y0)?;
+    write_u32(writer, tile.x1)?;
+    write_u32(writer, tile.y1)?;
+    write_u32(writer, samples.start)?;
+    write_u32(writer, samples.end)?;
+    writer.flush()
+}
+
+// Returns None once the coordinator has closed the connection.
+// Receives the next tile request for an image of the given size, or None
+// once the coordinator closes the connection. Tiles that are empty or reach
+// outside of the image are an error.
+pub fn receive_render_tile(
+    reader: &mut impl Read,
+    image_width: u32,
+    image_height: u32,
+) -> io::Result<Option<(Region, Range<u32>)>> {
+    match read_u32(reader) {
+        Ok(RENDER_TILE) => {}
+        Ok(other) => return Err(unexpected_message(other)),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+
+    let tile = Region::new(
+        read_u32(reader)?,
+        read_u32(reader)?,
+        read_u32(reader)?,
+        read_u32(reader)?,
+    );
+    let samples = read_u32(reader)?..read_u32(reader)?;
+    if tile.x0 >= tile.x1 || tile.y0 >= tile.y1 || tile.x1 > image_width || tile.y1 > image_height {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "tile {} is empty or lies outside of the {}x{} image",
+                tile, image_width, image_height
+            ),
+        ));
+    }
+    Ok(Some((tile, samples)))
+}
+
+pub fn send_tile_rendered(writer: &mut impl Write, film: &Film) -> io::Result<()> {
+    write_u32(writer, TILE_RENDERED)?;
+    film.write_state(writer)?;
+    writer.flush()
+}
+
+// Receives a rendered tile, or the error the worker failed with.
+pub fn receive_tile_rendered(reader: &mut impl Read, filter: Filter) -> io::Result<Film> {
+    let result = match read_u32(reader) {
+        Ok(TILE_RENDERED) => Film::read_state(reader, filter),
+        Ok(FAILED) => read_string(reader).and_then(|message| Err(io::Error::other(message))),
+        Ok(other) => Err(unexpected_message(other)),
+        Err(err) => Err(err),
+    };
+    result.map_err(|err| match err.kind() {
+        io::ErrorKind::UnexpectedEof => io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection closed before the tile was rendered",
+        ),
+        _ => err,
+    })
+}
+
+pub fn send_failed(writer: &mut impl Write, message: &str) -> io::Result<()> {
+    write_u32(writer, FAILED)?;
+    write_string(writer, message)?;
+    writer.flush()
+}
+
+fn expect_message(reader: &mut impl Read, expected: u32) -> io::Result<()> {
+    match read_u32(reader)? {
+        message if message == expected => Ok(()),
+        other => Err(unexpected_message(other)),
+    }
+}
+
+fn unexpected_message(message: u32) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected message {}", message),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peers_of_the_same_version_greet_each_other() {
+        let mut data = Vec::new();
+        send_hello(&mut data).unwrap();
+        receive_hello(&mut data.as_slice()).unwrap();
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut data = MAGIC.to_vec();
+        write_u32(&mut data, VERSION + 1).unwrap();
+        let err = receive_hello(&mut data.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("version"), "{}", err);
+    }
+
+    #[test]
+    fn peers_without_a_greeting_are_rejected() {
+        // a coordinator from before the greeting starts with the scene
+        let mut data = Vec::new();
+        write_u32(&mut data, SCENE).unwrap();
+        write_u32(&mut data, 0).unwrap();
+        let err = receive_hello(&mut data.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn tile_requests_round_trip() {
+        let mut data = Vec::new();
+        send_render_tile(&mut data, Region::new(32, 64, 48, 80), &(0..16)).unwrap();
+        let mut reader = data.as_slice();
+        let (tile, samples) = receive_render_tile(&mut reader, 48, 80).unwrap().unwrap();
+        assert_eq!((tile.x0, tile.y0, tile.x1, tile.y1), (32, 64, 48, 80));
+        assert_eq!(samples, 0..16);
+        // the coordinator closing the connection ends the requests
+        assert!(receive_render_tile(&mut reader, 48, 80).unwrap().is_none());
+    }
+
+    #[test]
+    fn tiles_outside_of_the_image_are_rejected() {
+        let tiles = [
+            Region::new(32, 64, 49, 80),
+            Region::new(32, 64, 48, 81),
+            Region::new(32, 64, 32, 80),
+            Region::new(40, 64, 32, 80),
+            Region::new(32, 80, 48, 80),
+        ];
+        for tile in tiles {
+            let mut data = Vec::new();
+            send_render_tile(&mut data, tile, &(0..16)).unwrap();
+            let err = receive_render_tile(&mut data.as_slice(), 48, 80).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", tile);
+        }
+    }
+
+    #[test]
+    fn worker_failures_are_reported() {
+        let mut data = Vec::new();
+        send_failed(&mut data, "no such lens").unwrap();
+        let err = receive_tile_rendered(&mut data.as_slice(), Filter::box_filter(None))
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "no such lens");
+    }
+
+    #[test]
+    fn rendered_tiles_round_trip() {
+        let filter = Filter::box_filter(None);
+        let mut film = Film::for_tile(8, 8, Region::new(0, 0, 4, 4), filter);
+        film.add_sample(1.5, 2.5, crate::color::Color::new(0.5, 1.0, 2.0));
+        let mut data = Vec::new();
+        send_tile_rendered(&mut data, &film).unwrap();
+        let read = receive_tile_rendered(&mut data.as_slice(), filter).unwrap();
+        let (read, written) = (read.pixels(), film.pixels());
+        assert_eq!(read.len(), written.len());
+        for (a, b) in read.iter().zip(&written) {
+            assert_eq!((a.x(), a.y(), a.z()), (b.x(), b.y(), b.z()));
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::io::{self, BufReader, BufWriter};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use crate::distributed::protocol::{
+    receive_hello, receive_render_tile, receive_scene, send_failed, send_hello, send_tile_rendered,
+};
+
+// Serves coordinators connecting to listener until the process is stopped.
+// Every connection is handled on its own thread.
+pub fn serve(listener: TcpListener) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        thread::spawn(move || {
+            let peer = stream
+                .peer_addr()
+                .map(|address| address.to_string())
+                .unwrap_or_default();
+            if let Err(err) = handle_connection(stream) {
+                eprintln!("connection from {} failed: {}", peer, err);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    // the greeting is answered even on a mismatch, so the coordinator can
+    // tell why the connection is closed
+    let hello = receive_hello(&mut reader);
+    send_hello(&mut writer)?;
+    hello?;
+
+    let description = receive_scene(&mut reader)?;
+    let camera = match description.build_camera() {
+        Ok(camera) => camera,
+        Err(err) => return send_failed(&mut writer, &err.to_string()),
+    };
+    let world = match description.build_world() {
+        Ok(world) => world,
+        Err(err) => return send_failed(&mut writer, &err.to_string()),
+    };
+
+    loop {
+        let request = receive_render_tile(
+            &mut reader,
+            description.image_width,
+            description.image_height,
+        );
+        let (tile, samples) = match request {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                // tells the coordinator what went wrong, if it still listens
+                let _ = send_failed(&mut writer, &err.to_string());
+                return Err(err);
+            }
+        };
+        let film = camera.render_tile(&world, tile, samples);
+        send_tile_rendered(&mut writer, &film)?;
+    }
+}
@@ -0,0 +1,58 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+// Errors returned by rendering and by reading and writing scene, lens,
+// checkpoint and image files.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    // a render or camera parameter that cannot be used, such as a zero
+    // image size
+    InvalidParameter(String),
+    // a malformed scene or lens description
+    SceneParse(String),
+    // a file of a kind or version that can't be read or written
+    UnsupportedFormat(String),
+    // the render was stopped through its cancellation token
+    Cancelled,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    // an I/O error with the file it happened on
+    pub fn file(path: &Path, err: io::Error) -> Self {
+        Error::Io(io::Error::new(
+            err.kind(),
+            format!("{}: {}", path.display(), err),
+        ))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::InvalidParameter(message) => write!(f, "invalid parameter: {}", message),
+            Error::SceneParse(message) => write!(f, "failed to parse scene: {}", message),
+            Error::UnsupportedFormat(message) => write!(f, "unsupported format: {}", message),
+            Error::Cancelled => write!(f, "render cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -0,0 +1,241 @@
+use std::io::{self, Write};
+
+// Writes uncompressed single part scanline OpenEXR images with 32-bit float
+// channels, which is enough for multilayer AOV output.
+
+const MAGIC: u32 = 20000630;
+const VERSION: u32 = 2;
+const PIXEL_TYPE_FLOAT: i32 = 2;
+
+// A channel with one value per pixel in row-major order. Channels of layers
+// are named "layer.channel", e.g. "albedo.R".
+pub struct ExrChannel {
+    pub name: String,
+    pub values: Vec<f32>,
+}
+
+pub fn write_exr(
+    writer: &mut impl Write,
+    width: u32,
+    height: u32,
+    mut channels: Vec<ExrChannel>,
+) -> io::Result<()> {
+    // readers expect the channels in alphabetical order, in the header as
+    // well as in the pixel data
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+    // the data window and the scanline sizes are stored as i32
+    let line_size = width as usize * channels.len() * 4;
+    if width == 0
+        || height == 0
+        || width > i32::MAX as u32
+        || height > i32::MAX as u32
+        || line_size > i32::MAX as usize
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{}x{} image with {} channels doesn't fit in an EXR file",
+                width,
+                height,
+                channels.len()
+            ),
+        ));
+    }
+
+    let pixel_count = width as usize * height as usize;
+    if let Some(channel) = channels.iter().find(|c| c.values.len() != pixel_count) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "channel {} has {} values for {} pixels",
+                channel.name,
+                channel.values.len(),
+                pixel_count
+            ),
+        ));
+    }
+
+    let mut header = Vec::new();
+    header.extend(MAGIC.to_le_bytes());
+    header.extend(VERSION.to_le_bytes());
+
+    let mut channel_list = Vec::new();
+    for channel in &channels {
+        channel_list.extend(channel.name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend(PIXEL_TYPE_FLOAT.to_le_bytes());
+        // linear flag and reserved bytes, then the x and y sampling
+        channel_list.extend([0, 0, 0, 0]);
+        channel_list.extend(1i32.to_le_bytes());
+        channel_list.extend(1i32.to_le_bytes());
+    }
+    channel_list.push(0);
+    attribute(&mut header, "channels", "chlist", &channel_list);
+
+    attribute(&mut header, "compression", "compression", &[0]);
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1.0f32.to_le_bytes(),
+    );
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1.0f32.to_le_bytes(),
+    );
+    header.push(0);
+    writer.write_all(&header)?;
+
+    // one scanline per chunk, each starting with its y and data size
+    let chunk_size = 8 + line_size;
+    let first_chunk = header.len() + 8 * height as usize;
+    for y in 0..height as usize {
+        writer.write_all(&((first_chunk + y * chunk_size) as u64).to_le_bytes())?;
+    }
+
+    let mut line = Vec::with_capacity(chunk_size);
+    for y in 0..height as usize {
+        line.clear();
+        line.extend((y as i32).to_le_bytes());
+        line.extend((line_size as i32).to_le_bytes());
+        for channel in &channels {
+            let row = &channel.values[y * width as usize..(y + 1) * width as usize];
+            for value in row {
+                line.extend(value.to_le_bytes());
+            }
+        }
+        writer.write_all(&line)?;
+    }
+    Ok(())
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend(name.as_bytes());
+    header.push(0);
+    header.extend(kind.as_bytes());
+    header.push(0);
+    header.extend((value.len() as i32).to_le_bytes());
+    header.extend(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_u32_at(data: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+    }
+
+    fn read_cstr(data: &[u8], at: &mut usize) -> String {
+        let end = *at + data[*at..].iter().position(|&b| b == 0).unwrap();
+        let s = String::from_utf8(data[*at..end].to_vec()).unwrap();
+        *at = end + 1;
+        s
+    }
+
+    // the attributes of the header as (name, type, value), and where the
+    // header ends
+    fn attributes(data: &[u8]) -> (Vec<(String, String, Vec<u8>)>, usize) {
+        let mut at = 8;
+        let mut attributes = Vec::new();
+        while data[at] != 0 {
+            let name = read_cstr(data, &mut at);
+            let kind = read_cstr(data, &mut at);
+            let size = read_u32_at(data, at) as usize;
+            at += 4;
+            attributes.push((name, kind, data[at..at + size].to_vec()));
+            at += size;
+        }
+        (attributes, at + 1)
+    }
+
+    fn channel(name: &str, values: Vec<f32>) -> ExrChannel {
+        ExrChannel {
+            name: name.to_string(),
+            values,
+        }
+    }
+
+    #[test]
+    fn header_describes_the_image() {
+        let mut data = Vec::new();
+        let channels = vec![
+            channel("R", vec![1.0; 6]),
+            channel("depth.Z", vec![2.0; 6]),
+            channel("G", vec![3.0; 6]),
+        ];
+        write_exr(&mut data, 3, 2, channels).unwrap();
+
+        assert_eq!(read_u32_at(&data, 0), MAGIC);
+        assert_eq!(read_u32_at(&data, 4), VERSION);
+        let (attributes, header_end) = attributes(&data);
+        let find = |name: &str| {
+            attributes
+                .iter()
+                .find(|(n, _, _)| n == name)
+                .unwrap_or_else(|| panic!("no {} attribute", name))
+        };
+
+        // channels are listed alphabetically
+        let (_, kind, list) = find("channels");
+        assert_eq!(kind, "chlist");
+        let mut at = 0;
+        let mut names = Vec::new();
+        while list[at] != 0 {
+            names.push(read_cstr(list, &mut at));
+            assert_eq!(read_u32_at(list, at) as i32, PIXEL_TYPE_FLOAT);
+            at += 16;
+        }
+        assert_eq!(names, ["G", "R", "depth.Z"]);
+
+        let (_, kind, window) = find("dataWindow");
+        assert_eq!(kind, "box2i");
+        let window: Vec<i32> = window
+            .chunks(4)
+            .map(|c| i32::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        assert_eq!(window, [0, 0, 2, 1]);
+        assert_eq!(find("compression").2, [0]);
+
+        // the offset table points at one chunk per scanline
+        let line_size = 3 * 3 * 4;
+        for y in 0..2 {
+            let entry = header_end + 8 * y;
+            let offset = u64::from_le_bytes(data[entry..entry + 8].try_into().unwrap()) as usize;
+            assert_eq!(read_u32_at(&data, offset), y as u32);
+            assert_eq!(read_u32_at(&data, offset + 4), line_size);
+            // the first channel of the line is G
+            let first = f32::from_le_bytes(data[offset + 8..offset + 12].try_into().unwrap());
+            assert_eq!(first, 3.0);
+        }
+        assert_eq!(
+            data.len(),
+            header_end + 2 * 8 + 2 * (8 + line_size as usize)
+        );
+    }
+
+    #[test]
+    fn channels_of_the_wrong_size_are_rejected() {
+        let err = write_exr(&mut Vec::new(), 2, 2, vec![channel("R", vec![0.0; 3])]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn images_too_large_for_the_format_are_rejected() {
+        let channels = (0..4)
+            .map(|i| channel(&i.to_string(), Vec::new()))
+            .collect();
+        let err = write_exr(&mut Vec::new(), u32::MAX, 1, channels).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
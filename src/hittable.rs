@@ -1,19 +1,32 @@
-use crate::material::ScatterResult;
+use crate::material::{Material, RefractiveIndex};
 use crate::math::interval::Interval;
 use crate::point::Point3;
 use crate::ray::Ray;
 use crate::vec3::Vec3;
 
 #[derive(Clone)]
-pub struct HitRecord {
+pub struct HitRecord<'a> {
     pub point: Point3,
     pub normal: Vec3,
     pub t: f64,
+    // surface coordinates of the point, for looking up textures
+    pub u: f64,
+    pub v: f64,
+    // derivatives of the point by u and v, tangent to the surface, for
+    // orienting normal and bump maps
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
     pub front_face: bool,
-    pub scatter_result: Option<ScatterResult>,
+    pub material: &'a dyn Material,
+    // set by the world, 1 for its first object
+    pub object_id: u32,
+    pub material_id: u32,
+    // refractive index on the other side of the surface from the material,
+    // None for air
+    pub outside_ior: Option<&'a RefractiveIndex>,
 }
 
-impl HitRecord {
+impl HitRecord<'_> {
     pub fn calculate_face_normal(ray: &Ray, outward_normal: Vec3) -> Vec3 {
         let front_face = ray.direction().dot(outward_normal) < 0.0;
         if front_face {
@@ -25,5 +38,5 @@ impl HitRecord {
 }
 
 pub trait Hittable: Sync {
-    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord<'_>>;
 }
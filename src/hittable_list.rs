@@ -5,13 +5,14 @@ use crate::ray::Ray;
 pub type HittableList = Vec<Box<dyn Hittable>>;
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord<'_>> {
         let mut closest_so_far = interval.max;
         let mut hit_record = None;
 
-        for object in self {
-            if let Some(record) = object.hit(ray, Interval::new(interval.min, closest_so_far)) {
+        for (index, object) in self.iter().enumerate() {
+            if let Some(mut record) = object.hit(ray, Interval::new(interval.min, closest_so_far)) {
                 closest_so_far = record.t;
+                record.object_id = index as u32 + 1;
                 hit_record = Some(record);
             }
         }
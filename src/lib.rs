@@ -0,0 +1,48 @@
+pub mod bump;
+pub mod color;
+pub mod cutout;
+pub mod display;
+pub mod distributed;
+mod encoding;
+pub mod error;
+pub mod exr;
+pub mod hittable;
+pub mod hittable_list;
+pub mod material;
+pub mod math;
+pub mod mesh;
+pub mod point;
+pub mod ray;
+pub mod refractive_index;
+pub mod sampler;
+pub mod scene;
+pub mod spectrum;
+pub mod sphere;
+pub mod texture;
+pub mod thin_film;
+pub mod vec3;
+
+pub use color::Color;
+pub use cutout::Cutout;
+pub use error::{Error, Result};
+pub use hittable::{HitRecord, Hittable};
+pub use hittable_list::HittableList;
+pub use material::{
+    BumpMap, Dielectric, FilmReflectance, Lambertian, Layered, Material, Metal, MixMaterial,
+    NormalMap, RefractiveIndex, ScatterResult, Subsurface, ThinFilm, Toon,
+};
+pub use mesh::{Mesh, Triangle};
+pub use point::Point3;
+pub use ray::Ray;
+pub use scene::aov::Aov;
+pub use scene::camera::{Camera, CameraBuilder};
+pub use scene::cancel::CancellationToken;
+pub use scene::description::{
+    random_spheres, random_spheres_with, SceneDescription, SceneMaterials,
+};
+pub use scene::film::Film;
+pub use scene::image::Image;
+pub use scene::observer::RenderObserver;
+pub use sphere::Sphere;
+pub use texture::Texture;
+pub use vec3::Vec3;
@@ -1,30 +1,40 @@
-use clap::{command, Parser};
-use color::Color;
-use hittable_list::HittableList;
-use material::{Dielectric, Lambertian, Material, Metal};
-use math::rng::{random_double, random_double_range};
-use point::Point3;
-use scene::camera::Camera;
-use sphere::Sphere;
+use clap::{Parser, Subcommand, ValueEnum};
+use rray::display::{ColorSpace, DisplayTransform, ToneMap};
+use rray::distributed::{render_distributed, serve, LocalWorkers};
+use rray::sampler::SamplerKind;
+use rray::scene::adaptive::AdaptiveSampling;
+use rray::scene::aov::Aov;
+use rray::scene::checkpoint::{Checkpoint, Checkpointing};
+use rray::scene::denoise::Denoiser;
+use rray::scene::filter::Filter;
+use rray::scene::image::{Image, ImageFormat};
+use rray::scene::lens::load_prescription;
+use rray::scene::outline::Outline;
+use rray::scene::progressive::Progressive;
+use rray::scene::stats::RenderStatistics;
+use rray::scene::tile::{Region, TileOrder};
+use rray::{Camera, Color, Dielectric, Error, RefractiveIndex, Result, SceneDescription};
 use std::fs::File;
-use vec3::Vec3;
-
-mod color;
-mod hittable;
-mod hittable_list;
-mod material;
-mod math;
-mod point;
-mod ray;
-mod scene;
-mod sphere;
-mod vec3;
+use std::io::{self, BufWriter, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    #[arg(short, long)]
-    output_file: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(short, long, required = true)]
+    output_file: Option<String>,
 
     #[arg(short, long)]
     depth: Option<u32>,
@@ -37,94 +47,689 @@ struct Args {
 
     #[arg(short, long)]
     height: Option<u32>,
+
+    /// Lens prescription file (radius thickness ior aperture per line, in mm)
+    #[arg(long)]
+    lens_file: Option<PathBuf>,
+
+    /// Film diagonal in mm, used with --lens-file
+    #[arg(long)]
+    film_diagonal: Option<f64>,
+
+    /// Pixel reconstruction filter
+    #[arg(long, value_enum, default_value_t = FilterKind::Box)]
+    filter: FilterKind,
+
+    /// Filter radius in pixels, defaults depend on the filter
+    #[arg(long)]
+    filter_radius: Option<f64>,
+
+    /// Exposure adjustment in stops applied before tone mapping
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    exposure: f64,
+
+    /// Tone mapping operator for .ppm output
+    #[arg(long, value_enum, default_value_t = ToneMapKind::Clamp)]
+    tone_map: ToneMapKind,
+
+    /// Luminance mapped to white by --tone-map extended-reinhard
+    #[arg(long, default_value_t = 4.0)]
+    white_point: f64,
+
+    /// Color space of .ppm output, .exr output is always linear sRGB
+    #[arg(long, value_enum, default_value_t = ColorSpaceArg::Srgb)]
+    color_space: ColorSpaceArg,
+
+    /// Sample generator for pixel, lens and bounce sample dimensions
+    #[arg(long, value_enum, default_value_t = SamplerArg::Independent)]
+    sampler: SamplerArg,
+
+    /// Trace wavelengths instead of RGB, converting to color at the film
+    #[arg(long)]
+    spectral: bool,
+
+    /// Glass of the glass spheres, plain glass has an index of 1.5 at every
+    /// wavelength
+    #[arg(long, value_enum, default_value_t = GlassKind::Plain)]
+    glass: GlassKind,
+
+    /// Dispersive glass with this index at 587.56 nm and Abbe number
+    #[arg(long, num_args = 2, value_names = ["ND", "VD"], conflicts_with = "glass")]
+    glass_abbe: Option<Vec<f64>>,
+
+    /// Color white light keeps after travelling 1 unit through the glass
+    #[arg(long, value_delimiter = ',', value_name = "R,G,B")]
+    glass_transmittance: Option<Vec<f64>>,
+
+    /// Coat the glass and metal spheres with a soap film around this many
+    /// nm thick, for iridescent colors
+    #[arg(long, value_name = "NM")]
+    thin_film: Option<f64>,
+
+    /// Make the large brown sphere translucent wax, in which light travels
+    /// about this far between scattering
+    #[arg(long, value_name = "DISTANCE")]
+    subsurface: Option<f64>,
+
+    /// Varnish the small diffuse spheres with a clear coat of this
+    /// roughness, from 0 for a mirror finish to 1
+    #[arg(long, value_name = "ROUGHNESS")]
+    clearcoat: Option<f64>,
+
+    /// Cover the metal spheres with patches of rust
+    #[arg(long)]
+    rust: bool,
+
+    /// Hammer dents about this deep into the large metal sphere with a
+    /// bump map
+    #[arg(long, value_name = "HEIGHT")]
+    bump: Option<f64>,
+
+    /// Hammer dents about this deep into the large metal sphere by
+    /// displacing the surface of a finely subdivided mesh
+    #[arg(long, value_name = "DEPTH")]
+    displace: Option<f64>,
+
+    /// Shade the large metal sphere with a tangent-space normal map read
+    /// from a PPM image
+    #[arg(long, value_name = "FILE")]
+    normal_map: Option<PathBuf>,
+
+    /// Cut holes with an opacity mask into the large brown sphere
+    #[arg(long, conflicts_with = "subsurface")]
+    cutout: bool,
+
+    /// Cel shade the diffuse spheres and the ground with this many bands of
+    /// brightness
+    #[arg(long, value_name = "BANDS")]
+    toon: Option<u32>,
+
+    /// Refine noisy pixels in batches of --samples until they converge
+    #[arg(long)]
+    adaptive: bool,
+
+    /// Maximum samples per pixel with --adaptive, defaults to 8 times --samples
+    #[arg(long)]
+    max_samples: Option<u32>,
+
+    /// Relative error of a pixel's mean at which --adaptive stops sampling it
+    #[arg(long, default_value_t = 0.05)]
+    target_error: f64,
+
+    /// Write the number of samples taken per pixel as a heatmap image
+    #[arg(long)]
+    sample_heatmap: Option<PathBuf>,
+
+    /// Render in passes, rewriting the output file as the image refines
+    #[arg(long)]
+    progressive: bool,
+
+    /// Samples per pixel added in each progressive pass
+    #[arg(long, default_value_t = 4, requires = "progressive")]
+    pass_samples: u32,
+
+    /// Minimum seconds between progressive snapshots, defaults to every pass
+    #[arg(long, requires = "progressive")]
+    snapshot_interval: Option<f64>,
+
+    /// Stop a progressive render after this many seconds
+    #[arg(long, requires = "progressive")]
+    time_limit: Option<f64>,
+
+    /// Edge length of the square tiles the image is rendered in
+    #[arg(long, default_value_t = 32)]
+    tile_size: u32,
+
+    /// Order in which tiles are rendered
+    #[arg(long, value_enum, default_value_t = TileOrderKind::Spiral)]
+    tile_order: TileOrderKind,
+
+    /// Only render the pixels in x0,y0,x1,y1 (x1 and y1 exclusive)
+    #[arg(long)]
+    region: Option<Region>,
+
+    /// Write only the --region instead of the full frame
+    #[arg(long, requires = "region")]
+    crop: bool,
+
+    /// Seed for generating the scene, random if not given
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Periodically save the render state to this file
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// Seconds between checkpoints
+    #[arg(long, default_value_t = 300.0, requires = "checkpoint")]
+    checkpoint_interval: f64,
+
+    /// Continue the render saved in --checkpoint, up to --samples per pixel
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+
+    /// Also write these comma separated AOVs, as layers of an .exr output or
+    /// as images next to a .ppm output
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        conflicts_with_all = ["workers", "local_workers", "checkpoint"]
+    )]
+    aovs: Vec<AovArg>,
+
+    /// Denoise the image guided by its albedo, normal and depth, writing the
+    /// noisy image next to it with .raw added to the file name
+    #[arg(long, conflicts_with_all = ["workers", "local_workers", "checkpoint"])]
+    denoise: bool,
+
+    /// Draw ink outlines this many pixels wide over the image where depth,
+    /// normal or object change
+    #[arg(
+        long,
+        value_name = "WIDTH",
+        conflicts_with_all = ["workers", "local_workers", "checkpoint"]
+    )]
+    outline: Option<f64>,
+
+    /// Render on the workers at these comma separated host:port addresses
+    #[arg(
+        long,
+        value_delimiter = ',',
+        conflicts_with_all = ["adaptive", "progressive", "checkpoint"]
+    )]
+    workers: Vec<String>,
+
+    /// Start this many worker processes on this machine and render on them
+    #[arg(long, conflicts_with_all = ["adaptive", "progressive", "checkpoint"])]
+    local_workers: Option<u32>,
+
+    /// Seconds a worker may take to connect or to render a tile before its
+    /// tile is handed to another worker
+    #[arg(long, default_value_t = 600.0, value_name = "SECONDS")]
+    worker_timeout: f64,
+
+    /// Don't print progress and render statistics
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Render tiles for coordinators that connect to this process
+    ServeWorker {
+        /// Port to listen on, 0 picks a free port
+        #[arg(long, default_value_t = 7878)]
+        port: u16,
+
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: String,
+    },
+}
+
+impl Args {
+    // Describes the parameters samples in a checkpoint depend on. The sample
+    // counts are left out so a resumed render can add more samples; the
+    // checkpoint keeps the count the sampler was laid out for instead.
+    fn fingerprint(&self) -> String {
+        format!(
+            "size={}x{} depth={} filter={:?} filter_radius={:?} sampler={:?} \
+             lens_file={:?} film_diagonal={:?} adaptive={} target_error={} spectral={} \
+             glass={:?} glass_abbe={:?} glass_transmittance={:?} thin_film={:?} \
+             subsurface={:?} clearcoat={:?} rust={} bump={:?} displace={:?} normal_map={:?} cutout={} toon={:?}",
+            self.width.unwrap_or(700),
+            self.height.unwrap_or(400),
+            self.depth.unwrap_or(4),
+            self.filter,
+            self.filter_radius,
+            self.sampler,
+            self.lens_file,
+            self.film_diagonal,
+            self.adaptive,
+            self.target_error,
+            self.spectral,
+            self.glass,
+            self.glass_abbe,
+            self.glass_transmittance,
+            self.thin_film,
+            self.subsurface,
+            self.clearcoat,
+            self.rust,
+            self.bump,
+            self.displace,
+            self.normal_map,
+            self.cutout,
+            self.toon
+        )
+    }
 }
 
-fn initialize_world() -> HittableList {
-    let mut world = HittableList::new();
-
-    let ground_material = Lambertian::new(Color::new(0.5, 0.5, 0.5));
-
-    world.push(Box::new(Sphere::new(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        Box::new(ground_material),
-    )));
-
-    for i in -9..9 {
-        for j in -9..9 {
-            let radius = random_double_range(0.0, 0.3);
-            let choose_mat = random_double();
-            let center = Point3::new(
-                i as f64 + 0.9 * random_double(),
-                radius,
-                j as f64 + 0.9 * random_double(),
-            );
-
-            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                let sphere_material: Box<dyn Material> = if choose_mat < 0.8 {
-                    let albedo = Color::random() * Color::random();
-                    Box::new(Lambertian::new(albedo))
-                } else if choose_mat < 0.95 {
-                    let albedo = Color::random();
-                    Box::new(Metal::new(albedo, 0.5 * random_double()))
-                } else {
-                    Box::new(Dielectric::new(1.5))
-                };
-
-                let sphere = Sphere::new(center, radius, sphere_material);
-                world.push(Box::new(sphere));
-            }
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+impl FilterKind {
+    fn filter(self, radius: Option<f64>) -> Filter {
+        match self {
+            FilterKind::Box => Filter::box_filter(radius),
+            FilterKind::Tent => Filter::tent(radius),
+            FilterKind::Gaussian => Filter::gaussian(radius),
+            FilterKind::Mitchell => Filter::mitchell(radius),
+            FilterKind::Lanczos => Filter::lanczos(radius),
         }
     }
+}
 
-    world.push(Box::new(Sphere::new(
-        Point3::new(0.0, 1.0, 0.0),
-        1.0,
-        Box::new(Dielectric::new(1.5)),
-    )));
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum TileOrderKind {
+    Scanline,
+    Spiral,
+    Hilbert,
+}
 
-    world.push(Box::new(Sphere::new(
-        Point3::new(-4.0, 1.0, 0.0),
-        1.0,
-        Box::new(Lambertian::new(Color::new(0.4, 0.2, 0.1))),
-    )));
+impl TileOrderKind {
+    fn order(self) -> TileOrder {
+        match self {
+            TileOrderKind::Scanline => TileOrder::Scanline,
+            TileOrderKind::Spiral => TileOrder::Spiral,
+            TileOrderKind::Hilbert => TileOrder::Hilbert,
+        }
+    }
+}
 
-    world.push(Box::new(Sphere::new(
-        Point3::new(4.0, 1.0, 0.0),
-        1.0,
-        Box::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0)),
-    )));
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ToneMapKind {
+    Clamp,
+    Reinhard,
+    ExtendedReinhard,
+    Aces,
+    Agx,
+    Hable,
+}
 
-    world
+impl ToneMapKind {
+    fn tone_map(self, white_point: f64) -> ToneMap {
+        match self {
+            ToneMapKind::Clamp => ToneMap::Clamp,
+            ToneMapKind::Reinhard => ToneMap::Reinhard,
+            ToneMapKind::ExtendedReinhard => ToneMap::ExtendedReinhard { white: white_point },
+            ToneMapKind::Aces => ToneMap::Aces,
+            ToneMapKind::Agx => ToneMap::Agx,
+            ToneMapKind::Hable => ToneMap::Hable,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ColorSpaceArg {
+    Srgb,
+    DisplayP3,
+    Rec2020,
+}
+
+impl ColorSpaceArg {
+    fn color_space(self) -> ColorSpace {
+        match self {
+            ColorSpaceArg::Srgb => ColorSpace::Srgb,
+            ColorSpaceArg::DisplayP3 => ColorSpace::DisplayP3,
+            ColorSpaceArg::Rec2020 => ColorSpace::Rec2020,
+        }
+    }
 }
 
-fn main() -> std::io::Result<()> {
-    let args = Args::parse();
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum GlassKind {
+    Plain,
+    Bk7,
+    FusedSilica,
+    Diamond,
+}
+
+impl GlassKind {
+    fn refractive_index(self) -> RefractiveIndex {
+        match self {
+            GlassKind::Plain => RefractiveIndex::Constant(1.5),
+            GlassKind::Bk7 => RefractiveIndex::bk7(),
+            GlassKind::FusedSilica => RefractiveIndex::fused_silica(),
+            GlassKind::Diamond => RefractiveIndex::diamond(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum SamplerArg {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+impl SamplerArg {
+    fn kind(self) -> SamplerKind {
+        match self {
+            SamplerArg::Independent => SamplerKind::Independent,
+            SamplerArg::Stratified => SamplerKind::Stratified,
+            SamplerArg::Halton => SamplerKind::Halton,
+            SamplerArg::Sobol => SamplerKind::Sobol,
+            SamplerArg::BlueNoise => SamplerKind::BlueNoise,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum AovArg {
+    Albedo,
+    Normal,
+    Position,
+    Depth,
+    ObjectId,
+    MaterialId,
+    Direct,
+    Indirect,
+    Diffuse,
+    Specular,
+}
+
+impl AovArg {
+    fn aov(self) -> Aov {
+        match self {
+            AovArg::Albedo => Aov::Albedo,
+            AovArg::Normal => Aov::Normal,
+            AovArg::Position => Aov::Position,
+            AovArg::Depth => Aov::Depth,
+            AovArg::ObjectId => Aov::ObjectId,
+            AovArg::MaterialId => Aov::MaterialId,
+            AovArg::Direct => Aov::Direct,
+            AovArg::Indirect => Aov::Indirect,
+            AovArg::Diffuse => Aov::Diffuse,
+            AovArg::Specular => Aov::Specular,
+        }
+    }
+}
+
+// a duration given in seconds on the command line
+fn seconds(name: &str, seconds: f64) -> Result<Duration> {
+    if seconds.is_nan() || seconds <= 0.0 {
+        return Err(Error::InvalidParameter(format!(
+            "{} of {} seconds needs to be positive",
+            name, seconds
+        )));
+    }
+    Duration::try_from_secs_f64(seconds).map_err(|_| {
+        Error::InvalidParameter(format!("{} of {} seconds is too long", name, seconds))
+    })
+}
+
+fn serve_worker(bind: &str, port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind((bind, port))?;
+    // printed on its own line so that a coordinator starting local workers
+    // can find the port
+    println!("listening on {}", listener.local_addr()?);
+    serve(listener)
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> Result<()> {
+    if let Some(Command::ServeWorker { port, bind }) = &args.command {
+        return Ok(serve_worker(bind, *port)?);
+    }
+
     let image_width: u32 = args.width.unwrap_or(700);
     let image_height: u32 = args.height.unwrap_or(400);
 
-    let mut file = File::create(&args.output_file)?;
+    let output_file = Path::new(args.output_file.as_deref().expect("clap requires it"));
+    ImageFormat::from_path(output_file)?;
 
     let depth = args.depth.unwrap_or(4);
     let samples = args.samples.unwrap_or(16);
 
-    let world = initialize_world();
+    let max_samples = args
+        .max_samples
+        .unwrap_or(samples.saturating_mul(8))
+        .max(samples);
+
+    let snapshot_interval = args
+        .snapshot_interval
+        .map(|interval| seconds("snapshot interval", interval))
+        .transpose()?;
+    let checkpoint_interval = seconds("checkpoint interval", args.checkpoint_interval)?;
+    let worker_timeout = seconds("worker timeout", args.worker_timeout)?;
+    let time_limit = args
+        .time_limit
+        .map(|limit| seconds("time limit", limit))
+        .transpose()?;
+
+    if let Some(radius) = args.filter_radius {
+        if radius.is_nan() || radius <= 0.0 {
+            return Err(Error::InvalidParameter(format!(
+                "filter radius {} must be positive",
+                radius
+            )));
+        }
+    }
+    let filter = args.filter.filter(args.filter_radius);
+
+    if !args.exposure.is_finite() {
+        return Err(Error::InvalidParameter(format!(
+            "exposure {} must be finite",
+            args.exposure
+        )));
+    }
+    if args.white_point.is_nan() || args.white_point <= 0.0 {
+        return Err(Error::InvalidParameter(format!(
+            "white point {} must be positive",
+            args.white_point
+        )));
+    }
+    if let Some(roughness) = args.clearcoat {
+        if !(0.0..=1.0).contains(&roughness) {
+            return Err(Error::InvalidParameter(format!(
+                "clearcoat roughness {} must be between 0 and 1",
+                roughness
+            )));
+        }
+    }
+    let glass = match args.glass_abbe.as_deref() {
+        Some(&[nd, vd]) => RefractiveIndex::from_abbe(nd, vd)?,
+        _ => args.glass.refractive_index(),
+    };
+
+    let glass_absorption = match args.glass_transmittance.as_deref() {
+        Some(&[r, g, b]) => Dielectric::transmittance_absorption(Color::new(r, g, b), 1.0)?,
+        Some(other) => {
+            return Err(Error::InvalidParameter(format!(
+                "glass transmittance needs 3 channels, got {}",
+                other.len()
+            )))
+        }
+        None => Color::new(0.0, 0.0, 0.0),
+    };
+
+    let display = DisplayTransform {
+        exposure: args.exposure,
+        tone_map: args.tone_map.tone_map(args.white_point),
+        color_space: args.color_space.color_space(),
+    };
+    let fingerprint = args.fingerprint();
 
-    let camera = Camera::new(
+    let checkpoint = match &args.checkpoint {
+        Some(path) if args.resume => Some(Checkpoint::load(path, filter)?),
+        _ => None,
+    };
+
+    // Camera::resume checks the checkpoint against the seed and fingerprint
+    let seed = match (args.seed, &checkpoint) {
+        (Some(seed), _) => seed,
+        (None, Some(checkpoint)) => checkpoint.seed,
+        (None, None) => rand::random(),
+    };
+    // stratified samplers need to know the maximum sample count, and a
+    // resumed render keeps the count its samples were taken with
+    let sampler_samples = match &checkpoint {
+        Some(checkpoint) => checkpoint.sampler_samples,
+        None if args.adaptive => max_samples,
+        None => samples,
+    };
+
+    let lens = match &args.lens_file {
+        Some(lens_file) => Some((
+            load_prescription(lens_file)?,
+            args.film_diagonal.unwrap_or(35.0),
+        )),
+        None => None,
+    };
+    let normal_map = match &args.normal_map {
+        Some(path) => Some(Image::load_ppm(path)?),
+        None => None,
+    };
+
+    let description = SceneDescription {
+        seed,
         image_width,
         image_height,
-        samples,
-        depth,
-        20.0,
-        Point3::new(13.0, 2.0, 3.0),
-        Point3::new(0.0, 0.0, 0.0),
-        Vec3::new(0.0, 1.0, 0.0),
-        0.6,
-        10.0,
-    );
-    camera.render(&world, &mut file);
-
-    println!("Done.");
+        samples_per_pixel: samples,
+        max_depth: depth,
+        filter,
+        sampler: args.sampler.kind(),
+        lens,
+        spectral: args.spectral,
+        glass,
+        glass_absorption,
+        thin_film: args.thin_film,
+        subsurface: args.subsurface,
+        clearcoat: args.clearcoat,
+        rust: args.rust,
+        bump: args.bump,
+        displacement: args.displace,
+        normal_map,
+        cutout: args.cutout,
+        toon: args.toon,
+    };
+
+    let mut camera = description
+        .build_camera_from(Camera::builder().tiles(args.tile_size, args.tile_order.order()))?
+        .with_sampler(args.sampler.kind().create(sampler_samples, 0))
+        .with_display_transform(display);
+
+    if let Some(region) = args.region {
+        if region.x1 > image_width || region.y1 > image_height {
+            return Err(Error::InvalidParameter(format!(
+                "region {} lies outside of the {}x{} image",
+                region, image_width, image_height
+            )));
+        }
+        camera = camera.with_region(region, args.crop);
+    }
+
+    if args.adaptive {
+        camera = camera.with_adaptive_sampling(AdaptiveSampling {
+            max_samples,
+            target_error: args.target_error,
+        });
+    }
+
+    if args.progressive {
+        camera = camera.with_progressive(Progressive {
+            pass_samples: args.pass_samples,
+            snapshot_interval,
+            time_limit,
+        });
+    }
+
+    if let Some(path) = &args.checkpoint {
+        camera = camera.with_checkpointing(Checkpointing {
+            path: path.clone(),
+            interval: checkpoint_interval,
+            seed,
+            fingerprint,
+            sampler_samples,
+        });
+    }
+
+    if !args.aovs.is_empty() {
+        camera = camera.with_aovs(args.aovs.iter().map(|aov| aov.aov()).collect());
+    }
+
+    if args.denoise {
+        camera = camera.with_denoiser(Denoiser::default());
+    }
+
+    if let Some(width) = args.outline {
+        if width.is_nan() || width <= 0.0 || width.is_infinite() {
+            return Err(Error::InvalidParameter(format!(
+                "outline width {} needs to be positive and finite",
+                width
+            )));
+        }
+        camera = camera.with_outline(Outline {
+            width,
+            ..Outline::default()
+        });
+    }
+
+    if !args.quiet {
+        camera = camera.with_progress_report();
+    }
+
+    let start = Instant::now();
+    let distributed = !args.workers.is_empty() || args.local_workers.is_some();
+    let film = if distributed {
+        let local_workers = LocalWorkers::spawn(args.local_workers.unwrap_or(0))?;
+        let workers: Vec<String> = args
+            .workers
+            .iter()
+            .chain(local_workers.addresses())
+            .cloned()
+            .collect();
+
+        let film = render_distributed(&description, &workers, camera.tiles(), worker_timeout)?;
+        camera.write_image(&film, output_file)?;
+        film
+    } else {
+        let world = description.build_world()?;
+        match checkpoint {
+            Some(checkpoint) => camera.resume(&world, output_file, checkpoint)?,
+            None => camera.render(&world, output_file)?,
+        }
+    };
+
+    if let Some(path) = &args.sample_heatmap {
+        let write = || -> io::Result<()> {
+            let mut writer = BufWriter::new(File::create(path)?);
+            camera.write_sample_heatmap(&film, &mut writer)?;
+            writer.flush()
+        };
+        write().map_err(|err| Error::file(path, err))?;
+    }
+
+    if !args.quiet {
+        if distributed {
+            // rays are counted by the workers, which don't report them
+            eprintln!("wall time: {:.2}s", start.elapsed().as_secs_f64());
+        } else {
+            let statistics = RenderStatistics {
+                wall_time: start.elapsed(),
+                counts: camera.ray_counts(),
+            };
+            eprintln!("{}", statistics);
+        }
+    }
 
     Ok(())
 }
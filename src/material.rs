@@ -1,13 +1,98 @@
-use crate::{color::Color, hittable::HitRecord, math::rng::random_double, ray::Ray, vec3::Vec3};
+use std::sync::Arc;
+
+pub use crate::bump::{BumpMap, NormalMap};
+use crate::error::{Error, Result};
+pub use crate::refractive_index::RefractiveIndex;
+use crate::sampler::IndependentSampler;
+use crate::spectrum::{rgb_reflectance, D_LINE};
+use crate::texture::{SolidColor, Texture};
+pub use crate::thin_film::{Substrate, ThinFilm};
+use crate::{color::Color, hittable::HitRecord, ray::Ray, sampler::Sampler, vec3::Vec3};
+
+// wavelengths in nm standing in for the red, green and blue channels of RGB
+// paths through dispersive materials
+const RGB_WAVELENGTHS: [f64; 3] = [610.0, 550.0, 465.0];
+
+// times light may bounce between the base and the top of a coating before it
+// is taken as absorbed
+const MAX_COATING_BOUNCES: u32 = 8;
+// key of the independent sample stream light reflected back down at the top
+// of a coating walks with
+const COATING_WALK_SEED: u64 = 0x636f6174;
+
+// The kind of reflection a scattered ray was sampled from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lobe {
+    Diffuse,
+    Specular,
+}
 
 #[derive(Clone)]
 pub struct ScatterResult {
     pub attenuation: Color,
     pub scattered: Ray,
+    pub lobe: Lobe,
+    // whether the scattered direction was picked for the wavelength of the
+    // ray only, so other wavelengths can't follow it
+    pub dispersive: bool,
+    // reflectance at every wavelength, which paths carrying several use in
+    // place of the attenuation
+    pub film_reflectance: Option<FilmReflectance>,
+}
+
+// Reflectance of a thin film on a metal at a hit, seen from air. Metals
+// reflect every wavelength in the same direction, so the film only weighs
+// each wavelength of a path differently.
+#[derive(Clone)]
+pub struct FilmReflectance {
+    film: ThinFilm,
+    thickness: f64,
+    cos_theta: f64,
+    albedo: Color,
+}
+
+impl FilmReflectance {
+    pub fn at(&self, wavelength: f64) -> f64 {
+        let substrate = Substrate::Conductor(rgb_reflectance(self.albedo, wavelength));
+        self.film
+            .reflectance(self.thickness, wavelength, self.cos_theta, 1.0, substrate)
+    }
+}
+
+// What fills the inside of a closed dielectric surface.
+#[derive(Clone, Copy)]
+pub struct Medium<'a> {
+    pub refraction_index: &'a RefractiveIndex,
+    // absorption coefficient per unit distance of each channel
+    pub absorption: Color,
+    // scattering coefficient per unit distance of each channel
+    pub scattering: Color,
+    // Henyey-Greenstein asymmetry of the scattering, from -1 for back
+    // scattering through 0 for isotropic to 1 for forward scattering
+    pub anisotropy: f64,
+    // Where media overlap, the one with the highest priority fills the
+    // overlap and the surfaces of the others inside it are ignored.
+    pub priority: u32,
 }
 
 pub trait Material: Sync {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<ScatterResult>;
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterResult>;
+
+    // the normal the surface is shaded with at the hit, facing the ray like
+    // the normal of the hit
+    fn shading_normal(&self, hit_record: &HitRecord) -> Vec3 {
+        hit_record.normal
+    }
+
+    // the medium behind the surface, for materials that rays pass into
+    fn medium(&self) -> Option<Medium<'_>> {
+        None
+    }
 }
 
 pub struct Lambertian {
@@ -21,8 +106,13 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _ray: &Ray, hit_record: &HitRecord) -> Option<ScatterResult> {
-        let randomized_direction = hit_record.normal + Vec3::random_unit_vector();
+    fn scatter(
+        &self,
+        _ray: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterResult> {
+        let randomized_direction = hit_record.normal + Vec3::sample_unit_vector(sampler.get_2d());
         let scatter_direction = if randomized_direction.near_zero() {
             hit_record.normal
         } else {
@@ -32,6 +122,9 @@ impl Material for Lambertian {
         Some(ScatterResult {
             attenuation: self.albedo,
             scattered,
+            lobe: Lobe::Diffuse,
+            dispersive: false,
+            film_reflectance: None,
         })
     }
 }
@@ -39,6 +132,7 @@ impl Material for Lambertian {
 pub struct Metal {
     albedo: Color,
     fuzz: f64,
+    thin_film: Option<ThinFilm>,
 }
 
 impl Metal {
@@ -46,30 +140,130 @@ impl Metal {
         Self {
             albedo,
             fuzz: fuzz.min(1.0),
+            thin_film: None,
         }
     }
+
+    // Coats the metal with a transparent film, whose reflectance replaces
+    // the albedo.
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
+    }
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<ScatterResult> {
-        let reflected = Vec3::reflect(ray.direction().unit_vector(), hit_record.normal);
-        let scattered_direction = reflected + (self.fuzz * Vec3::random_unit_vector());
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterResult> {
+        let unit_direction = ray.direction().unit_vector();
+        let reflected = Vec3::reflect(unit_direction, hit_record.normal);
+        let scattered_direction =
+            reflected + (self.fuzz * Vec3::sample_unit_vector(sampler.get_2d()));
         let scattered = Ray::new(hit_record.point, scattered_direction.unit_vector());
 
+        let (attenuation, film_reflectance) = match &self.thin_film {
+            None => (self.albedo, None),
+            Some(film) => {
+                let thickness = film.thickness_at(hit_record);
+                let cos_theta = Vec3::dot(-unit_direction, hit_record.normal);
+                // metals are always seen from air
+                match ray.wavelength() {
+                    Some(wavelength) => {
+                        let film_reflectance = FilmReflectance {
+                            film: film.clone(),
+                            thickness,
+                            cos_theta,
+                            albedo: self.albedo,
+                        };
+                        let r = film_reflectance.at(wavelength);
+                        (Color::new(r, r, r), Some(film_reflectance))
+                    }
+                    None => {
+                        let substrate = |channel: usize| Substrate::Conductor(self.albedo[channel]);
+                        let r = film_rgb_reflectance(film, thickness, cos_theta, 1.0, substrate);
+                        (r, None)
+                    }
+                }
+            }
+        };
+
         Some(ScatterResult {
-            attenuation: self.albedo,
+            attenuation,
             scattered,
+            lobe: Lobe::Specular,
+            dispersive: false,
+            film_reflectance,
         })
     }
 }
 
 pub struct Dielectric {
-    refraction_index: f64,
+    refraction_index: RefractiveIndex,
+    absorption: Color,
+    priority: u32,
+    thin_film: Option<ThinFilm>,
 }
 
 impl Dielectric {
     pub fn new(refraction_index: f64) -> Self {
-        Self { refraction_index }
+        Self::with_refractive_index(RefractiveIndex::Constant(refraction_index))
+    }
+
+    // Rays without a wavelength see the index at the sodium d-line.
+    pub fn with_refractive_index(refraction_index: RefractiveIndex) -> Self {
+        Self {
+            refraction_index,
+            absorption: Color::new(0.0, 0.0, 0.0),
+            priority: 0,
+            thin_film: None,
+        }
+    }
+
+    // Light travelling a distance d inside is attenuated by
+    // exp(-absorption * d) following the Beer-Lambert law.
+    pub fn with_absorption(mut self, absorption: Color) -> Self {
+        self.absorption = absorption;
+        self
+    }
+
+    // Sets the absorption so that white light keeps the given color after
+    // travelling distance inside.
+    pub fn with_transmittance(self, color: Color, distance: f64) -> Result<Self> {
+        Ok(self.with_absorption(Self::transmittance_absorption(color, distance)?))
+    }
+
+    // the absorption coefficients leaving color of white light after distance
+    pub fn transmittance_absorption(color: Color, distance: f64) -> Result<Color> {
+        let channels = [color.x(), color.y(), color.z()];
+        let in_range = |c: &f64| (0.0..=1.0).contains(c) && *c > 0.0;
+        if !channels.iter().all(in_range) || distance.is_nan() || distance <= 0.0 {
+            return Err(Error::InvalidParameter(format!(
+                "transmittance {} at distance {} needs channels in (0, 1] and a positive distance",
+                color, distance
+            )));
+        }
+        let absorption = |c: f64| -c.ln() / distance;
+        Ok(Color::new(
+            absorption(channels[0]),
+            absorption(channels[1]),
+            absorption(channels[2]),
+        ))
+    }
+
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    // Coats the surface with a transparent film, whose reflectance replaces
+    // the Fresnel reflectance of the bare surface on both sides.
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
     }
 
     // Schlick's approximation for reflectance
@@ -81,13 +275,34 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<ScatterResult> {
-        let attenuation = Color::new(1.0, 1.0, 1.0);
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterResult> {
+        let mut attenuation = Color::new(1.0, 1.0, 1.0);
+        let mut wavelength = ray.wavelength();
+
+        // Without a wavelength to trace, a dispersive path picks one of the
+        // color channels and carries only that on, at its wavelength.
+        if wavelength.is_none() && !self.refraction_index.is_constant() {
+            let channel = ((3.0 * sampler.get_1d()) as usize).min(2);
+            let mut channels = [0.0; 3];
+            channels[channel] = 3.0;
+            attenuation = Color::new(channels[0], channels[1], channels[2]);
+            wavelength = Some(RGB_WAVELENGTHS[channel]);
+        }
 
+        let wavelength_or_d_line = wavelength.unwrap_or(D_LINE);
+        let refraction_index = self.refraction_index.at(wavelength_or_d_line);
+        let outside_index = hit_record
+            .outside_ior
+            .map_or(1.0, |outside| outside.at(wavelength_or_d_line));
         let refraction_ratio = if hit_record.front_face {
-            1.0 / self.refraction_index
+            outside_index / refraction_index
         } else {
-            self.refraction_index
+            refraction_index / outside_index
         };
 
         let unit_direction = ray.direction().unit_vector();
@@ -97,15 +312,630 @@ impl Material for Dielectric {
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
-        let direction = if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > random_double() {
+        let reflect = cannot_refract
+            || match &self.thin_film {
+                None => Dielectric::reflectance(cos_theta, refraction_ratio) > sampler.get_1d(),
+                Some(film) => {
+                    let thickness = film.thickness_at(hit_record);
+                    let (incident_index, transmitted_index) = if hit_record.front_face {
+                        (outside_index, refraction_index)
+                    } else {
+                        (refraction_index, outside_index)
+                    };
+                    let substrate = Substrate::Dielectric(transmitted_index);
+                    match wavelength {
+                        Some(wavelength) => {
+                            film.reflectance(
+                                thickness,
+                                wavelength,
+                                cos_theta,
+                                incident_index,
+                                substrate,
+                            ) > sampler.get_1d()
+                        }
+                        // Reflection is picked by the mean reflectance of
+                        // the channels, and each channel is weighted by how
+                        // much more or less it reflects than the mean.
+                        None => {
+                            let r = film_rgb_reflectance(
+                                film,
+                                thickness,
+                                cos_theta,
+                                incident_index,
+                                |_| substrate,
+                            );
+                            let mean = (r.x() + r.y() + r.z()) / 3.0;
+                            let reflect = mean > sampler.get_1d();
+                            attenuation = attenuation
+                                * if reflect {
+                                    r / mean
+                                } else {
+                                    (Color::new(1.0, 1.0, 1.0) - r) / (1.0 - mean)
+                                };
+                            reflect
+                        }
+                    }
+                }
+            };
+
+        let direction = if reflect {
             Vec3::reflect(unit_direction, hit_record.normal)
         } else {
             Vec3::refract(unit_direction, hit_record.normal, refraction_ratio)
         };
 
+        let scattered = Ray::new(hit_record.point, direction);
         Some(ScatterResult {
             attenuation,
+            scattered: match wavelength {
+                Some(wavelength) => scattered.with_wavelength(wavelength),
+                None => scattered,
+            },
+            lobe: Lobe::Specular,
+            dispersive: !self.refraction_index.is_constant()
+                || (self.thin_film.is_some() && wavelength.is_some()),
+            film_reflectance: None,
+        })
+    }
+
+    fn medium(&self) -> Option<Medium<'_>> {
+        Some(Medium {
+            refraction_index: &self.refraction_index,
+            absorption: self.absorption,
+            scattering: Color::new(0.0, 0.0, 0.0),
+            anisotropy: 0.0,
+            priority: self.priority,
+        })
+    }
+}
+
+// Translucent material such as skin, wax, marble or milk. Light refracts
+// through its smooth boundary into a medium where it scatters many times,
+// and comes out somewhere else. The path tracer follows it on a random walk
+// through the medium, so the material needs a closed surface.
+pub struct Subsurface {
+    boundary: Dielectric,
+    scattering: Color,
+    anisotropy: f64,
+}
+
+impl Subsurface {
+    // albedo is the color of a thick slab of the material, and
+    // mean_free_path the average distance light of each channel travels
+    // between interactions inside.
+    pub fn new(albedo: Color, mean_free_path: Color) -> Result<Self> {
+        let in_range = |c: f64| (0.0..=1.0).contains(&c);
+        if ![albedo.x(), albedo.y(), albedo.z()]
+            .into_iter()
+            .all(in_range)
+        {
+            return Err(Error::InvalidParameter(format!(
+                "subsurface albedo {} needs channels in [0, 1]",
+                albedo
+            )));
+        }
+        let positive = |d: f64| d > 0.0 && d.is_finite();
+        let distances = [mean_free_path.x(), mean_free_path.y(), mean_free_path.z()];
+        if !distances.into_iter().all(positive) {
+            return Err(Error::InvalidParameter(format!(
+                "subsurface mean free path {} needs positive finite channels",
+                mean_free_path
+            )));
+        }
+
+        let channel = |albedo: f64, distance: f64| {
+            let extinction = 1.0 / distance;
+            let single_scattering = single_scattering_albedo(albedo);
+            (
+                (1.0 - single_scattering) * extinction,
+                single_scattering * extinction,
+            )
+        };
+        let [(ar, sr), (ag, sg), (ab, sb)] = [
+            channel(albedo.x(), distances[0]),
+            channel(albedo.y(), distances[1]),
+            channel(albedo.z(), distances[2]),
+        ];
+
+        Ok(Self {
+            boundary: Dielectric::new(1.4).with_absorption(Color::new(ar, ag, ab)),
+            scattering: Color::new(sr, sg, sb),
+            anisotropy: 0.0,
+        })
+    }
+
+    // the index of refraction of the boundary, 1.4 by default
+    pub fn with_refraction_index(mut self, refraction_index: f64) -> Self {
+        self.boundary.refraction_index = RefractiveIndex::Constant(refraction_index);
+        self
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: f64) -> Result<Self> {
+        if anisotropy.is_nan() || anisotropy <= -1.0 || anisotropy >= 1.0 {
+            return Err(Error::InvalidParameter(format!(
+                "anisotropy {} needs to be in (-1, 1)",
+                anisotropy
+            )));
+        }
+        self.anisotropy = anisotropy;
+        Ok(self)
+    }
+
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.boundary = self.boundary.with_priority(priority);
+        self
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterResult> {
+        self.boundary.scatter(ray, hit_record, sampler)
+    }
+
+    fn medium(&self) -> Option<Medium<'_>> {
+        let medium = self.boundary.medium()?;
+        Some(Medium {
+            scattering: self.scattering,
+            anisotropy: self.anisotropy,
+            ..medium
+        })
+    }
+}
+
+// A base material under a clear dielectric coating, like car paint or
+// varnished wood. Light either reflects off the coating, or refracts into
+// it, scatters off the base and bounces around inside the coating until it
+// refracts back out, being absorbed by the coating on the way.
+pub struct Layered {
+    base: Box<dyn Material>,
+    refraction_index: f64,
+    roughness: f64,
+    // absorption of the coating integrated over its thickness
+    absorption: Color,
+}
+
+impl Layered {
+    // a coating of the given index of refraction, 1.5 for most varnishes
+    pub fn new(base: Box<dyn Material>, refraction_index: f64) -> Result<Self> {
+        if refraction_index.is_nan() || refraction_index < 1.0 || refraction_index.is_infinite() {
+            return Err(Error::InvalidParameter(format!(
+                "coating refractive index {} needs to be at least 1",
+                refraction_index
+            )));
+        }
+        Ok(Self {
+            base,
+            refraction_index,
+            roughness: 0.0,
+            absorption: Color::new(0.0, 0.0, 0.0),
+        })
+    }
+
+    // blurs the reflection off the coating like the fuzz of metals
+    pub fn with_roughness(mut self, roughness: f64) -> Result<Self> {
+        if !(0.0..=1.0).contains(&roughness) {
+            return Err(Error::InvalidParameter(format!(
+                "coating roughness {} must be between 0 and 1",
+                roughness
+            )));
+        }
+        self.roughness = roughness;
+        Ok(self)
+    }
+
+    // Light crossing the coating at an angle θ to the normal is attenuated
+    // by exp(-absorption / cos θ).
+    pub fn with_absorption(mut self, absorption: Color) -> Self {
+        self.absorption = absorption;
+        self
+    }
+
+    // Sets the absorption so that white light crossing the coating straight
+    // keeps the given color.
+    pub fn with_tint(self, color: Color) -> Result<Self> {
+        Ok(self.with_absorption(Dielectric::transmittance_absorption(color, 1.0)?))
+    }
+
+    fn coating_transmittance(&self, cosine: f64) -> Color {
+        let channel = |absorption: f64| (-absorption / cosine.max(1e-4)).exp();
+        Color::new(
+            channel(self.absorption.x()),
+            channel(self.absorption.y()),
+            channel(self.absorption.z()),
+        )
+    }
+}
+
+impl Material for Layered {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterResult> {
+        let unit_direction = ray.direction().unit_vector();
+        let normal = hit_record.normal;
+        let cos_theta = Vec3::dot(-unit_direction, normal).clamp(0.0, 1.0);
+        let with_wavelength = |scattered: Ray| match ray.wavelength() {
+            Some(wavelength) => scattered.with_wavelength(wavelength),
+            None => scattered,
+        };
+
+        if Dielectric::reflectance(cos_theta, 1.0 / self.refraction_index) > sampler.get_1d() {
+            let reflected = Vec3::reflect(unit_direction, normal);
+            let direction = reflected + self.roughness * Vec3::sample_unit_vector(sampler.get_2d());
+            return Some(ScatterResult {
+                attenuation: Color::new(1.0, 1.0, 1.0),
+                scattered: Ray::new(hit_record.point, direction.unit_vector()),
+                lobe: Lobe::Specular,
+                dispersive: false,
+                film_reflectance: None,
+            });
+        }
+
+        let mut attenuation = Color::new(1.0, 1.0, 1.0);
+        let mut dispersive = false;
+        let mut direction = Vec3::refract(unit_direction, normal, 1.0 / self.refraction_index);
+        // Light reflected back down at the top of the coating takes its
+        // samples from an independent stream of the pixel sample, so that the
+        // walk stays within the sample dimensions of its bounce.
+        let mut walk_sampler: Option<IndependentSampler> = None;
+        for _ in 0..MAX_COATING_BOUNCES {
+            let pass_sampler: &mut dyn Sampler = match &mut walk_sampler {
+                Some(walk_sampler) => walk_sampler,
+                None => &mut *sampler,
+            };
+            attenuation = attenuation * self.coating_transmittance(Vec3::dot(-direction, normal));
+            let coated_ray = with_wavelength(Ray::new(ray.origin(), direction));
+            let result = self.base.scatter(&coated_ray, hit_record, pass_sampler)?;
+            attenuation = attenuation * result.attenuation;
+            // the coating attenuates by channel, so a film below it is only
+            // followed at the wavelength of the ray
+            dispersive |= result.dispersive || result.film_reflectance.is_some();
+
+            let up = result.scattered.direction().unit_vector();
+            let cos_up = Vec3::dot(up, normal);
+            // transmitted through the base, or scattered into it
+            if cos_up <= 0.0 {
+                return Some(ScatterResult {
+                    attenuation,
+                    dispersive,
+                    film_reflectance: None,
+                    ..result
+                });
+            }
+            attenuation = attenuation * self.coating_transmittance(cos_up);
+
+            // leaves the coating unless reflected back down at its top
+            let sin_out = self.refraction_index * (1.0 - cos_up * cos_up).sqrt();
+            if sin_out < 1.0 {
+                let cos_out = (1.0 - sin_out * sin_out).sqrt();
+                if Dielectric::reflectance(cos_out, self.refraction_index) <= pass_sampler.get_1d()
+                {
+                    let out = Vec3::refract(up, -normal, self.refraction_index);
+                    return Some(ScatterResult {
+                        attenuation,
+                        scattered: Ray::new(result.scattered.origin(), out),
+                        lobe: result.lobe,
+                        dispersive,
+                        film_reflectance: None,
+                    });
+                }
+            }
+            direction = Vec3::reflect(up, normal);
+            if walk_sampler.is_none() {
+                walk_sampler = Some(sampler.independent_stream(COATING_WALK_SEED));
+            }
+        }
+
+        // light still trapped in the coating is taken as absorbed
+        None
+    }
+
+    fn medium(&self) -> Option<Medium<'_>> {
+        self.base.medium()
+    }
+}
+
+// Picks one of two materials at every scattering event, the second with
+// probability weight and the first otherwise, blending them on average. A
+// texture weight masks one material with the other, like rust on metal or
+// dirt on paint.
+pub struct MixMaterial {
+    first: Box<dyn Material>,
+    second: Box<dyn Material>,
+    weight: Arc<dyn Texture>,
+}
+
+impl MixMaterial {
+    pub fn new(first: Box<dyn Material>, second: Box<dyn Material>, weight: f64) -> Self {
+        let weight = weight.clamp(0.0, 1.0);
+        Self::with_texture(
+            first,
+            second,
+            Arc::new(SolidColor::new(Color::new(weight, weight, weight))),
+        )
+    }
+
+    // the weight is the first channel of texture
+    pub fn with_texture(
+        first: Box<dyn Material>,
+        second: Box<dyn Material>,
+        weight: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            first,
+            second,
+            weight,
+        }
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterResult> {
+        let weight = self
+            .weight
+            .value(hit_record.u, hit_record.v, hit_record.point)
+            .x();
+        if weight > sampler.get_1d() {
+            self.second.scatter(ray, hit_record, sampler)
+        } else {
+            self.first.scatter(ray, hit_record, sampler)
+        }
+    }
+
+    // a closed surface holds one medium, that of the first material having
+    // one
+    fn medium(&self) -> Option<Medium<'_>> {
+        self.first.medium().or_else(|| self.second.medium())
+    }
+}
+
+// Cel shading for illustration-style renders. How much a surface faces the
+// light direction is rounded up to one of a few evenly spaced bands of
+// brightness, and paths leave towards the light, so the light color comes
+// from the sky there and other objects cast hard shadows. Surfaces facing
+// away from the light are lit at the ambient level by what lies along the
+// normal.
+#[derive(Clone)]
+pub struct Toon {
+    color: Color,
+    light: Vec3,
+    bands: u32,
+    ambient: f64,
+}
+
+impl Toon {
+    pub fn new(color: Color, light: Vec3, bands: u32) -> Result<Self> {
+        if bands == 0 {
+            return Err(Error::InvalidParameter(
+                "toon shading needs at least one band".to_string(),
+            ));
+        }
+        let finite = [light.x(), light.y(), light.z()]
+            .into_iter()
+            .all(f64::is_finite);
+        if !finite || light.near_zero() {
+            return Err(Error::InvalidParameter(format!(
+                "toon light direction {} needs to be finite and not zero",
+                light
+            )));
+        }
+        Ok(Self {
+            color,
+            light: light.unit_vector(),
+            bands,
+            ambient: 0.3,
+        })
+    }
+
+    // the brightness of the unlit side, 0.3 by default, which the lit bands
+    // are spread above
+    pub fn with_ambient(mut self, ambient: f64) -> Self {
+        self.ambient = ambient.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    fn level(&self, cos_theta: f64) -> f64 {
+        if cos_theta <= 0.0 {
+            return self.ambient;
+        }
+        let band = (cos_theta * self.bands as f64)
+            .ceil()
+            .min(self.bands as f64);
+        self.ambient + (1.0 - self.ambient) * band / self.bands as f64
+    }
+}
+
+impl Material for Toon {
+    fn scatter(
+        &self,
+        _ray: &Ray,
+        hit_record: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterResult> {
+        let cos_theta = Vec3::dot(hit_record.normal, self.light);
+        let direction = if cos_theta > 0.0 {
+            self.light
+        } else {
+            hit_record.normal
+        };
+        Some(ScatterResult {
+            attenuation: self.level(cos_theta) * self.color,
             scattered: Ray::new(hit_record.point, direction),
+            lobe: Lobe::Diffuse,
+            dispersive: false,
+            film_reflectance: None,
         })
     }
 }
+
+// The single scattering albedo of a medium whose multiple scattering gives
+// a thick slab the given albedo, following Chiang et al.'s fit.
+fn single_scattering_albedo(albedo: f64) -> f64 {
+    let s = 4.09712 + 4.20863 * albedo
+        - (9.59217 + 41.6808 * albedo + 17.7126 * albedo * albedo).sqrt();
+    1.0 - s * s
+}
+
+// thin film reflectance of each color channel at its stand-in wavelength
+fn film_rgb_reflectance(
+    film: &ThinFilm,
+    thickness: f64,
+    cos_theta: f64,
+    outside_index: f64,
+    substrate: impl Fn(usize) -> Substrate,
+) -> Color {
+    let channel = |channel: usize| {
+        film.reflectance(
+            thickness,
+            RGB_WAVELENGTHS[channel],
+            cos_theta,
+            outside_index,
+            substrate(channel),
+        )
+    };
+    Color::new(channel(0), channel(1), channel(2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::math::interval::Interval;
+    use crate::point::Point3;
+    use crate::sampler::SamplerKind;
+    use crate::sphere::Sphere;
+
+    fn scatter_off(material: Box<dyn Material>, ray: &Ray) -> ScatterResult {
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, -2.0), 1.0, material);
+        let hit_record = sphere
+            .hit(ray, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        let mut sampler = SamplerKind::Independent.create(1, 7);
+        sampler.start_pixel_sample(0, 0, 0);
+        hit_record
+            .material
+            .scatter(ray, &hit_record, sampler.as_mut())
+            .unwrap()
+    }
+
+    fn filmed_metal() -> Metal {
+        Metal::new(Color::new(0.9, 0.6, 0.3), 0.0)
+            .with_thin_film(ThinFilm::new(420.0, 1.4).unwrap())
+    }
+
+    #[test]
+    fn thin_films_on_metals_weigh_every_wavelength() {
+        let ray =
+            Ray::new(Point3::new(0.3, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)).with_wavelength(550.0);
+        let result = scatter_off(Box::new(filmed_metal()), &ray);
+        assert!(!result.dispersive);
+        let film_reflectance = result.film_reflectance.unwrap();
+        assert_eq!(film_reflectance.at(550.0), result.attenuation.x());
+        assert!((film_reflectance.at(450.0) - film_reflectance.at(650.0)).abs() > 0.01);
+    }
+
+    // counts the sample dimensions a material consumes
+    #[derive(Clone)]
+    struct CountingSampler {
+        inner: IndependentSampler,
+        dimensions: u32,
+    }
+
+    impl Sampler for CountingSampler {
+        fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+            self.inner.start_pixel_sample(x, y, sample_index);
+            self.dimensions = 0;
+        }
+
+        fn set_dimension(&mut self, dimension: u32) {
+            self.inner.set_dimension(dimension);
+        }
+
+        fn get_1d(&mut self) -> f64 {
+            self.dimensions += 1;
+            self.inner.get_1d()
+        }
+
+        fn get_2d(&mut self) -> (f64, f64) {
+            self.dimensions += 2;
+            self.inner.get_2d()
+        }
+
+        fn independent_stream(&self, seed: u64) -> IndependentSampler {
+            self.inner.independent_stream(seed)
+        }
+
+        fn clone_box(&self) -> Box<dyn Sampler> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn coatings_leave_the_alpha_dimension_of_a_bounce() {
+        let base = Box::new(Lambertian::new(Color::new(0.8, 0.8, 0.8)));
+        let sphere = Sphere::new(
+            Point3::new(0.0, 0.0, -2.0),
+            1.0,
+            Box::new(
+                Layered::new(base, 2.5)
+                    .unwrap()
+                    .with_roughness(0.3)
+                    .unwrap(),
+            ),
+        );
+        let ray = Ray::new(Point3::new(0.3, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit_record = sphere
+            .hit(&ray, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        let mut sampler = CountingSampler {
+            inner: IndependentSampler::new(3),
+            dimensions: 0,
+        };
+        for sample_index in 0..1000 {
+            sampler.start_pixel_sample(0, 0, sample_index);
+            hit_record.material.scatter(&ray, &hit_record, &mut sampler);
+            assert!(sampler.dimensions <= 7, "{} dimensions", sampler.dimensions);
+        }
+    }
+
+    #[test]
+    fn coatings_reject_invalid_parameters() {
+        let base = || Box::new(Lambertian::new(Color::new(0.8, 0.8, 0.8)));
+        for refraction_index in [0.5, f64::NAN, f64::INFINITY] {
+            assert!(Layered::new(base(), refraction_index).is_err());
+        }
+        for roughness in [-0.1, 1.5, f64::NAN] {
+            assert!(Layered::new(base(), 1.5)
+                .unwrap()
+                .with_roughness(roughness)
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn coatings_follow_thin_films_at_the_ray_wavelength() {
+        let ray =
+            Ray::new(Point3::new(0.3, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)).with_wavelength(550.0);
+        let coated = Layered::new(Box::new(filmed_metal()), 1.0).unwrap();
+        let result = scatter_off(Box::new(coated), &ray);
+        assert!(result.dispersive);
+        assert!(result.film_reflectance.is_none());
+    }
+}
@@ -1,7 +1,20 @@
-use rand::Rng;
+use std::cell::RefCell;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+// Reseeds the generator of the calling thread, making the values it returns
+// from then on reproducible.
+pub fn seed_thread_rng(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
 
 pub fn random_double() -> f64 {
-    rand::thread_rng().gen()
+    RNG.with(|rng| rng.borrow_mut().gen())
 }
 
 pub fn random_double_range(min: f64, max: f64) -> f64 {
@@ -0,0 +1,742 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use crate::error::{Error, Result};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::math::interval::Interval;
+use crate::point::Point3;
+use crate::ray::Ray;
+use crate::sphere::sphere_uv;
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+
+// most triangles in a leaf of the bounding volume hierarchy
+const LEAF_TRIANGLES: usize = 4;
+
+// the grid of longitudes and latitudes spheres are subdivided from
+const SPHERE_SEGMENTS: u32 = 8;
+const SPHERE_RINGS: u32 = 4;
+// 3 million triangles
+const MAX_SPHERE_SUBDIVISIONS: u32 = 8;
+
+// The corners of a triangle, as indices of their vertices and of their
+// texture coordinates, which differ between the triangles around a vertex
+// where the texture wraps around.
+#[derive(Clone, Copy, Debug)]
+pub struct Triangle {
+    pub vertices: [u32; 3],
+    pub uvs: [u32; 3],
+}
+
+// A surface of triangles, shaded with normals interpolated between their
+// vertices so that it looks smooth. Meshes are refined by subdivision and
+// can then be displaced along their normals by a height texture, which
+// unlike a bump map changes the silhouette and the shadows of the surface.
+pub struct Mesh {
+    geometry: Geometry,
+    nodes: Vec<Node>,
+    material: Box<dyn Material>,
+    material_id: u32,
+}
+
+impl Mesh {
+    // Triangles wind counterclockwise seen from the outside. The normals of
+    // the vertices are averaged from the triangles around them.
+    pub fn new(
+        positions: Vec<Point3>,
+        uvs: Vec<(f64, f64)>,
+        triangles: Vec<Triangle>,
+        material: Box<dyn Material>,
+    ) -> Result<Self> {
+        for triangle in &triangles {
+            let outside =
+                |indices: [u32; 3], len: usize| indices.iter().any(|&index| index as usize >= len);
+            if outside(triangle.vertices, positions.len()) || outside(triangle.uvs, uvs.len()) {
+                return Err(Error::InvalidParameter(format!(
+                    "triangle {:?} refers to missing vertices",
+                    triangle
+                )));
+            }
+        }
+        let mut geometry = Geometry {
+            normals: vec![Vec3::new(0.0, 0.0, 0.0); positions.len()],
+            positions,
+            uvs,
+            triangles,
+        };
+        geometry.average_normals();
+        Ok(Self::from_geometry(geometry, material))
+    }
+
+    // A sphere of triangles subdivided from a grid of 8 longitudes and 4
+    // latitudes, each subdivision splitting every triangle into four, with
+    // the texture coordinates of Sphere.
+    pub fn sphere(
+        center: Point3,
+        radius: f64,
+        subdivisions: u32,
+        material: Box<dyn Material>,
+    ) -> Result<Self> {
+        if subdivisions > MAX_SPHERE_SUBDIVISIONS {
+            return Err(Error::InvalidParameter(format!(
+                "{} subdivisions of a sphere are more than {}",
+                subdivisions, MAX_SPHERE_SUBDIVISIONS
+            )));
+        }
+        let radius = radius.max(0.0);
+        let mut geometry = sphere_grid(center, radius);
+        let poles = [0, geometry.positions.len() - 1];
+        for _ in 0..subdivisions {
+            geometry = geometry.subdivide();
+            geometry.project_onto_sphere(center, radius, poles);
+        }
+        Ok(Self::from_geometry(geometry, material))
+    }
+
+    // Splits every triangle into four at the midpoints of its edges, which
+    // are placed on the curved triangle through the corners and their
+    // normals (Vlachos et al.'s PN triangles), so the surface gets smoother.
+    pub fn subdivide(self) -> Self {
+        Self::from_geometry(self.geometry.subdivide(), self.material)
+            .with_material_id(self.material_id)
+    }
+
+    // Moves every vertex along its normal by the first channel of height
+    // times scale, and averages the normals of the displaced triangles.
+    pub fn displace(mut self, height: &dyn Texture, scale: f64) -> Self {
+        self.geometry.displace(height, scale);
+        Self::from_geometry(self.geometry, self.material).with_material_id(self.material_id)
+    }
+
+    // the ID reported for the material in the material ID output
+    pub fn with_material_id(mut self, material_id: u32) -> Self {
+        self.material_id = material_id;
+        self
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.geometry.triangles.len()
+    }
+
+    fn from_geometry(mut geometry: Geometry, material: Box<dyn Material>) -> Self {
+        let nodes = geometry.build_hierarchy();
+        Self {
+            geometry,
+            nodes,
+            material,
+            material_id: 0,
+        }
+    }
+
+    fn hit_record(&self, ray: &Ray, triangle: usize, t: f64, b1: f64, b2: f64) -> HitRecord<'_> {
+        let geometry = &self.geometry;
+        let Triangle { vertices, uvs } = geometry.triangles[triangle];
+        let [p0, p1, p2] = vertices.map(|vertex| geometry.positions[vertex as usize]);
+        let [n0, n1, n2] = vertices.map(|vertex| geometry.normals[vertex as usize]);
+        let [uv0, uv1, uv2] = uvs.map(|uv| geometry.uvs[uv as usize]);
+        let b0 = 1.0 - b1 - b2;
+
+        let geometric = Vec3::cross(p1 - p0, p2 - p0).unit_vector();
+        let front_face = ray.direction().dot(geometric) < 0.0;
+        let facing = |outward: Vec3| if front_face { outward } else { -outward };
+        let shading = b0 * n0 + b1 * n1 + b2 * n2;
+        // interpolated normals turned away from the ray are left out
+        let normal = if shading.near_zero() || facing(shading).dot(ray.direction()) >= 0.0 {
+            facing(geometric)
+        } else {
+            facing(shading.unit_vector())
+        };
+
+        // the derivatives of the plane of the triangle by u and v
+        let (du02, dv02) = (uv0.0 - uv2.0, uv0.1 - uv2.1);
+        let (du12, dv12) = (uv1.0 - uv2.0, uv1.1 - uv2.1);
+        let (dp02, dp12) = (p0 - p2, p1 - p2);
+        let determinant = du02 * dv12 - dv02 * du12;
+        let (dpdu, dpdv) = if determinant.abs() > 1e-12 {
+            (
+                (dv12 * dp02 - dv02 * dp12) / determinant,
+                (du02 * dp12 - du12 * dp02) / determinant,
+            )
+        } else {
+            let tangent = if geometric.x().abs() > 0.9 {
+                Vec3::new(0.0, 1.0, 0.0)
+            } else {
+                Vec3::new(1.0, 0.0, 0.0)
+            };
+            let dpdu = Vec3::cross(tangent, geometric).unit_vector();
+            (dpdu, Vec3::cross(geometric, dpdu))
+        };
+
+        HitRecord {
+            point: ray.at(t),
+            normal,
+            t,
+            u: b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0,
+            v: b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1,
+            dpdu,
+            dpdv,
+            front_face,
+            material: self.material.as_ref(),
+            object_id: 0,
+            material_id: self.material_id,
+            outside_ior: None,
+        }
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord<'_>> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let direction = ray.direction();
+        let inverse_direction = Vec3::new(
+            1.0 / direction.x(),
+            1.0 / direction.y(),
+            1.0 / direction.z(),
+        );
+        let mut closest = interval.max;
+        let mut nearest = None;
+
+        // the hierarchy is balanced, so it is far less than 64 deep
+        let mut stack = [0usize; 64];
+        let mut stack_len = 1;
+        while stack_len > 0 {
+            stack_len -= 1;
+            let node = &self.nodes[stack[stack_len]];
+            if !node
+                .bounds
+                .hit(ray.origin(), inverse_direction, interval.min, closest)
+            {
+                continue;
+            }
+            if node.count == 0 {
+                stack[stack_len] = node.start;
+                stack[stack_len + 1] = node.start + 1;
+                stack_len += 2;
+                continue;
+            }
+            for triangle in node.start..node.start + node.count {
+                let range = Interval::new(interval.min, closest);
+                if let Some((t, b1, b2)) = self.geometry.intersect(triangle, ray, range) {
+                    closest = t;
+                    nearest = Some((triangle, t, b1, b2));
+                }
+            }
+        }
+
+        let (triangle, t, b1, b2) = nearest?;
+        Some(self.hit_record(ray, triangle, t, b1, b2))
+    }
+}
+
+// vertices, texture coordinates and triangles of a mesh
+struct Geometry {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    triangles: Vec<Triangle>,
+}
+
+impl Geometry {
+    fn average_normals(&mut self) {
+        let mut sums = vec![Vec3::new(0.0, 0.0, 0.0); self.positions.len()];
+        for triangle in &self.triangles {
+            let [p0, p1, p2] = triangle
+                .vertices
+                .map(|vertex| self.positions[vertex as usize]);
+            // weighted by the area of the triangle
+            let normal = Vec3::cross(p1 - p0, p2 - p0);
+            for vertex in triangle.vertices {
+                sums[vertex as usize] += normal;
+            }
+        }
+        self.normals = sums
+            .into_iter()
+            .map(|sum| {
+                if sum.near_zero() {
+                    sum
+                } else {
+                    sum.unit_vector()
+                }
+            })
+            .collect();
+    }
+
+    fn subdivide(&self) -> Geometry {
+        let mut positions = self.positions.clone();
+        let mut normals = self.normals.clone();
+        let mut uvs = self.uvs.clone();
+        let mut vertex_midpoints = HashMap::new();
+        let mut uv_midpoints = HashMap::new();
+        let mut triangles = Vec::with_capacity(4 * self.triangles.len());
+
+        for triangle in &self.triangles {
+            // the triangle between the midpoints, midpoint i on the edge from
+            // corner i
+            let mut middle = Triangle {
+                vertices: [0; 3],
+                uvs: [0; 3],
+            };
+            for edge in 0..3 {
+                let (a, b) = (edge, (edge + 1) % 3);
+                let (va, vb) = (triangle.vertices[a], triangle.vertices[b]);
+                middle.vertices[edge] = *vertex_midpoints
+                    .entry((va.min(vb), va.max(vb)))
+                    .or_insert_with(|| {
+                        let (p0, n0) = (positions[va as usize], normals[va as usize]);
+                        let (p1, n1) = (positions[vb as usize], normals[vb as usize]);
+                        let (position, normal) = pn_midpoint(p0, n0, p1, n1);
+                        positions.push(position);
+                        normals.push(normal);
+                        positions.len() as u32 - 1
+                    });
+                let (ta, tb) = (triangle.uvs[a], triangle.uvs[b]);
+                middle.uvs[edge] =
+                    *uv_midpoints
+                        .entry((ta.min(tb), ta.max(tb)))
+                        .or_insert_with(|| {
+                            let (uv0, uv1) = (uvs[ta as usize], uvs[tb as usize]);
+                            uvs.push((0.5 * (uv0.0 + uv1.0), 0.5 * (uv0.1 + uv1.1)));
+                            uvs.len() as u32 - 1
+                        });
+            }
+
+            // corner i lies between midpoints i - 1 and i
+            for i in 0..3 {
+                let previous = (i + 2) % 3;
+                triangles.push(Triangle {
+                    vertices: [
+                        triangle.vertices[i],
+                        middle.vertices[i],
+                        middle.vertices[previous],
+                    ],
+                    uvs: [triangle.uvs[i], middle.uvs[i], middle.uvs[previous]],
+                });
+            }
+            triangles.push(middle);
+        }
+
+        Geometry {
+            positions,
+            normals,
+            uvs,
+            triangles,
+        }
+    }
+
+    // Moves the vertices onto the sphere and gives them its normals and
+    // texture coordinates, except at the poles, where u is undefined.
+    fn project_onto_sphere(&mut self, center: Point3, radius: f64, poles: [usize; 2]) {
+        for (position, normal) in self.positions.iter_mut().zip(&mut self.normals) {
+            let offset = *position - center;
+            if !offset.near_zero() {
+                *normal = offset.unit_vector();
+                *position = center + radius * *normal;
+            }
+        }
+        for (vertex, uv) in self.uv_vertices().into_iter().zip(&mut self.uvs) {
+            if poles.contains(&vertex) {
+                continue;
+            }
+            let (u, v) = sphere_uv(self.normals[vertex]);
+            // keep the texture coordinates on their side of the seam
+            let u = if uv.0 - u > 0.5 {
+                u + 1.0
+            } else if u - uv.0 > 0.5 {
+                u - 1.0
+            } else {
+                u
+            };
+            *uv = (u, v);
+        }
+    }
+
+    fn displace(&mut self, height: &dyn Texture, scale: f64) {
+        let mut vertex_uvs = vec![(0.0, 0.0); self.positions.len()];
+        for (vertex, &uv) in self.uv_vertices().into_iter().zip(&self.uvs) {
+            vertex_uvs[vertex] = uv;
+        }
+        for ((position, normal), (u, v)) in
+            self.positions.iter_mut().zip(&self.normals).zip(vertex_uvs)
+        {
+            *position = *position + scale * height.value(u, v, *position).x() * *normal;
+        }
+        self.average_normals();
+    }
+
+    // the vertex of each of the texture coordinates
+    fn uv_vertices(&self) -> Vec<usize> {
+        let mut vertices = vec![0; self.uvs.len()];
+        for triangle in &self.triangles {
+            for (vertex, uv) in triangle.vertices.into_iter().zip(triangle.uvs) {
+                vertices[uv as usize] = vertex as usize;
+            }
+        }
+        vertices
+    }
+
+    // Möller and Trumbore's test, returning the distance along the ray and
+    // the barycentric coordinates of the second and third corners.
+    fn intersect(&self, triangle: usize, ray: &Ray, interval: Interval) -> Option<(f64, f64, f64)> {
+        let [p0, p1, p2] = self.triangles[triangle]
+            .vertices
+            .map(|vertex| self.positions[vertex as usize]);
+        let (edge1, edge2) = (p1 - p0, p2 - p0);
+        let p = Vec3::cross(ray.direction(), edge2);
+        let determinant = edge1.dot(p);
+        if determinant == 0.0 {
+            return None;
+        }
+        let inverse = 1.0 / determinant;
+        let offset = ray.origin() - p0;
+        let b1 = offset.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+        let q = Vec3::cross(offset, edge1);
+        let b2 = ray.direction().dot(q) * inverse;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+        let t = edge2.dot(q) * inverse;
+        interval.surrounds(t).then_some((t, b1, b2))
+    }
+
+    // Sorts the triangles into a bounding volume hierarchy, split at the
+    // median along the longest axis of their centroids.
+    fn build_hierarchy(&mut self) -> Vec<Node> {
+        if self.triangles.is_empty() {
+            return Vec::new();
+        }
+        let bounds: Vec<Aabb> = (0..self.triangles.len())
+            .map(|triangle| {
+                self.triangles[triangle]
+                    .vertices
+                    .iter()
+                    .fold(Aabb::empty(), |bounds, &vertex| {
+                        bounds.grow(self.positions[vertex as usize])
+                    })
+            })
+            .collect();
+        let centroids: Vec<Point3> = bounds
+            .iter()
+            .map(|bounds| 0.5 * (bounds.min + bounds.max))
+            .collect();
+
+        let mut order: Vec<usize> = (0..self.triangles.len()).collect();
+        let mut nodes = vec![Node::default()];
+        let mut pending = vec![(0, 0, order.len())];
+        while let Some((node, start, end)) = pending.pop() {
+            let triangles = &mut order[start..end];
+            nodes[node].bounds = triangles.iter().fold(Aabb::empty(), |union, &triangle| {
+                union.union(bounds[triangle])
+            });
+            if triangles.len() <= LEAF_TRIANGLES {
+                nodes[node].start = start;
+                nodes[node].count = triangles.len();
+                continue;
+            }
+
+            let spread = triangles.iter().fold(Aabb::empty(), |spread, &triangle| {
+                spread.grow(centroids[triangle])
+            });
+            let extent = spread.max - spread.min;
+            let axis = if extent.x() >= extent.y() && extent.x() >= extent.z() {
+                0
+            } else if extent.y() >= extent.z() {
+                1
+            } else {
+                2
+            };
+            let half = triangles.len() / 2;
+            triangles.select_nth_unstable_by(half, |&a, &b| {
+                centroids[a][axis].total_cmp(&centroids[b][axis])
+            });
+
+            let children = nodes.len();
+            nodes.push(Node::default());
+            nodes.push(Node::default());
+            nodes[node].start = children;
+            pending.push((children, start, start + half));
+            pending.push((children + 1, start + half, end));
+        }
+
+        self.triangles = order
+            .iter()
+            .map(|&triangle| self.triangles[triangle])
+            .collect();
+        nodes
+    }
+}
+
+// A node of a bounding volume hierarchy. Leaves hold count triangles from
+// start, other nodes have their two children at start and start + 1.
+#[derive(Clone, Copy)]
+struct Node {
+    bounds: Aabb,
+    start: usize,
+    count: usize,
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self {
+            bounds: Aabb::empty(),
+            start: 0,
+            count: 0,
+        }
+    }
+}
+
+// axis-aligned bounding box
+#[derive(Clone, Copy)]
+struct Aabb {
+    min: Point3,
+    max: Point3,
+}
+
+impl Aabb {
+    fn empty() -> Self {
+        Self {
+            min: Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    fn grow(self, point: Point3) -> Self {
+        self.union(Self {
+            min: point,
+            max: point,
+        })
+    }
+
+    fn union(self, other: Self) -> Self {
+        Self {
+            min: Point3::new(
+                self.min.x().min(other.min.x()),
+                self.min.y().min(other.min.y()),
+                self.min.z().min(other.min.z()),
+            ),
+            max: Point3::new(
+                self.max.x().max(other.max.x()),
+                self.max.y().max(other.max.y()),
+                self.max.z().max(other.max.z()),
+            ),
+        }
+    }
+
+    // whether a ray enters the box between t_min and t_max, by the slab test
+    fn hit(&self, origin: Point3, inverse_direction: Vec3, t_min: f64, t_max: f64) -> bool {
+        let (mut t_min, mut t_max) = (t_min, t_max);
+        for axis in 0..3 {
+            let a = (self.min[axis] - origin[axis]) * inverse_direction[axis];
+            let b = (self.max[axis] - origin[axis]) * inverse_direction[axis];
+            // NaNs from rays along the faces of the box are ignored by min
+            // and max
+            t_min = t_min.max(a.min(b));
+            t_max = t_max.min(a.max(b));
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// The midpoint of the edge of a PN triangle from p0 to p1 and its normal,
+// which lie on the cubic curve and the quadratic normal field through the
+// corners and their normals.
+fn pn_midpoint(p0: Point3, n0: Vec3, p1: Point3, n1: Vec3) -> (Point3, Vec3) {
+    let edge = p1 - p0;
+    let position = 0.5 * (p0 + p1) - (edge.dot(n0) * n0 - edge.dot(n1) * n1) / 8.0;
+    let length_squared = edge.length_squared();
+    if length_squared == 0.0 {
+        return (p0, n0);
+    }
+    let normal = n0 + n1 - (2.0 * edge.dot(n0 + n1) / length_squared) * edge;
+    let normal = if normal.near_zero() {
+        normal
+    } else {
+        normal.unit_vector()
+    };
+    (position, normal)
+}
+
+// The coarsest sphere: a grid of longitudes and latitudes whose triangles
+// meet at a vertex at each pole, the south pole first and the north pole
+// last. The vertices along the seam at u = 0 have a second set of texture
+// coordinates at u = 1, and the triangles at the poles take the u of their
+// middle there.
+fn sphere_grid(center: Point3, radius: f64) -> Geometry {
+    let (segments, rings) = (SPHERE_SEGMENTS, SPHERE_RINGS);
+    let point = |u: f64, v: f64| {
+        let (theta, phi) = (v * PI, u * 2.0 * PI);
+        Vec3::new(
+            -theta.sin() * phi.cos(),
+            -theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    };
+
+    let mut normals = vec![Vec3::new(0.0, -1.0, 0.0)];
+    for ring in 1..rings {
+        for segment in 0..segments {
+            normals.push(point(
+                segment as f64 / segments as f64,
+                ring as f64 / rings as f64,
+            ));
+        }
+    }
+    normals.push(Vec3::new(0.0, 1.0, 0.0));
+    let north = normals.len() as u32 - 1;
+
+    let mut uvs = Vec::new();
+    for ring in 1..rings {
+        for segment in 0..=segments {
+            uvs.push((segment as f64 / segments as f64, ring as f64 / rings as f64));
+        }
+    }
+    let pole_uvs = uvs.len() as u32;
+    for v in [0.0, 1.0] {
+        for segment in 0..segments {
+            uvs.push(((segment as f64 + 0.5) / segments as f64, v));
+        }
+    }
+
+    // vertex and texture coordinates at a segment and ring off the poles
+    let corner = |segment: u32, ring: u32| {
+        (
+            1 + (ring - 1) * segments + segment % segments,
+            (ring - 1) * (segments + 1) + segment,
+        )
+    };
+    let triangle = |corners: [(u32, u32); 3]| Triangle {
+        vertices: corners.map(|corner| corner.0),
+        uvs: corners.map(|corner| corner.1),
+    };
+    let mut triangles = Vec::new();
+    for segment in 0..segments {
+        let south = (0, pole_uvs + segment);
+        triangles.push(triangle([
+            south,
+            corner(segment + 1, 1),
+            corner(segment, 1),
+        ]));
+        for ring in 1..rings - 1 {
+            let (a, b) = (corner(segment, ring), corner(segment + 1, ring));
+            let (c, d) = (corner(segment + 1, ring + 1), corner(segment, ring + 1));
+            triangles.push(triangle([a, b, c]));
+            triangles.push(triangle([a, c, d]));
+        }
+        let north = (north, pole_uvs + segments + segment);
+        triangles.push(triangle([
+            north,
+            corner(segment, rings - 1),
+            corner(segment + 1, rings - 1),
+        ]));
+    }
+
+    Geometry {
+        positions: normals
+            .iter()
+            .map(|&normal| center + radius * normal)
+            .collect(),
+        normals,
+        uvs,
+        triangles,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+
+    fn gray() -> Box<dyn Material> {
+        Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    // rays from all around towards the center of a sphere at the origin
+    fn rays() -> impl Iterator<Item = Ray> {
+        (0..200).map(|i| {
+            let (u, v) = ((i as f64 * 0.618034).fract(), (i as f64 + 0.5) / 200.0);
+            let direction = Vec3::sample_unit_vector((u, v));
+            Ray::new(3.0 * direction, -direction)
+        })
+    }
+
+    #[test]
+    fn subdivided_spheres_match_spheres() {
+        let mesh = Mesh::sphere(Point3::new(0.0, 0.0, 0.0), 1.0, 4, gray()).unwrap();
+        assert_eq!(mesh.triangle_count(), 48 * 4usize.pow(4));
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, gray());
+        let interval = || Interval::new(0.001, f64::INFINITY);
+        for ray in rays() {
+            let expected = sphere.hit(&ray, interval()).unwrap();
+            let hit_record = mesh.hit(&ray, interval()).unwrap();
+            assert!(hit_record.front_face);
+            assert!((hit_record.t - expected.t).abs() < 1e-3);
+            assert!((hit_record.normal - expected.normal).length() < 1e-3);
+            // texture coordinates away from the seam and the poles
+            if (0.05..0.95).contains(&expected.u) && (0.1..0.9).contains(&expected.v) {
+                assert!((hit_record.u - expected.u).abs() < 1e-3);
+                assert!((hit_record.v - expected.v).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn displaced_surfaces_move_along_their_normals() {
+        let height = SolidColor::new(Color::new(0.5, 0.5, 0.5));
+        let mesh = Mesh::sphere(Point3::new(0.0, 0.0, 0.0), 1.0, 4, gray())
+            .unwrap()
+            .displace(&height, 0.2);
+        for ray in rays() {
+            let hit_record = mesh.hit(&ray, Interval::new(0.001, f64::INFINITY)).unwrap();
+            assert!((hit_record.t - 1.9).abs() < 1e-3, "{}", hit_record.t);
+        }
+    }
+
+    #[test]
+    fn subdivision_smooths_coarse_meshes() {
+        // a tetrahedron, whose corners stay on the sphere through them
+        let s = 1.0 / 3.0f64.sqrt();
+        let positions = vec![
+            Point3::new(s, s, s),
+            Point3::new(s, -s, -s),
+            Point3::new(-s, s, -s),
+            Point3::new(-s, -s, s),
+        ];
+        let triangles = [[0, 1, 2], [0, 3, 1], [0, 2, 3], [1, 3, 2]]
+            .map(|vertices| Triangle {
+                vertices,
+                uvs: [0; 3],
+            })
+            .to_vec();
+        let mesh = Mesh::new(positions, vec![(0.0, 0.0)], triangles, gray()).unwrap();
+        // the center of a face bulges out from a third of the way to the sphere
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(-1.0, -1.0, -1.0));
+        let t = |mesh: &Mesh| mesh.hit(&ray, Interval::new(0.0, f64::INFINITY)).unwrap().t;
+        let flat = t(&mesh);
+        let subdivided = mesh.subdivide();
+        assert_eq!(subdivided.triangle_count(), 16);
+        assert!(t(&subdivided) > 1.25 * flat);
+        assert!(t(&subdivided) < 1.0 / 3.0f64.sqrt());
+    }
+
+    #[test]
+    fn meshes_reject_missing_vertices() {
+        let triangle = Triangle {
+            vertices: [0, 1, 3],
+            uvs: [0, 0, 0],
+        };
+        let positions = vec![Point3::new(0.0, 0.0, 0.0); 3];
+        assert!(Mesh::new(positions, vec![(0.0, 0.0)], vec![triangle], gray()).is_err());
+        assert!(Mesh::sphere(Point3::new(0.0, 0.0, 0.0), 1.0, 20, gray()).is_err());
+    }
+}
@@ -5,11 +5,30 @@ use crate::vec3::Vec3;
 pub struct Ray {
     origin: Point3,
     direction: Vec3,
+    // wavelength in nm the ray is traced at in spectral mode
+    wavelength: Option<f64>,
+    // uniform sample picking the surfaces of partially opaque objects
+    alpha_sample: Option<f64>,
 }
 
 impl Ray {
     pub fn new(origin: Point3, direction: Vec3) -> Self {
-        Self { origin, direction }
+        Self {
+            origin,
+            direction,
+            wavelength: None,
+            alpha_sample: None,
+        }
+    }
+
+    pub fn with_wavelength(mut self, wavelength: f64) -> Self {
+        self.wavelength = Some(wavelength);
+        self
+    }
+
+    pub fn with_alpha_sample(mut self, alpha_sample: f64) -> Self {
+        self.alpha_sample = Some(alpha_sample);
+        self
     }
 
     pub fn at(&self, t: f64) -> Point3 {
@@ -23,4 +42,12 @@ impl Ray {
     pub fn direction(&self) -> Vec3 {
         self.direction
     }
+
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+
+    pub fn alpha_sample(&self) -> Option<f64> {
+        self.alpha_sample
+    }
 }
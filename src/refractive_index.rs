@@ -0,0 +1,182 @@
+use std::io::{self, Read, Write};
+
+use crate::encoding::{read_f64, read_u32, write_f64, write_u32};
+use crate::error::{Error, Result};
+use crate::spectrum::{SpectralCurve, D_LINE};
+
+// Fraunhofer F and C lines, between which the Abbe number measures
+// dispersion
+const F_LINE: f64 = 486.13;
+const C_LINE: f64 = 656.27;
+
+// Index of refraction, which may vary with the wavelength in nm. Dispersion
+// formulas take wavelengths in micrometers.
+#[derive(Clone, Debug)]
+pub enum RefractiveIndex {
+    Constant(f64),
+    Curve(SpectralCurve),
+    // n = a + b / λ²
+    Cauchy { a: f64, b: f64 },
+    // n² = 1 + Σ b λ² / (λ² - c)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl RefractiveIndex {
+    // Cauchy's equation through the index nd at the d-line with the Abbe
+    // number vd, which is lower for more dispersive glass.
+    pub fn from_abbe(nd: f64, vd: f64) -> Result<Self> {
+        if nd.is_nan() || nd < 1.0 || vd.is_nan() || vd <= 0.0 {
+            return Err(Error::InvalidParameter(format!(
+                "Abbe number {} with index {} needs an index of at least 1 and a positive \
+                 Abbe number",
+                vd, nd
+            )));
+        }
+        let inverse_square = |wavelength: f64| 1.0 / micrometers(wavelength).powi(2);
+        let b = (nd - 1.0) / (vd * (inverse_square(F_LINE) - inverse_square(C_LINE)));
+        let a = nd - b * inverse_square(D_LINE);
+        Ok(RefractiveIndex::Cauchy { a, b })
+    }
+
+    // Schott N-BK7 borosilicate crown glass
+    pub fn bk7() -> Self {
+        RefractiveIndex::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        }
+    }
+
+    // Malitson's fused silica
+    pub fn fused_silica() -> Self {
+        RefractiveIndex::Sellmeier {
+            b: [0.6961663, 0.4079426, 0.8974794],
+            c: [0.00467914826, 0.0135120631, 97.9340025],
+        }
+    }
+
+    // Peter's diamond, with strong dispersion giving it its fire
+    pub fn diamond() -> Self {
+        RefractiveIndex::Sellmeier {
+            b: [0.3306, 4.3356, 0.0],
+            c: [0.030625, 0.011236, 0.0],
+        }
+    }
+
+    pub fn at(&self, wavelength: f64) -> f64 {
+        match self {
+            RefractiveIndex::Constant(ior) => *ior,
+            RefractiveIndex::Curve(curve) => curve.evaluate(wavelength),
+            RefractiveIndex::Cauchy { a, b } => a + b / micrometers(wavelength).powi(2),
+            RefractiveIndex::Sellmeier { b, c } => {
+                let l2 = micrometers(wavelength).powi(2);
+                let sum: f64 = b.iter().zip(c).map(|(b, c)| b * l2 / (l2 - c)).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+
+    pub fn is_constant(&self) -> bool {
+        matches!(self, RefractiveIndex::Constant(_))
+    }
+
+    pub fn write_state(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            RefractiveIndex::Constant(ior) => {
+                write_u32(writer, 0)?;
+                write_f64(writer, *ior)
+            }
+            RefractiveIndex::Curve(curve) => {
+                write_u32(writer, 1)?;
+                write_u32(writer, curve.samples().len() as u32)?;
+                for &(wavelength, value) in curve.samples() {
+                    write_f64(writer, wavelength)?;
+                    write_f64(writer, value)?;
+                }
+                Ok(())
+            }
+            RefractiveIndex::Cauchy { a, b } => {
+                write_u32(writer, 2)?;
+                write_f64(writer, *a)?;
+                write_f64(writer, *b)
+            }
+            RefractiveIndex::Sellmeier { b, c } => {
+                write_u32(writer, 3)?;
+                for value in b.iter().chain(c) {
+                    write_f64(writer, *value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn read_state(reader: &mut impl Read) -> io::Result<Self> {
+        match read_u32(reader)? {
+            0 => Ok(RefractiveIndex::Constant(read_f64(reader)?)),
+            1 => {
+                let count = read_u32(reader)?;
+                let samples = (0..count)
+                    .map(|_| Ok((read_f64(reader)?, read_f64(reader)?)))
+                    .collect::<io::Result<Vec<_>>>()?;
+                let curve = SpectralCurve::new(samples)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+                Ok(RefractiveIndex::Curve(curve))
+            }
+            2 => Ok(RefractiveIndex::Cauchy {
+                a: read_f64(reader)?,
+                b: read_f64(reader)?,
+            }),
+            3 => {
+                let mut values = [0.0; 6];
+                for value in &mut values {
+                    *value = read_f64(reader)?;
+                }
+                Ok(RefractiveIndex::Sellmeier {
+                    b: [values[0], values[1], values[2]],
+                    c: [values[3], values[4], values[5]],
+                })
+            }
+            tag => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown refractive index {}", tag),
+            )),
+        }
+    }
+}
+
+fn micrometers(wavelength: f64) -> f64 {
+    wavelength / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn abbe_number(index: &RefractiveIndex) -> f64 {
+        (index.at(D_LINE) - 1.0) / (index.at(F_LINE) - index.at(C_LINE))
+    }
+
+    #[test]
+    fn presets_match_their_catalog_indices() {
+        // index at the d-line and Abbe number
+        let presets = [
+            (RefractiveIndex::bk7(), 1.5168, 64.17),
+            (RefractiveIndex::fused_silica(), 1.4585, 67.8),
+            (RefractiveIndex::diamond(), 2.4175, 55.3),
+        ];
+        for (index, nd, vd) in presets {
+            assert!((index.at(D_LINE) - nd).abs() < 2e-4, "{:?}", index);
+            assert!((abbe_number(&index) - vd).abs() < 0.5, "{:?}", index);
+            // normal dispersion, bending blue light the most
+            assert!(index.at(450.0) > index.at(650.0));
+        }
+    }
+
+    #[test]
+    fn abbe_numbers_round_trip() {
+        let index = RefractiveIndex::from_abbe(1.62, 36.4).unwrap();
+        assert!((index.at(D_LINE) - 1.62).abs() < 1e-12);
+        assert!((abbe_number(&index) - 36.4).abs() < 1e-9);
+        assert!(RefractiveIndex::from_abbe(0.9, 40.0).is_err());
+        assert!(RefractiveIndex::from_abbe(1.5, 0.0).is_err());
+    }
+}
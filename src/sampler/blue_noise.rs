@@ -0,0 +1,120 @@
+use std::sync::OnceLock;
+
+use super::sobol::scrambled_sobol;
+use super::{hash, IndependentSampler, SampleState, Sampler};
+
+const MASK_SIZE: usize = 64;
+
+// A tileable blue noise mask holding a value in [0, 1) per texel, built by
+// greedy insertion: points are inserted one at a time into the texel with
+// the least gaussian energy from the points so far, and the insertion order
+// becomes the texel value. This is only the void filling phase of
+// Ulichney's void-and-cluster method, without its initial binary pattern
+// and the relaxation removing points from the tightest clusters, so the
+// first few ranks are less evenly spread.
+fn blue_noise_mask() -> &'static [f64] {
+    static MASK: OnceLock<Vec<f64>> = OnceLock::new();
+    MASK.get_or_init(|| {
+        let n = MASK_SIZE * MASK_SIZE;
+        let sigma: f64 = 1.5;
+
+        // gaussian energy for every toroidal offset
+        let kernel: Vec<f64> = (0..n)
+            .map(|i| {
+                let dx = (i % MASK_SIZE).min(MASK_SIZE - i % MASK_SIZE) as f64;
+                let dy = (i / MASK_SIZE).min(MASK_SIZE - i / MASK_SIZE) as f64;
+                (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+            })
+            .collect();
+
+        let mut energy = vec![0.0f64; n];
+        let mut rank = vec![usize::MAX; n];
+
+        for r in 0..n {
+            // the first point is arbitrary, after that take the emptiest texel
+            let void = (0..n)
+                .filter(|&i| rank[i] == usize::MAX)
+                .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+                .unwrap();
+            rank[void] = r;
+
+            let (vx, vy) = (void % MASK_SIZE, void / MASK_SIZE);
+            for (i, e) in energy.iter_mut().enumerate() {
+                let dx = (i % MASK_SIZE + MASK_SIZE - vx) % MASK_SIZE;
+                let dy = (i / MASK_SIZE + MASK_SIZE - vy) % MASK_SIZE;
+                *e += kernel[dy * MASK_SIZE + dx];
+            }
+        }
+
+        rank.into_iter()
+            .map(|r| (r as f64 + 0.5) / n as f64)
+            .collect()
+    })
+}
+
+// Owen scrambled Sobol points shared by all pixels, each pixel shifted
+// toroidally by a blue noise mask value. Per-pixel errors are then
+// distributed as blue noise across the image instead of white noise.
+#[derive(Clone)]
+pub struct BlueNoiseSampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    state: SampleState,
+}
+
+impl BlueNoiseSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        Self {
+            samples_per_pixel: samples_per_pixel.max(1),
+            seed,
+            state: SampleState::default(),
+        }
+    }
+
+    fn sample(&self, dimension: u32) -> f64 {
+        let dimension_hash = hash(&[dimension as u64, self.seed]);
+
+        // every dimension reads the mask at a different offset
+        let offset_x = (dimension_hash as usize) % MASK_SIZE;
+        let offset_y = ((dimension_hash >> 32) as usize) % MASK_SIZE;
+        let mask_x = (self.state.x as usize + offset_x) % MASK_SIZE;
+        let mask_y = (self.state.y as usize + offset_y) % MASK_SIZE;
+        let shift = blue_noise_mask()[mask_y * MASK_SIZE + mask_x];
+
+        let value = scrambled_sobol(
+            self.state.sample_index,
+            dimension,
+            dimension_hash as u32,
+            self.samples_per_pixel,
+        );
+        (value + shift).fract()
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.state.start(x, y, sample_index);
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.state.dimension = dimension;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.state.advance(1);
+        self.sample(dimension)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let dimension = self.state.advance(2);
+        (self.sample(dimension), self.sample(dimension + 1))
+    }
+
+    fn independent_stream(&self, seed: u64) -> IndependentSampler {
+        self.state.independent_stream(seed)
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,87 @@
+use super::{mix_bits, IndependentSampler, SampleState, Sampler, ONE_MINUS_EPSILON};
+
+// 2^53
+const PRECISION: f64 = 9007199254740992.0;
+
+// Dimensions past the table reuse the bases with a different scramble.
+const PRIMES: [u64; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+// The Halton sequence with one prime base per dimension, Owen scrambled per
+// pixel so neighbouring pixels are decorrelated.
+#[derive(Clone)]
+pub struct HaltonSampler {
+    seed: u64,
+    state: SampleState,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            state: SampleState::default(),
+        }
+    }
+
+    fn sample(&self, dimension: u32) -> f64 {
+        let base = PRIMES[dimension as usize % PRIMES.len()];
+        let scramble = self.state.pixel_hash(dimension, self.seed);
+        owen_scrambled_radical_inverse(base, self.state.sample_index as u64, scramble)
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.state.start(x, y, sample_index);
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.state.dimension = dimension;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.state.advance(1);
+        self.sample(dimension)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let dimension = self.state.advance(2);
+        (self.sample(dimension), self.sample(dimension + 1))
+    }
+
+    fn independent_stream(&self, seed: u64) -> IndependentSampler {
+        self.state.independent_stream(seed)
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+// Mirrors the base-b digits of a around the radix point, permuting every
+// digit (including the infinitely many leading zeros, up to precision) by a
+// permutation that depends on the digits before it.
+fn owen_scrambled_radical_inverse(base: u64, mut a: u64, scramble: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_m = 1.0;
+    let mut reversed_digits = 0u64;
+
+    // digits past base^m >= 2^53 are below the precision of f64, and
+    // stopping there keeps reversed_digits below 2^53 * base
+    while inv_base_m * PRECISION > 1.0 {
+        let next = a / base;
+        let digit = a - next * base;
+        let digit_hash = mix_bits(scramble ^ reversed_digits);
+        let digit = (digit + digit_hash % base) % base;
+
+        reversed_digits = reversed_digits * base + digit;
+        inv_base_m *= inv_base;
+        a = next;
+    }
+
+    (reversed_digits as f64 * inv_base_m).min(ONE_MINUS_EPSILON)
+}
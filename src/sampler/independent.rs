@@ -0,0 +1,56 @@
+use super::{hash, to_unit_f64, SampleState, Sampler};
+
+// Uniform random values with no correlation between samples.
+#[derive(Clone)]
+pub struct IndependentSampler {
+    seed: u64,
+    state: SampleState,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            state: SampleState::default(),
+        }
+    }
+
+    fn sample(&self, dimension: u32) -> f64 {
+        let state = &self.state;
+        to_unit_f64(hash(&[
+            state.x as u64,
+            state.y as u64,
+            state.sample_index as u64,
+            dimension as u64,
+            self.seed,
+        ]))
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.state.start(x, y, sample_index);
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.state.dimension = dimension;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.state.advance(1);
+        self.sample(dimension)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let dimension = self.state.advance(2);
+        (self.sample(dimension), self.sample(dimension + 1))
+    }
+
+    fn independent_stream(&self, seed: u64) -> IndependentSampler {
+        self.state.independent_stream(seed)
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,233 @@
+mod blue_noise;
+mod halton;
+mod independent;
+mod sobol;
+mod stratified;
+
+pub use blue_noise::BlueNoiseSampler;
+pub use halton::HaltonSampler;
+pub use independent::IndependentSampler;
+pub use sobol::SobolSampler;
+pub use stratified::StratifiedSampler;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+impl SamplerKind {
+    // samples_per_pixel sizes the strata of the stratified sampler and the
+    // shuffles padding the Sobol based samplers
+    pub fn create(self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(samples_per_pixel, seed)),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler::new(samples_per_pixel, seed)),
+        }
+    }
+}
+
+// Produces the sample values for a path. A path starts with
+// start_pixel_sample, after which every get_1d / get_2d call consumes the
+// next sample dimensions. All implementations are deterministic functions of
+// the pixel, sample index and dimension, so they can be cloned freely.
+pub trait Sampler: Send + Sync {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32);
+
+    // Jumps to a dimension so that, for example, every bounce of a path
+    // consumes the same dimensions regardless of the preceding materials.
+    fn set_dimension(&mut self, dimension: u32);
+
+    fn get_1d(&mut self) -> f64;
+
+    fn get_2d(&mut self) -> (f64, f64);
+
+    // A stream of uncorrelated samples of its own for the current pixel
+    // sample, starting at the current dimension and keyed by seed, for
+    // walks that take an unbounded number of samples. Consumes no
+    // dimensions of this sampler.
+    fn independent_stream(&self, seed: u64) -> IndependentSampler;
+
+    fn clone_box(&self) -> Box<dyn Sampler>;
+}
+
+// The position of a sampler within a pixel sample, shared by all samplers.
+#[derive(Clone, Copy, Default)]
+struct SampleState {
+    x: u32,
+    y: u32,
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl SampleState {
+    fn start(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.x = x;
+        self.y = y;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    // returns the current dimension and advances by count
+    fn advance(&mut self, count: u32) -> u32 {
+        let dimension = self.dimension;
+        self.dimension += count;
+        dimension
+    }
+
+    fn independent_stream(&self, seed: u64) -> IndependentSampler {
+        let mut sampler = IndependentSampler::new(seed);
+        sampler.start_pixel_sample(self.x, self.y, self.sample_index);
+        sampler.set_dimension(self.dimension);
+        sampler
+    }
+
+    fn pixel_hash(&self, dimension: u32, seed: u64) -> u64 {
+        hash(&[self.x as u64, self.y as u64, dimension as u64, seed])
+    }
+}
+
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0x9e3779b97f4a7c15, |h, &v| mix_bits(h ^ mix_bits(v)))
+}
+
+fn to_unit_f64(bits: u64) -> f64 {
+    ((bits >> 11) as f64 * (1.0 / (1u64 << 53) as f64)).min(ONE_MINUS_EPSILON)
+}
+
+fn u32_to_unit_f64(bits: u32) -> f64 {
+    (bits as f64 / 4294967296.0).min(ONE_MINUS_EPSILON)
+}
+
+// Kensler's hash based permutation, returns element i of a random
+// permutation of 0..n selected by seed
+fn permutation_element(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            break;
+        }
+    }
+
+    (i.wrapping_add(seed)) % n
+}
+
+// nested uniform scramble of the bits of a 32-bit fixed point value
+fn owen_scramble(mut v: u32, seed: u32) -> u32 {
+    v = v.reverse_bits();
+    v ^= v.wrapping_mul(0x3d20adea);
+    v = v.wrapping_add(seed);
+    v = v.wrapping_mul((seed >> 16) | 1);
+    v ^= v.wrapping_mul(0x05526c56);
+    v ^= v.wrapping_mul(0x53a22864);
+    v.reverse_bits()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 5] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+        SamplerKind::BlueNoise,
+    ];
+
+    #[test]
+    fn samples_stay_in_unit_interval_in_every_dimension() {
+        // past the camera, bounces, wavelength and deep into random walks
+        let dimensions = [0, 3, 20, 21, 22, 100, 405, 4500];
+        for kind in KINDS {
+            let mut sampler = kind.create(16, 7);
+            for sample_index in 0..40 {
+                sampler.start_pixel_sample(3, 5, sample_index);
+                for dimension in dimensions {
+                    sampler.set_dimension(dimension);
+                    let u = sampler.get_1d();
+                    let (v, w) = sampler.get_2d();
+                    for value in [u, v, w] {
+                        assert!((0.0..1.0).contains(&value), "{:?} gave {}", kind, value);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn samplers_are_deterministic() {
+        for kind in KINDS {
+            let mut first = kind.create(8, 1);
+            let mut second = first.clone_box();
+            for sampler in [&mut first, &mut second] {
+                sampler.start_pixel_sample(10, 2, 5);
+                sampler.set_dimension(30);
+            }
+            assert_eq!(first.get_2d(), second.get_2d(), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn independent_streams_follow_the_pixel_sample() {
+        for kind in KINDS {
+            let mut sampler = kind.create(8, 1);
+            let stream = |sampler: &mut Box<dyn Sampler>, sample_index| {
+                sampler.start_pixel_sample(10, 2, sample_index);
+                sampler.set_dimension(30);
+                sampler.independent_stream(5).get_2d()
+            };
+            let first = stream(&mut sampler, 5);
+            assert_eq!(first, stream(&mut sampler, 5), "{:?}", kind);
+            assert_ne!(first, stream(&mut sampler, 6), "{:?}", kind);
+
+            // taking a stream leaves the sampler where it was
+            let mut other = sampler.clone_box();
+            sampler.independent_stream(5);
+            assert_eq!(sampler.get_1d(), other.get_1d(), "{:?}", kind);
+        }
+    }
+}
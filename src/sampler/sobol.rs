@@ -0,0 +1,211 @@
+use std::sync::OnceLock;
+
+use super::{
+    hash, owen_scramble, permutation_element, u32_to_unit_f64, IndependentSampler, SampleState,
+    Sampler,
+};
+
+// Primitive polynomial degree, coefficients and initial direction numbers
+// for dimensions 2 and up, from Joe and Kuo's new-joe-kuo-6.21201 table.
+// Dimension 1 is the van der Corput sequence.
+const DIRECTION_TABLE: [(u32, u32, &[u32]); 20] = [
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+    (6, 19, &[1, 1, 1, 15, 7, 5]),
+    (6, 22, &[1, 3, 1, 15, 13, 25]),
+    (6, 25, &[1, 1, 5, 5, 19, 61]),
+    (7, 1, &[1, 3, 7, 11, 23, 15, 103]),
+    (7, 4, &[1, 3, 7, 13, 13, 15, 69]),
+];
+
+const SOBOL_DIMENSIONS: usize = DIRECTION_TABLE.len() + 1;
+
+type DirectionNumbers = [[u32; 32]; SOBOL_DIMENSIONS];
+
+fn direction_numbers() -> &'static DirectionNumbers {
+    static NUMBERS: OnceLock<DirectionNumbers> = OnceLock::new();
+    NUMBERS.get_or_init(|| {
+        let mut numbers = [[0u32; 32]; SOBOL_DIMENSIONS];
+
+        for (k, v) in numbers[0].iter_mut().enumerate() {
+            *v = 1 << (31 - k);
+        }
+
+        for (dimension, &(degree, coefficients, initial)) in DIRECTION_TABLE.iter().enumerate() {
+            let v = &mut numbers[dimension + 1];
+            let s = degree as usize;
+            for k in 0..s {
+                v[k] = initial[k] << (31 - k);
+            }
+            for k in s..32 {
+                v[k] = v[k - s] ^ (v[k - s] >> s);
+                for l in 1..s {
+                    if (coefficients >> (s - 1 - l)) & 1 == 1 {
+                        v[k] ^= v[k - l];
+                    }
+                }
+            }
+        }
+
+        numbers
+    })
+}
+
+// the sample_index-th point of a Sobol dimension as 32-bit fixed point
+fn sobol_sample(mut index: u32, dimension: usize) -> u32 {
+    let v = &direction_numbers()[dimension];
+    let mut result = 0;
+    let mut bit = 0;
+    while index != 0 {
+        if index & 1 == 1 {
+            result ^= v[bit];
+        }
+        index >>= 1;
+        bit += 1;
+    }
+    result
+}
+
+// Evaluates dimension of the Owen scrambled Sobol sequence. Dimensions past
+// the direction table are padded with the first dimension, its points
+// shuffled by a random permutation of every round of samples_per_pixel
+// indices, so they stay stratified but are paired with the other
+// dimensions at random.
+pub(super) fn scrambled_sobol(
+    index: u32,
+    dimension: u32,
+    scramble: u32,
+    samples_per_pixel: u32,
+) -> f64 {
+    let value = if (dimension as usize) < SOBOL_DIMENSIONS {
+        sobol_sample(index, dimension as usize)
+    } else {
+        let round = index / samples_per_pixel;
+        let shuffle = hash(&[scramble as u64, dimension as u64, round as u64]) as u32;
+        let shuffled = permutation_element(index % samples_per_pixel, samples_per_pixel, shuffle);
+        sobol_sample(round * samples_per_pixel + shuffled, 0)
+    };
+    u32_to_unit_f64(owen_scramble(value, scramble))
+}
+
+// The Sobol sequence with an independent Owen scramble per pixel.
+#[derive(Clone)]
+pub struct SobolSampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    state: SampleState,
+}
+
+impl SobolSampler {
+    // samples_per_pixel sizes the shuffles of the dimensions past the
+    // direction table
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        Self {
+            samples_per_pixel: samples_per_pixel.max(1),
+            seed,
+            state: SampleState::default(),
+        }
+    }
+
+    fn sample(&self, dimension: u32) -> f64 {
+        let scramble = self.state.pixel_hash(dimension, self.seed) as u32;
+        scrambled_sobol(
+            self.state.sample_index,
+            dimension,
+            scramble,
+            self.samples_per_pixel,
+        )
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.state.start(x, y, sample_index);
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.state.dimension = dimension;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.state.advance(1);
+        self.sample(dimension)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let dimension = self.state.advance(2);
+        (self.sample(dimension), self.sample(dimension + 1))
+    }
+
+    fn independent_stream(&self, seed: u64) -> IndependentSampler {
+        self.state.independent_stream(seed)
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the stratum of each of count samples of a dimension
+    fn strata(dimension: u32, count: u32, round: u32) -> Vec<usize> {
+        let mut sampler = SobolSampler::new(count, 3);
+        (round * count..(round + 1) * count)
+            .map(|sample_index| {
+                sampler.start_pixel_sample(1, 1, sample_index);
+                sampler.set_dimension(dimension);
+                (sampler.get_1d() * count as f64) as usize
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_dimension_is_stratified() {
+        for dimension in [0, 5, SOBOL_DIMENSIONS as u32 - 1, 21, 40, 4000] {
+            for round in 0..2 {
+                let mut strata = strata(dimension, 64, round);
+                strata.sort_unstable();
+                assert_eq!(
+                    strata,
+                    (0..64).collect::<Vec<_>>(),
+                    "dimension {}",
+                    dimension
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn padded_dimensions_are_not_correlated() {
+        let count = 64;
+        let base = strata(21, count, 0);
+        for dimension in [22, 23, 60] {
+            let other = strata(dimension, count, 0);
+            // the same shuffle in two dimensions would put the points on a
+            // line of matching strata
+            let matching = base.iter().zip(&other).filter(|(a, b)| a == b).count();
+            assert!(
+                matching < 8,
+                "dimension {} matches {} strata",
+                dimension,
+                matching
+            );
+        }
+    }
+}
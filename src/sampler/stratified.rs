@@ -0,0 +1,78 @@
+use super::{hash, permutation_element, to_unit_f64, IndependentSampler, SampleState, Sampler};
+
+// Jittered sampling: every dimension is split into as many strata as there
+// are samples per pixel and each sample index lands in a different stratum.
+// 2D samples use an nx * ny grid. Sample indices past the sample count start
+// a new, differently permuted round of strata.
+#[derive(Clone)]
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    x_strata: u32,
+    y_strata: u32,
+    seed: u64,
+    state: SampleState,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        let x_strata = (samples_per_pixel as f64).sqrt().floor() as u32;
+        let y_strata = samples_per_pixel.div_ceil(x_strata);
+        Self {
+            samples_per_pixel,
+            x_strata,
+            y_strata,
+            seed,
+            state: SampleState::default(),
+        }
+    }
+
+    // picks the stratum of the current sample among count strata, returning
+    // it and the hash used for jittering inside of it
+    fn stratum(&self, dimension: u32, count: u32) -> (u32, u64) {
+        let round = self.state.sample_index / self.samples_per_pixel;
+        let index = self.state.sample_index % self.samples_per_pixel;
+        let pixel_hash = self
+            .state
+            .pixel_hash(dimension, hash(&[self.seed, round as u64]));
+
+        let stratum = permutation_element(index, count, pixel_hash as u32);
+        let jitter_hash = hash(&[pixel_hash, self.state.sample_index as u64]);
+        (stratum, jitter_hash)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.state.start(x, y, sample_index);
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.state.dimension = dimension;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.state.advance(1);
+        let (stratum, jitter_hash) = self.stratum(dimension, self.samples_per_pixel);
+        (stratum as f64 + to_unit_f64(jitter_hash)) / self.samples_per_pixel as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let dimension = self.state.advance(2);
+        let (stratum, jitter_hash) = self.stratum(dimension, self.x_strata * self.y_strata);
+        let x = stratum % self.x_strata;
+        let y = stratum / self.x_strata;
+        (
+            (x as f64 + to_unit_f64(jitter_hash)) / self.x_strata as f64,
+            (y as f64 + to_unit_f64(hash(&[jitter_hash]))) / self.y_strata as f64,
+        )
+    }
+
+    fn independent_stream(&self, seed: u64) -> IndependentSampler {
+        self.state.independent_stream(seed)
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,72 @@
+use std::io::{self, Read, Write};
+
+use crate::color::Color;
+use crate::encoding::{read_f64, read_u32, write_f64, write_u32};
+
+// luminances below this are treated as this bright when computing the
+// relative error, so dark pixels don't demand unbounded samples
+const LUMINANCE_FLOOR: f64 = 0.01;
+
+// Keeps sampling pixels in batches of samples_per_pixel until the relative
+// standard error of their mean luminance falls below target_error or they
+// reach max_samples.
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptiveSampling {
+    pub max_samples: u32,
+    pub target_error: f64,
+}
+
+// Running mean and variance of the sample luminance of a pixel, using
+// Welford's algorithm.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PixelVariance {
+    count: u32,
+    mean: f64,
+    m2: f64,
+}
+
+impl PixelVariance {
+    pub fn add(&mut self, color: Color) {
+        let luminance = luminance(color);
+        self.count += 1;
+        let delta = luminance - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (luminance - self.mean);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    // standard error of the mean relative to the mean
+    pub fn relative_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let variance = self.m2 / (self.count - 1) as f64;
+        let standard_error = (variance / self.count as f64).sqrt();
+        standard_error / self.mean.max(LUMINANCE_FLOOR)
+    }
+
+    pub fn write_state(&self, writer: &mut impl Write) -> io::Result<()> {
+        write_u32(writer, self.count)?;
+        write_f64(writer, self.mean)?;
+        write_f64(writer, self.m2)
+    }
+
+    pub fn read_state(reader: &mut impl Read) -> io::Result<Self> {
+        Ok(Self {
+            count: read_u32(reader)?,
+            mean: read_f64(reader)?,
+            m2: read_f64(reader)?,
+        })
+    }
+
+    pub fn converged(&self, target_error: f64) -> bool {
+        self.relative_error() <= target_error
+    }
+}
+
+pub fn luminance(color: Color) -> f64 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
@@ -0,0 +1,251 @@
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::material::Lobe;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+// Arbitrary output values, images of what camera paths saw besides their
+// color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aov {
+    // attenuation of the first surface hit
+    Albedo,
+    // world space shading normal at the first hit, with bump and normal
+    // maps applied, facing the camera
+    Normal,
+    // world space position of the first hit
+    Position,
+    // distance from the camera to the first hit
+    Depth,
+    // object and material IDs of the first hit, 0 for the background
+    ObjectId,
+    MaterialId,
+    // light reaching the camera after at most one bounce, and the rest
+    Direct,
+    Indirect,
+    // light scattered first by a diffuse or a specular lobe
+    Diffuse,
+    Specular,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 10] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Position,
+        Aov::Depth,
+        Aov::ObjectId,
+        Aov::MaterialId,
+        Aov::Direct,
+        Aov::Indirect,
+        Aov::Diffuse,
+        Aov::Specular,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Position => "position",
+            Aov::Depth => "depth",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+            Aov::Diffuse => "diffuse",
+            Aov::Specular => "specular",
+        }
+    }
+
+    // IDs can't be averaged, so they are taken from the first sample of a
+    // pixel instead
+    pub fn is_id(self) -> bool {
+        matches!(self, Aov::ObjectId | Aov::MaterialId)
+    }
+
+    // whether the AOV holds light reaching the camera, rather than data
+    pub fn is_light(self) -> bool {
+        matches!(
+            self,
+            Aov::Direct | Aov::Indirect | Aov::Diffuse | Aov::Specular
+        )
+    }
+
+    // Channel names in EXR layers. Single channel AOVs hold their value in
+    // all three color channels of images, of which only the first is written.
+    pub fn channel_names(self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+            _ => &["R", "G", "B"],
+        }
+    }
+
+    // Maps the values of an AOV image to colors that can be viewed in an 8
+    // bit image. Normals are mapped from [-1, 1], depths are scaled by the
+    // farthest depth and IDs get a random color each.
+    pub fn preview(self, values: &[Color]) -> Vec<Color> {
+        match self {
+            Aov::Normal => values
+                .iter()
+                .map(|&n| 0.5 * (n + Color::new(1.0, 1.0, 1.0)))
+                .collect(),
+            Aov::Depth => {
+                let max = values.iter().map(|d| d.x()).fold(0.0, f64::max);
+                let scale = if max > 0.0 { 1.0 / max } else { 0.0 };
+                values.iter().map(|&d| scale * d).collect()
+            }
+            Aov::ObjectId | Aov::MaterialId => values.iter().map(|id| id_color(id.x())).collect(),
+            _ => values.to_vec(),
+        }
+    }
+
+    fn value(self, sample: &AovSample) -> Color {
+        let scalar = |v: f64| Color::new(v, v, v);
+        match self {
+            Aov::Albedo => sample.albedo,
+            Aov::Normal => sample.normal,
+            Aov::Position => sample.position,
+            Aov::Depth => scalar(sample.depth),
+            Aov::ObjectId => scalar(sample.object_id as f64),
+            Aov::MaterialId => scalar(sample.material_id as f64),
+            Aov::Direct => sample.direct,
+            Aov::Indirect => sample.indirect,
+            Aov::Diffuse => sample.diffuse,
+            Aov::Specular => sample.specular,
+        }
+    }
+}
+
+fn id_color(id: f64) -> Color {
+    if id <= 0.0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    let mut h = (id as u64).wrapping_mul(0x9e3779b97f4a7c15);
+    h ^= h >> 29;
+    let channel = |shift: u32| 0.2 + 0.8 * ((h >> shift) & 0xff) as f64 / 255.0;
+    Color::new(channel(0), channel(8), channel(16))
+}
+
+// What a single camera path recorded for the AOVs.
+#[derive(Clone, Copy)]
+pub struct AovSample {
+    pub albedo: Color,
+    pub normal: Vec3,
+    pub position: Vec3,
+    pub depth: f64,
+    pub object_id: u32,
+    pub material_id: u32,
+    pub direct: Color,
+    pub indirect: Color,
+    pub diffuse: Color,
+    pub specular: Color,
+}
+
+impl Default for AovSample {
+    fn default() -> Self {
+        let black = Color::new(0.0, 0.0, 0.0);
+        Self {
+            albedo: black,
+            normal: black,
+            position: black,
+            depth: 0.0,
+            object_id: 0,
+            material_id: 0,
+            direct: black,
+            indirect: black,
+            diffuse: black,
+            specular: black,
+        }
+    }
+}
+
+impl AovSample {
+    pub fn record_first_hit(&mut self, ray: &Ray, hit_record: &HitRecord) {
+        self.normal = hit_record.material.shading_normal(hit_record);
+        self.position = hit_record.point;
+        self.depth = hit_record.t * ray.direction().length();
+        self.object_id = hit_record.object_id;
+        self.material_id = hit_record.material_id;
+    }
+
+    // applies the weight of the camera ray to the light of the path
+    pub fn scale_light(&mut self, weight: f64) {
+        self.direct = weight * self.direct;
+        self.indirect = weight * self.indirect;
+        self.diffuse = weight * self.diffuse;
+        self.specular = weight * self.specular;
+    }
+
+    // Sorts light reaching the camera after bounces scattering events, the
+    // first of which (if any) scattered with first_lobe.
+    pub fn record_light(&mut self, bounces: u32, first_lobe: Option<Lobe>, light: Color) {
+        if bounces <= 1 {
+            self.direct += light;
+        } else {
+            self.indirect += light;
+        }
+        match first_lobe {
+            Some(Lobe::Diffuse) => self.diffuse += light,
+            Some(Lobe::Specular) => self.specular += light,
+            None => {}
+        }
+    }
+}
+
+// Per-pixel sums of the AOV samples of a film. The values are averaged over
+// the samples inside each pixel, without the reconstruction filter.
+#[derive(Clone)]
+pub struct AovBuffers {
+    aovs: Vec<Aov>,
+    sums: Vec<Vec<Color>>,
+    counts: Vec<u32>,
+}
+
+impl AovBuffers {
+    pub fn new(aovs: &[Aov], len: usize) -> Self {
+        Self {
+            aovs: aovs.to_vec(),
+            sums: vec![vec![Color::new(0.0, 0.0, 0.0); len]; aovs.len()],
+            counts: vec![0; len],
+        }
+    }
+
+    pub fn aovs(&self) -> &[Aov] {
+        &self.aovs
+    }
+
+    pub fn add(&mut self, index: usize, sample_index: u32, sample: &AovSample) {
+        self.counts[index] += 1;
+        for (aov, sums) in self.aovs.iter().zip(&mut self.sums) {
+            if !aov.is_id() || sample_index == 0 {
+                sums[index] += aov.value(sample);
+            }
+        }
+    }
+
+    // adds pixel other_index of other to pixel index
+    pub fn add_pixel(&mut self, index: usize, other: &AovBuffers, other_index: usize) {
+        self.counts[index] += other.counts[other_index];
+        for (sums, other_sums) in self.sums.iter_mut().zip(&other.sums) {
+            sums[index] += other_sums[other_index];
+        }
+    }
+
+    // the per-pixel values of aov in row-major order, if it is recorded
+    pub fn pixels(&self, aov: Aov) -> Option<Vec<Color>> {
+        let position = self.aovs.iter().position(|&a| a == aov)?;
+        let pixels = self.sums[position]
+            .iter()
+            .zip(&self.counts)
+            .map(|(&sum, &count)| {
+                if aov.is_id() || count == 0 {
+                    sum
+                } else {
+                    sum / count as f64
+                }
+            })
+            .collect();
+        Some(pixels)
+    }
+}
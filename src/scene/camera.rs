@@ -1,17 +1,75 @@
-use std::fs::File;
-use std::io::Write;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use rayon::prelude::*;
 
-use crate::color::{write_color, Color};
-use crate::hittable::Hittable;
-use crate::hittable_list::HittableList;
+use crate::color::Color;
+use crate::display::DisplayTransform;
+use crate::error::{Error, Result};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::{Lobe, Medium, ScatterResult};
 use crate::math::interval::Interval;
-use crate::math::rng::random_double_range;
 use crate::point::Point3;
 use crate::ray::Ray;
+use crate::sampler::{IndependentSampler, Sampler};
+use crate::scene::adaptive::{AdaptiveSampling, PixelVariance};
+use crate::scene::aov::{Aov, AovSample};
+use crate::scene::cancel::CancellationToken;
+use crate::scene::checkpoint::{Checkpoint, Checkpointing};
+use crate::scene::denoise::Denoiser;
+use crate::scene::film::Film;
+use crate::scene::filter::Filter;
+use crate::scene::image::{save_with_aovs, sibling_path, Image, ImageFormat};
+use crate::scene::lens::{LensElement, LensSystem};
+use crate::scene::media::{
+    continue_ray, sample_flight, sample_phase, Flight, Interface, MediumStack,
+};
+use crate::scene::observer::RenderObserver;
+use crate::scene::outline::Outline;
+use crate::scene::progress::ProgressReporter;
+use crate::scene::progressive::{Progressive, ProgressiveClock};
+use crate::scene::stats::{RayCounters, RayCounts};
+use crate::scene::tile::{tiles, Region, TileOrder};
+use crate::spectrum::{
+    rgb_illuminant, rgb_reflectance, SampledSpectrum, SampledWavelengths, SPECTRUM_SAMPLES,
+};
 use crate::vec3::Vec3;
 
+// lens prescriptions are in millimeters, the scene in meters
+const MILLIMETERS_PER_UNIT: f64 = 1000.0;
+
+// sample dimensions used by the pixel offset and lens position, after which
+// every bounce starts a block of its own
+const CAMERA_DIMENSIONS: u32 = 4;
+const BOUNCE_DIMENSIONS: u32 = 8;
+// the last dimension of every bounce block picks which surfaces of
+// partially opaque objects the bounce hits, leaving the others to materials.
+// Materials that may take more samples than the rest of the block, like
+// light bouncing around inside of a coating, continue in an independent
+// stream of the pixel sample from where they are in the block (see
+// Sampler::independent_stream), keyed by a seed of their own.
+const ALPHA_DIMENSION: u32 = BOUNCE_DIMENSIONS - 1;
+// sample dimensions of each scattering event of a random walk through a
+// medium, in a block after those of the bounces and the wavelengths, which
+// also gives the alpha samples of the rays continuing a bounce
+const WALK_DIMENSIONS: u32 = 4;
+
+// scattering events a path may take through a medium between two surfaces
+// before it is given up on
+const MAX_WALK_STEPS: u32 = 1024;
+
+// pass size used to reach checkpoints when not rendering progressively
+const CHECKPOINT_PASS_SAMPLES: u32 = 16;
+
+pub enum LensMode {
+    ThinLens,
+    Realistic(LensSystem),
+}
+
 pub struct Camera {
     image_width: u32,
     image_height: u32,
@@ -20,26 +78,149 @@ pub struct Camera {
     pixel_delta_u: Vec3,
     pixel_delta_v: Vec3,
     samples_per_pixel: u32,
-    pixel_samples_scale: f64,
     max_depth: u32,
     defocus_angle: f64,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+    focus_dist: f64,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    lens: LensMode,
+    filter: Filter,
+    sampler: Box<dyn Sampler>,
+    adaptive: Option<AdaptiveSampling>,
+    progressive: Option<Progressive>,
+    checkpointing: Option<Checkpointing>,
+    tile_size: u32,
+    tile_order: TileOrder,
+    region: Option<Region>,
+    crop_to_region: bool,
+    report_progress: bool,
+    counters: RayCounters,
+    cancellation: Option<CancellationToken>,
+    observer: Option<Arc<dyn RenderObserver>>,
+    aovs: Vec<Aov>,
+    denoiser: Option<Denoiser>,
+    outline: Option<Outline>,
+    display: DisplayTransform,
+    spectral: bool,
 }
 
-impl Camera {
-    pub fn new(
-        image_width: u32,
-        image_height: u32,
-        samples_per_pixel: u32,
-        max_depth: u32,
-        vfov: f64,
-        lookfrom: Point3,
-        lookat: Point3,
-        vup: Vec3,
-        defocus_angle: f64,
-        focus_dist: f64,
-    ) -> Self {
+// Configures the position, field of view and image of a camera. Anything
+// not set keeps the default of a 700x400 image looking down -z from the
+// origin.
+#[derive(Clone)]
+pub struct CameraBuilder {
+    image_width: u32,
+    image_height: u32,
+    samples_per_pixel: u32,
+    max_depth: u32,
+    vfov: f64,
+    lookfrom: Point3,
+    lookat: Point3,
+    vup: Vec3,
+    defocus_angle: f64,
+    focus_dist: f64,
+    tile_size: u32,
+    tile_order: TileOrder,
+}
+
+impl Default for CameraBuilder {
+    fn default() -> Self {
+        Self {
+            image_width: 700,
+            image_height: 400,
+            samples_per_pixel: 16,
+            max_depth: 4,
+            vfov: 90.0,
+            lookfrom: Point3::new(0.0, 0.0, 0.0),
+            lookat: Point3::new(0.0, 0.0, -1.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            defocus_angle: 0.0,
+            focus_dist: 10.0,
+            tile_size: 32,
+            tile_order: TileOrder::Spiral,
+        }
+    }
+}
+
+impl CameraBuilder {
+    pub fn image_size(mut self, width: u32, height: u32) -> Self {
+        self.image_width = width;
+        self.image_height = height;
+        self
+    }
+
+    pub fn samples_per_pixel(mut self, samples_per_pixel: u32) -> Self {
+        self.samples_per_pixel = samples_per_pixel;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: u32) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // vertical field of view in degrees
+    pub fn vertical_fov(mut self, vfov: f64) -> Self {
+        self.vfov = vfov;
+        self
+    }
+
+    pub fn look_from(mut self, lookfrom: Point3) -> Self {
+        self.lookfrom = lookfrom;
+        self
+    }
+
+    pub fn look_at(mut self, lookat: Point3) -> Self {
+        self.lookat = lookat;
+        self
+    }
+
+    pub fn up(mut self, vup: Vec3) -> Self {
+        self.vup = vup;
+        self
+    }
+
+    // cone angle in degrees of the rays through each pixel, 0 for a pinhole
+    pub fn defocus_angle(mut self, defocus_angle: f64) -> Self {
+        self.defocus_angle = defocus_angle;
+        self
+    }
+
+    // distance from the camera to the plane of perfect focus
+    pub fn focus_distance(mut self, focus_dist: f64) -> Self {
+        self.focus_dist = focus_dist;
+        self
+    }
+
+    // square tiles of tile_size pixels, handed out to the render threads in
+    // tile_order
+    pub fn tiles(mut self, tile_size: u32, tile_order: TileOrder) -> Self {
+        self.tile_size = tile_size;
+        self.tile_order = tile_order;
+        self
+    }
+
+    pub fn build(self) -> Result<Camera> {
+        self.validate()?;
+
+        let CameraBuilder {
+            image_width,
+            image_height,
+            samples_per_pixel,
+            max_depth,
+            vfov,
+            lookfrom,
+            lookat,
+            vup,
+            defocus_angle,
+            focus_dist,
+            tile_size,
+            tile_order,
+        } = self;
+
         let aspect_ratio = image_width as f64 / image_height as f64;
 
         let center = lookfrom;
@@ -71,7 +252,7 @@ impl Camera {
         let defocus_disk_u = u * defocus_radius;
         let defocus_disk_v = v * defocus_radius;
 
-        Self {
+        Ok(Camera {
             image_width,
             image_height,
             center,
@@ -79,83 +260,1025 @@ impl Camera {
             pixel_delta_u,
             pixel_delta_v,
             samples_per_pixel,
-            pixel_samples_scale: 1.0 / samples_per_pixel as f64,
             max_depth,
             defocus_angle,
             defocus_disk_u,
             defocus_disk_v,
+            focus_dist,
+            u,
+            v,
+            w,
+            lens: LensMode::ThinLens,
+            filter: Filter::box_filter(None),
+            sampler: Box::new(IndependentSampler::new(0)),
+            adaptive: None,
+            progressive: None,
+            checkpointing: None,
+            tile_size,
+            tile_order,
+            region: None,
+            crop_to_region: false,
+            report_progress: false,
+            counters: RayCounters::default(),
+            cancellation: None,
+            observer: None,
+            aovs: Vec::new(),
+            denoiser: None,
+            outline: None,
+            display: DisplayTransform::default(),
+            spectral: false,
+        })
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.image_width == 0 || self.image_height == 0 {
+            return Err(Error::InvalidParameter(format!(
+                "image size {}x{} is empty",
+                self.image_width, self.image_height
+            )));
+        }
+        if self.samples_per_pixel == 0 {
+            return Err(Error::InvalidParameter(
+                "samples per pixel must be at least 1".to_string(),
+            ));
+        }
+        if self.tile_size == 0 {
+            return Err(Error::InvalidParameter(
+                "tile size must be at least 1".to_string(),
+            ));
+        }
+        if !(self.vfov > 0.0 && self.vfov < 180.0) {
+            return Err(Error::InvalidParameter(format!(
+                "vertical field of view {} must be between 0 and 180 degrees",
+                self.vfov
+            )));
+        }
+        if self.focus_dist.is_nan() || self.focus_dist <= 0.0 {
+            return Err(Error::InvalidParameter(format!(
+                "focus distance {} must be positive",
+                self.focus_dist
+            )));
+        }
+        if (self.lookfrom - self.lookat).length_squared() == 0.0 {
+            return Err(Error::InvalidParameter(
+                "camera looks at its own position".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Camera {
+    pub fn builder() -> CameraBuilder {
+        CameraBuilder::default()
+    }
+
+    pub fn with_sampler(mut self, sampler: Box<dyn Sampler>) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    // With adaptive sampling samples_per_pixel becomes the minimum sample
+    // count of a pixel and the batch size it is refined with.
+    pub fn with_adaptive_sampling(mut self, adaptive: AdaptiveSampling) -> Self {
+        self.adaptive = Some(adaptive);
+        self
+    }
+
+    pub fn with_progressive(mut self, progressive: Progressive) -> Self {
+        self.progressive = Some(progressive);
+        self
+    }
+
+    pub fn with_checkpointing(mut self, checkpointing: Checkpointing) -> Self {
+        self.checkpointing = Some(checkpointing);
+        self
+    }
+
+    // Only renders the pixels inside region. The output is either the full
+    // frame with everything outside of the region black, or, with
+    // crop_to_region, just the region.
+    pub fn with_region(mut self, region: Region, crop_to_region: bool) -> Self {
+        self.region = Some(region);
+        self.crop_to_region = crop_to_region;
+        self
+    }
+
+    // Prints the progress and estimated time remaining to stderr while
+    // rendering.
+    pub fn with_progress_report(mut self) -> Self {
+        self.report_progress = true;
+        self
+    }
+
+    // Stops rendering once token is cancelled, in which case rendering
+    // returns Error::Cancelled.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    pub fn with_observer(mut self, observer: Arc<dyn RenderObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    // Also records the given AOVs, which are written along with the image.
+    pub fn with_aovs(mut self, aovs: Vec<Aov>) -> Self {
+        self.aovs = aovs;
+        self
+    }
+
+    // Writes the denoised image at the output, and the raw image next to it
+    // with .raw added to the file name (image.raw.ppm for image.ppm). The
+    // feature AOVs the denoiser needs are recorded even if not requested.
+    pub fn with_denoiser(mut self, denoiser: Denoiser) -> Self {
+        self.denoiser = Some(denoiser);
+        self
+    }
+
+    // Draws outlines over the output image, after denoising it. The feature
+    // AOVs the outlines are found in are recorded even if not requested.
+    pub fn with_outline(mut self, outline: Outline) -> Self {
+        self.outline = Some(outline);
+        self
+    }
+
+    // Sets the exposure, tone mapping and color space of 8-bit outputs.
+    pub fn with_display_transform(mut self, display: DisplayTransform) -> Self {
+        self.display = display;
+        self
+    }
+
+    // Traces every path at a few sampled wavelengths instead of in RGB,
+    // upsampling the colors of materials and lights to spectra.
+    pub fn with_spectral(mut self) -> Self {
+        self.spectral = true;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    // Replaces the thin lens with a multi-element lens system. The field of
+    // view is then determined by the lens and the film diagonal (in mm).
+    pub fn with_lens_system(
+        mut self,
+        elements: Vec<LensElement>,
+        film_diagonal: f64,
+    ) -> Result<Self> {
+        let aspect_ratio = self.image_width as f64 / self.image_height as f64;
+        let lens = LensSystem::new(
+            elements,
+            film_diagonal,
+            aspect_ratio,
+            self.focus_dist * MILLIMETERS_PER_UNIT,
+        )?;
+        self.lens = LensMode::Realistic(lens);
+        Ok(self)
+    }
+
+    // Renders the image, replacing the image at output with progressive
+    // snapshots and the final image.
+    pub fn render(&self, world: &dyn Hittable, output: &Path) -> Result<Film> {
+        ImageFormat::from_path(output)?;
+        self.render_passes(
+            world,
+            Some(output),
+            self.empty_film(),
+            self.empty_variances(),
+        )
+    }
+
+    // Renders the image in memory, without writing any files other than
+    // checkpoints.
+    pub fn render_image(&self, world: &dyn Hittable) -> Result<Image> {
+        Ok(self.image(&self.render_film(world)?))
+    }
+
+    // Renders into a film in memory, from which the image and AOV images
+    // can be taken.
+    pub fn render_film(&self, world: &dyn Hittable) -> Result<Film> {
+        self.render_passes(world, None, self.empty_film(), self.empty_variances())
+    }
+
+    fn empty_film(&self) -> Film {
+        Film::new(self.image_width, self.image_height, self.filter).with_aovs(&self.film_aovs())
+    }
+
+    fn tile_film(&self, tile: Region) -> Film {
+        Film::for_tile(self.image_width, self.image_height, tile, self.filter)
+            .with_aovs(&self.film_aovs())
+    }
+
+    // the requested AOVs along with the ones the denoiser and the outlines
+    // need
+    fn film_aovs(&self) -> Vec<Aov> {
+        let mut aovs = self.aovs.clone();
+        let mut features = Vec::new();
+        if self.denoiser.is_some() {
+            features.extend(Denoiser::FEATURES);
+        }
+        if self.outline.is_some() {
+            features.extend(Outline::FEATURES);
+        }
+        for feature in features {
+            if !aovs.contains(&feature) {
+                aovs.push(feature);
+            }
+        }
+        aovs
+    }
+
+    fn empty_variances(&self) -> Vec<PixelVariance> {
+        let pixel_count = self.image_width as usize * self.image_height as usize;
+        vec![PixelVariance::default(); pixel_count]
+    }
+
+    // Continues a render from a checkpoint, taking the remaining samples up
+    // to samples_per_pixel (or max_samples with adaptive sampling). The
+    // checkpoint has to match the seed, fingerprint and sampler layout of
+    // the camera's checkpointing, which describe this render.
+    pub fn resume(
+        &self,
+        world: &dyn Hittable,
+        output: &Path,
+        checkpoint: Checkpoint,
+    ) -> Result<Film> {
+        ImageFormat::from_path(output)?;
+        match &self.checkpointing {
+            Some(checkpointing) => checkpointing.check(&checkpoint)?,
+            None => {
+                return Err(Error::InvalidParameter(
+                    "resuming a render needs checkpointing describing it".to_string(),
+                ))
+            }
+        }
+        if checkpoint.film.width() != self.image_width
+            || checkpoint.film.height() != self.image_height
+        {
+            return Err(Error::InvalidParameter(format!(
+                "checkpoint is {}x{}, but the image is {}x{}",
+                checkpoint.film.width(),
+                checkpoint.film.height(),
+                self.image_width,
+                self.image_height
+            )));
+        }
+
+        self.render_passes(world, Some(output), checkpoint.film, checkpoint.variances)
+    }
+
+    fn render_passes(
+        &self,
+        world: &dyn Hittable,
+        output: Option<&Path>,
+        mut film: Film,
+        mut variances: Vec<PixelVariance>,
+    ) -> Result<Film> {
+        // without progressive rendering or checkpoints, a single pass takes
+        // all samples (adaptive sampling keeps adding passes of the same size)
+        let pass_samples = match (&self.progressive, &self.checkpointing) {
+            (Some(progressive), _) => progressive.pass_samples.max(1),
+            (None, Some(_)) => CHECKPOINT_PASS_SAMPLES,
+            (None, None) => self.samples_per_pixel.max(1),
+        };
+        let mut clock = self.progressive.map(ProgressiveClock::start);
+        let mut last_checkpoint = Instant::now();
+        let tiles = self.tiles();
+        let progress = self
+            .report_progress
+            .then(|| self.progress_reporter(&variances));
+
+        for pass in 0.. {
+            let samples_taken = self.render_pass(
+                world,
+                &tiles,
+                &mut variances,
+                pass_samples,
+                progress.as_ref(),
+                &mut film,
+            );
+
+            if self.is_cancelled() {
+                // pixels are only ever cut off between samples, so the state
+                // so far can still be resumed
+                if let Some(checkpointing) = &self.checkpointing {
+                    checkpointing.save(&film, &variances)?;
+                }
+                return Err(Error::Cancelled);
+            }
+
+            // pixels that converged in this pass take no more samples
+            if let Some(progress) = &progress {
+                progress.set_total(self.scheduled_samples(&variances));
+            }
+
+            // the pass that finds every pixel done takes no samples
+            if samples_taken > 0 {
+                if let Some(observer) = &self.observer {
+                    observer.pass_finished(pass, &film);
+                }
+            }
+
+            let finished =
+                samples_taken == 0 || clock.as_ref().is_some_and(ProgressiveClock::out_of_time);
+            if finished {
+                break;
+            }
+
+            if clock.as_mut().is_some_and(ProgressiveClock::snapshot_due) {
+                if let Some(output) = output {
+                    self.write_image(&film, output)?;
+                }
+            }
+
+            if let Some(checkpointing) = &self.checkpointing {
+                if last_checkpoint.elapsed() >= checkpointing.interval {
+                    checkpointing.save(&film, &variances)?;
+                    last_checkpoint = Instant::now();
+                }
+            }
         }
+
+        if let Some(progress) = &progress {
+            progress.finish();
+        }
+
+        // the final checkpoint allows adding more samples later on
+        if let Some(checkpointing) = &self.checkpointing {
+            checkpointing.save(&film, &variances)?;
+        }
+
+        if let Some(output) = output {
+            self.write_image(&film, output)?;
+        }
+        Ok(film)
     }
 
-    pub fn render(&self, world: &HittableList, file: &mut File) {
-        // header
-        writeln!(file, "P3\n{} {}\n255", self.image_width, self.image_height).unwrap();
+    // Adds up to pass_samples samples to every pixel that still needs them
+    // to film, returning how many were taken. Stops early, between pixels,
+    // when the render is cancelled.
+    fn render_pass(
+        &self,
+        world: &dyn Hittable,
+        tiles: &[Region],
+        variances: &mut [PixelVariance],
+        pass_samples: u32,
+        progress: Option<&ProgressReporter>,
+        film: &mut Film,
+    ) -> u64 {
+        let width = self.image_width as usize;
 
-        let pixels: Vec<Color> = (0..self.image_height)
+        // every tile works on its own copy of the state of its pixels, which
+        // is written back once the pass is done
+        let initial_variances: &[PixelVariance] = variances;
+
+        // the threads take tiles from a shared counter so they are started
+        // in order, and add every finished tile to the shared film
+        let shared_film = Mutex::new(film);
+        let next_tile = AtomicUsize::new(0);
+        let (samples_taken, finished_tiles) = (0..rayon::current_num_threads())
             .into_par_iter()
-            .flat_map(|j| {
-                (0..self.image_width)
-                    .into_par_iter()
-                    .map(|i| {
-                        let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-                        for _ in 0..self.samples_per_pixel {
-                            let r = self.get_ray(i, j);
-                            pixel_color += Self::ray_color(&r, self.max_depth, world);
+            .map(|_| {
+                let mut samples_taken = 0;
+                let mut finished_tiles = Vec::new();
+                let mut sampler = self.sampler.clone_box();
+
+                while !self.is_cancelled() {
+                    let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                    let Some(&tile) = tiles.get(index) else {
+                        break;
+                    };
+
+                    // samples near the edges are splatted onto the
+                    // neighbouring tiles, which the tile film covers too
+                    let mut tile_film = self.tile_film(tile);
+                    let mut tile_samples = 0;
+                    let mut counts = RayCounts::default();
+                    let mut tile_variances: Vec<PixelVariance> = tile
+                        .pixels()
+                        .map(|(i, j)| initial_variances[j as usize * width + i as usize])
+                        .collect();
+                    for ((i, j), variance) in tile.pixels().zip(tile_variances.iter_mut()) {
+                        if self.is_cancelled() {
+                            break;
                         }
-                        pixel_color * self.pixel_samples_scale
-                    })
-                    .collect::<Vec<_>>()
+                        tile_samples += self.render_pixel(
+                            i,
+                            j,
+                            world,
+                            sampler.as_mut(),
+                            variance,
+                            pass_samples,
+                            &mut tile_film,
+                            &mut counts,
+                        ) as u64;
+                    }
+
+                    let mut film = shared_film.lock().unwrap();
+                    film.add(&tile_film);
+                    if let Some(observer) = &self.observer {
+                        observer.tile_finished(tile, &film);
+                    }
+                    drop(film);
+
+                    self.counters.add(&counts);
+                    if let Some(progress) = progress {
+                        progress.advance(tile_samples);
+                    }
+                    samples_taken += tile_samples;
+                    finished_tiles.push((tile, tile_variances));
+                }
+
+                (samples_taken, finished_tiles)
             })
-            .collect();
+            .reduce(
+                || (0, Vec::new()),
+                |(samples, mut tiles), (other_samples, other_tiles)| {
+                    tiles.extend(other_tiles);
+                    (samples + other_samples, tiles)
+                },
+            );
+
+        for (tile, tile_variances) in finished_tiles {
+            for ((i, j), variance) in tile.pixels().zip(tile_variances) {
+                variances[j as usize * width + i as usize] = variance;
+            }
+        }
+
+        samples_taken
+    }
+
+    // Renders the given samples of every pixel in tile on their own, for
+    // combining them with the rest of the image elsewhere.
+    pub fn render_tile(&self, world: &dyn Hittable, tile: Region, samples: Range<u32>) -> Film {
+        let tile_film = || self.tile_film(tile);
+
+        (tile.y0..tile.y1)
+            .into_par_iter()
+            .fold(tile_film, |mut film, j| {
+                let mut sampler = self.sampler.clone_box();
+                let mut counts = RayCounts::default();
+                for i in tile.x0..tile.x1 {
+                    for sample_index in samples.clone() {
+                        self.take_sample(
+                            i,
+                            j,
+                            sample_index,
+                            world,
+                            sampler.as_mut(),
+                            &mut film,
+                            &mut counts,
+                        );
+                    }
+                }
+                self.counters.add(&counts);
+                film
+            })
+            .reduce(tile_film, Film::merge)
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+    }
+
+    // the rays traced by all renders of this camera so far
+    pub fn ray_counts(&self) -> RayCounts {
+        self.counters.load()
+    }
+
+    fn progress_reporter(&self, variances: &[PixelVariance]) -> ProgressReporter {
+        let taken = self
+            .render_region()
+            .pixels()
+            .map(|(i, j)| variances[(j * self.image_width + i) as usize].count() as u64)
+            .sum();
+        ProgressReporter::new(
+            self.scheduled_samples(variances),
+            taken,
+            self.progressive
+                .and_then(|progressive| progressive.time_limit),
+        )
+    }
+
+    // The samples the pixels of the rendered region will have once they are
+    // done, counting those that may still converge as taking max_samples.
+    fn scheduled_samples(&self, variances: &[PixelVariance]) -> u64 {
+        self.render_region()
+            .pixels()
+            .map(|(i, j)| {
+                let variance = &variances[(j * self.image_width + i) as usize];
+                self.sample_target(variance).max(variance.count()) as u64
+            })
+            .sum()
+    }
+
+    // the tiles of the rendered region, in rendering order
+    pub fn tiles(&self) -> Vec<Region> {
+        tiles(self.render_region(), self.tile_size, self.tile_order)
+    }
+
+    // the part of the image that is rendered
+    fn render_region(&self) -> Region {
+        let image = Region::new(0, 0, self.image_width, self.image_height);
+        match self.region {
+            Some(region) => region.intersect(&image),
+            None => image,
+        }
+    }
+
+    // Takes up to pass_samples more samples of pixel (i, j), returning how
+    // many were taken. Pixels stop once they reach samples_per_pixel, or with
+    // adaptive sampling, once they have converged or reached max_samples.
+    #[allow(clippy::too_many_arguments)]
+    fn render_pixel(
+        &self,
+        i: u32,
+        j: u32,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+        variance: &mut PixelVariance,
+        pass_samples: u32,
+        film: &mut Film,
+        counts: &mut RayCounts,
+    ) -> u32 {
+        let taken = variance.count();
+        let end = self.sample_target(variance).min(taken + pass_samples);
+
+        for sample_index in taken..end {
+            let color = self.take_sample(i, j, sample_index, world, sampler, film, counts);
+            variance.add(color);
+        }
+
+        end.saturating_sub(taken)
+    }
+
+    // the number of samples a pixel is rendered to
+    fn sample_target(&self, variance: &PixelVariance) -> u32 {
+        match self.adaptive {
+            Some(adaptive)
+                if variance.count() >= self.samples_per_pixel
+                    && variance.converged(adaptive.target_error) =>
+            {
+                variance.count()
+            }
+            Some(adaptive) => adaptive.max_samples,
+            None => self.samples_per_pixel,
+        }
+    }
+
+    // Takes sample sample_index of pixel (i, j) and adds it to film,
+    // returning its color.
+    #[allow(clippy::too_many_arguments)]
+    fn take_sample(
+        &self,
+        i: u32,
+        j: u32,
+        sample_index: u32,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+        film: &mut Film,
+        counts: &mut RayCounts,
+    ) -> Color {
+        sampler.start_pixel_sample(i, j, sample_index);
+        if self.aovs.is_empty() && self.denoiser.is_none() && self.outline.is_none() {
+            let (x, y, color) = self.sample_pixel(i, j, world, sampler, counts, None);
+            film.add_sample(x, y, color);
+            return color;
+        }
+
+        let mut aov = AovSample::default();
+        let (x, y, color) = self.sample_pixel(i, j, world, sampler, counts, Some(&mut aov));
+        film.add_sample(x, y, color);
+        film.add_aov_sample(x, y, sample_index, &aov);
+        color
+    }
+
+    // Resolves the film to the output image, which is either the full frame
+    // with everything outside of the rendered region black, or just the
+    // region when cropping to it.
+    // Writes the sample counts of the rendered pixels of film as a heatmap.
+    pub fn write_sample_heatmap(&self, film: &Film, file: &mut impl Write) -> io::Result<()> {
+        film.write_sample_heatmap(file, self.render_region())
+    }
+
+    pub fn image(&self, film: &Film) -> Image {
+        self.region_image(film, &film.pixels())
+    }
+
+    // the requested AOV images of the film, cropped like the image
+    pub fn aov_images(&self, film: &Film) -> Vec<(Aov, Image)> {
+        self.aovs
+            .iter()
+            .filter_map(|&aov| Some((aov, self.aov_image(film, aov)?)))
+            .collect()
+    }
+
+    fn aov_image(&self, film: &Film, aov: Aov) -> Option<Image> {
+        let pixels = film.aov_pixels(aov)?;
+        Some(self.region_image(film, &pixels))
+    }
+
+    // The image denoised with the camera's denoiser, or None without one or
+    // when the film lacks the feature AOVs.
+    pub fn denoised_image(&self, film: &Film) -> Option<Result<Image>> {
+        let denoiser = self.denoiser?;
+        let [albedo, normal, depth] = Denoiser::FEATURES.map(|aov| self.aov_image(film, aov));
+        Some(denoiser.denoise(&self.image(film), &albedo?, &normal?, &depth?))
+    }
+
+    // image with the camera's outlines drawn over it, or None without them
+    // or when the film lacks the feature AOVs
+    pub fn outlined_image(&self, film: &Film, image: &Image) -> Option<Result<Image>> {
+        let outline = self.outline?;
+        let [normal, depth, object_id] = Outline::FEATURES.map(|aov| self.aov_image(film, aov));
+        Some(outline.draw(image, &normal?, &depth?, &object_id?))
+    }
+
+    fn region_image(&self, film: &Film, pixels: &[Color]) -> Image {
+        let region = self.render_region();
+        let output = if self.crop_to_region {
+            region
+        } else {
+            Region::new(0, 0, film.width(), film.height())
+        };
 
-        // all pixels at once
-        for pixel_color in pixels {
-            write_color(file, pixel_color).unwrap();
+        let mut image = Image::new(output.width(), output.height());
+        for (i, j) in region.pixels() {
+            image.set_pixel(
+                i - output.x0,
+                j - output.y0,
+                pixels[(j * film.width() + i) as usize],
+            );
         }
+        image
     }
 
-    fn ray_color(ray: &Ray, max_depth: u32, world: &HittableList) -> Color {
-        if max_depth <= 0 {
-            return Color::new(0.0, 0.0, 0.0);
+    // Replaces the image at output with the current image on the film, along
+    // with its AOVs.
+    pub fn write_image(&self, film: &Film, output: &Path) -> Result<()> {
+        let image = self.image(film);
+        let image = match self.denoised_image(film) {
+            Some(denoised) => {
+                save_with_aovs(&sibling_path(output, "raw"), &image, &[], &self.display)?;
+                denoised?
+            }
+            None => image,
+        };
+        let image = match self.outlined_image(film, &image) {
+            Some(outlined) => outlined?,
+            None => image,
+        };
+        save_with_aovs(output, &image, &self.aov_images(film), &self.display)
+    }
+
+    // the first sample dimension of random walks through media, after the
+    // one picking wavelengths
+    fn walk_dimensions(&self) -> u32 {
+        CAMERA_DIMENSIONS + self.max_depth * BOUNCE_DIMENSIONS + 1
+    }
+
+    // Traces one camera path through pixel (i, j), returning the raster
+    // position it was taken at and its color.
+    fn sample_pixel(
+        &self,
+        i: u32,
+        j: u32,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+        counts: &mut RayCounts,
+        mut aov: Option<&mut AovSample>,
+    ) -> (f64, f64, Color) {
+        let (offset_x, offset_y) = sampler.get_2d();
+        let x = i as f64 + offset_x;
+        let y = j as f64 + offset_y;
+
+        let color = match self.get_ray(x, y, sampler.get_2d()) {
+            Some((r, weight)) => {
+                counts.camera_rays += 1;
+                let color = if self.spectral {
+                    // after the dimensions of the deepest path, so spectral
+                    // paths scatter like RGB ones
+                    sampler.set_dimension(CAMERA_DIMENSIONS + self.max_depth * BOUNCE_DIMENSIONS);
+                    let mut wavelengths = SampledWavelengths::sample_visible(sampler.get_1d());
+                    self.ray_spectrum(
+                        &r,
+                        &mut wavelengths,
+                        world,
+                        sampler,
+                        counts,
+                        aov.as_deref_mut(),
+                    )
+                } else {
+                    self.ray_color(&r, world, sampler, counts, aov.as_deref_mut())
+                };
+                if let Some(aov) = aov {
+                    aov.scale_light(weight);
+                }
+                weight * color
+            }
+            None => Color::new(0.0, 0.0, 0.0),
+        };
+        (x, y, color)
+    }
+
+    // Follows a path from the camera until it leaves the scene, is absorbed
+    // or reaches max_depth rays, recording what it saw in aov.
+    fn ray_color(
+        &self,
+        ray: &Ray,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+        counts: &mut RayCounts,
+        mut aov: Option<&mut AovSample>,
+    ) -> Color {
+        let mut ray = ray.clone();
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut first_lobe = None;
+        let mut media = MediumStack::default();
+        let mut walk_dimension = self.walk_dimensions();
+
+        for bounce in 0..self.max_depth {
+            let event = next_hit(
+                world,
+                &mut ray,
+                &mut media,
+                sampler,
+                CAMERA_DIMENSIONS + bounce * BOUNCE_DIMENSIONS + ALPHA_DIMENSION,
+                &mut walk_dimension,
+                counts,
+                |medium, distance, sampler| {
+                    let Flight {
+                        scatter_distance,
+                        weight,
+                    } = sample_flight(
+                        channels(medium.absorption + medium.scattering),
+                        channels(medium.scattering),
+                        channels(throughput),
+                        distance,
+                        sampler,
+                    );
+                    throughput = throughput * Color::new(weight[0], weight[1], weight[2]);
+                    scatter_distance
+                },
+            );
+            let hit_record = match event {
+                PathEvent::Surface(hit_record) => hit_record,
+                PathEvent::Escaped => {
+                    let light = throughput * background(&ray);
+                    if let Some(aov) = aov {
+                        aov.record_light(bounce, first_lobe, light);
+                    }
+                    return light;
+                }
+                PathEvent::Trapped => break,
+            };
+
+            sampler.set_dimension(CAMERA_DIMENSIONS + bounce * BOUNCE_DIMENSIONS);
+            let scatter_result = hit_record.material.scatter(&ray, &hit_record, sampler);
+
+            if bounce == 0 {
+                first_lobe =
+                    record_first_scatter(aov.as_deref_mut(), &ray, &hit_record, &scatter_result);
+            }
+
+            let Some(scatter_result) = scatter_result else {
+                return Color::new(0.0, 0.0, 0.0);
+            };
+            media.cross(&hit_record, scatter_result.scattered.direction());
+            throughput = throughput * scatter_result.attenuation;
+            // a path that picked a color channel at a dispersive surface
+            // keeps tracing its wavelength
+            ray = match ray.wavelength() {
+                Some(wavelength) => scatter_result.scattered.with_wavelength(wavelength),
+                None => scatter_result.scattered,
+            };
         }
 
-        if let Some(hit_result) = world.hit(ray, Interval::new(0.001, f64::INFINITY)) {
-            if let Some(scatter_result) = hit_result.scatter_result {
-                return scatter_result.attenuation
-                    * Self::ray_color(&scatter_result.scattered, max_depth - 1, world);
+        counts.max_depth_paths += 1;
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // ray_color for a path carrying wavelengths instead of RGB, returning
+    // its sRGB color.
+    fn ray_spectrum(
+        &self,
+        ray: &Ray,
+        wavelengths: &mut SampledWavelengths,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+        counts: &mut RayCounts,
+        mut aov: Option<&mut AovSample>,
+    ) -> Color {
+        let mut ray = ray.clone().with_wavelength(wavelengths.hero());
+        let mut throughput = SampledSpectrum::splat(1.0);
+        let mut first_lobe = None;
+        let mut media = MediumStack::default();
+        let mut walk_dimension = self.walk_dimensions();
+
+        for bounce in 0..self.max_depth {
+            let event = next_hit(
+                world,
+                &mut ray,
+                &mut media,
+                sampler,
+                CAMERA_DIMENSIONS + bounce * BOUNCE_DIMENSIONS + ALPHA_DIMENSION,
+                &mut walk_dimension,
+                counts,
+                |medium, distance, sampler| {
+                    let absorption = wavelengths.sample(|l| rgb_reflectance(medium.absorption, l));
+                    let scattering = wavelengths.sample(|l| rgb_reflectance(medium.scattering, l));
+                    let Flight {
+                        scatter_distance,
+                        weight,
+                    } = sample_flight::<SPECTRUM_SAMPLES>(
+                        std::array::from_fn(|i| absorption[i] + scattering[i]),
+                        std::array::from_fn(|i| scattering[i]),
+                        std::array::from_fn(|i| throughput[i]),
+                        distance,
+                        sampler,
+                    );
+                    throughput = throughput * SampledSpectrum::from_fn(|i| weight[i]);
+                    scatter_distance
+                },
+            );
+            let hit_record = match event {
+                PathEvent::Surface(hit_record) => hit_record,
+                PathEvent::Escaped => {
+                    let sky = background(&ray);
+                    let radiance = throughput * wavelengths.sample(|l| rgb_illuminant(sky, l));
+                    let light = wavelengths.to_rgb(radiance);
+                    if let Some(aov) = aov {
+                        aov.record_light(bounce, first_lobe, light);
+                    }
+                    return light;
+                }
+                PathEvent::Trapped => break,
+            };
+
+            sampler.set_dimension(CAMERA_DIMENSIONS + bounce * BOUNCE_DIMENSIONS);
+            let scatter_result = hit_record.material.scatter(&ray, &hit_record, sampler);
+
+            if bounce == 0 {
+                first_lobe =
+                    record_first_scatter(aov.as_deref_mut(), &ray, &hit_record, &scatter_result);
+            }
+
+            let Some(scatter_result) = scatter_result else {
+                return Color::new(0.0, 0.0, 0.0);
+            };
+            if scatter_result.dispersive {
+                wavelengths.terminate_secondary();
             }
-            return Color::new(0.0, 0.0, 0.0);
+            media.cross(&hit_record, scatter_result.scattered.direction());
+            let attenuation = scatter_result.attenuation;
+            throughput = throughput
+                * match &scatter_result.film_reflectance {
+                    Some(film_reflectance) => wavelengths.sample(|l| film_reflectance.at(l)),
+                    None => wavelengths.sample(|l| rgb_reflectance(attenuation, l)),
+                };
+            ray = scatter_result.scattered.with_wavelength(wavelengths.hero());
         }
 
-        let unit_direction = ray.direction().unit_vector();
-        let t = 0.5 * (unit_direction.y() + 1.0);
-        (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
+        counts.max_depth_paths += 1;
+        Color::new(0.0, 0.0, 0.0)
     }
 
-    fn get_ray(&self, i: u32, j: u32) -> Ray {
-        let offset = Self::sample_square();
-        let pixel_sample = self.pixel00_loc
-            + ((i as f64 + offset.x()) * self.pixel_delta_u)
-            + ((j as f64 + offset.y()) * self.pixel_delta_v);
+    // Returns a ray through the raster position (x, y) along with its weight,
+    // using lens_sample to pick a point on the lens. Rays blocked inside a
+    // lens system produce no sample.
+    fn get_ray(&self, x: f64, y: f64, lens_sample: (f64, f64)) -> Option<(Ray, f64)> {
+        if let LensMode::Realistic(lens) = &self.lens {
+            let s = x / self.image_width as f64;
+            let t = y / self.image_height as f64;
+            let (lens_ray, weight) = lens.generate_ray(s, t, lens_sample)?;
+
+            let origin = self.center + self.lens_to_world(lens_ray.origin()) / MILLIMETERS_PER_UNIT;
+            let direction = self.lens_to_world(lens_ray.direction());
+            return Some((Ray::new(origin, direction), weight));
+        }
+
+        let pixel_sample =
+            self.pixel00_loc + ((x - 0.5) * self.pixel_delta_u) + ((y - 0.5) * self.pixel_delta_v);
         let origin = if self.defocus_angle <= 0.0 {
             self.center
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(lens_sample)
         };
         let direction = pixel_sample - origin;
-        Ray::new(origin, direction)
+        Some((Ray::new(origin, direction), 1.0))
     }
 
-    fn sample_square() -> Vec3 {
-        Vec3::new(
-            random_double_range(-0.5, 0.5),
-            random_double_range(-0.5, 0.5),
-            0.0,
-        )
+    // lens space has +z pointing along the viewing direction
+    fn lens_to_world(&self, p: Vec3) -> Vec3 {
+        p.x() * self.u + p.y() * self.v - p.z() * self.w
     }
 
-    fn defocus_disk_sample(&self) -> Vec3 {
-        let p = Vec3::random_in_unit_disk();
+    fn defocus_disk_sample(&self, u: (f64, f64)) -> Vec3 {
+        let p = Vec3::sample_in_unit_disk(u);
         self.center + p.x() * self.defocus_disk_u + p.y() * self.defocus_disk_v
     }
 }
+
+// What a path met next.
+enum PathEvent<'w> {
+    Surface(HitRecord<'w>),
+    // left the scene
+    Escaped,
+    // scattered more than MAX_WALK_STEPS times inside a medium
+    Trapped,
+}
+
+// Finds the next surface ray hits that isn't a false interface between
+// nested dielectrics, continuing ray past the false ones. transport is given
+// the medium and length of every segment travelled inside a medium, and
+// returns the distance at which the path scattered in it, if it did, from
+// where ray continues in a direction picked by the phase function. The
+// first ray takes its alpha sample from alpha_dimension and the rays
+// continuing it from the walk dimensions.
+#[allow(clippy::too_many_arguments)]
+fn next_hit<'w>(
+    world: &'w dyn Hittable,
+    ray: &mut Ray,
+    media: &mut MediumStack<'w>,
+    sampler: &mut dyn Sampler,
+    mut alpha_dimension: u32,
+    walk_dimension: &mut u32,
+    counts: &mut RayCounts,
+    mut transport: impl FnMut(&Medium, f64, &mut dyn Sampler) -> Option<f64>,
+) -> PathEvent<'w> {
+    let mut walk_steps = 0;
+    loop {
+        counts.rays += 1;
+        sampler.set_dimension(alpha_dimension);
+        *ray = ray.clone().with_alpha_sample(sampler.get_1d());
+        alpha_dimension = *walk_dimension;
+        *walk_dimension += 1;
+        let Some(mut hit_record) = world.hit(ray, Interval::new(0.001, f64::INFINITY)) else {
+            return PathEvent::Escaped;
+        };
+        if let Some(&medium) = media.current() {
+            let scatters = medium.scattering.length_squared() > 0.0;
+            if scatters {
+                walk_steps += 1;
+                if walk_steps > MAX_WALK_STEPS {
+                    return PathEvent::Trapped;
+                }
+                sampler.set_dimension(*walk_dimension);
+                *walk_dimension += WALK_DIMENSIONS;
+            }
+
+            let length = ray.direction().length();
+            if let Some(distance) = transport(&medium, hit_record.t * length, sampler) {
+                let direction = sample_phase(ray.direction(), medium.anisotropy, sampler.get_2d());
+                let scattered = Ray::new(ray.at(distance / length), direction);
+                *ray = match ray.wavelength() {
+                    Some(wavelength) => scattered.with_wavelength(wavelength),
+                    None => scattered,
+                };
+                continue;
+            }
+        }
+
+        match media.interface(&mut hit_record) {
+            Interface::Real => return PathEvent::Surface(hit_record),
+            Interface::False => {
+                media.cross(&hit_record, ray.direction());
+                *ray = continue_ray(ray, &hit_record);
+            }
+        }
+    }
+}
+
+fn channels(color: Color) -> [f64; 3] {
+    [color.x(), color.y(), color.z()]
+}
+
+// Records the first surface a path hit in aov, returning the lobe the path
+// continued with.
+fn record_first_scatter(
+    aov: Option<&mut AovSample>,
+    ray: &Ray,
+    hit_record: &HitRecord,
+    scatter_result: &Option<ScatterResult>,
+) -> Option<Lobe> {
+    if let Some(aov) = aov {
+        aov.record_first_hit(ray, hit_record);
+        if let Some(scatter_result) = scatter_result {
+            aov.albedo = scatter_result.attenuation;
+        }
+    }
+    scatter_result.as_ref().map(|result| result.lobe)
+}
+
+// the sky gradient lighting the scene
+fn background(ray: &Ray) -> Color {
+    let unit_direction = ray.direction().unit_vector();
+    let t = 0.5 * (unit_direction.y() + 1.0);
+    (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
+}
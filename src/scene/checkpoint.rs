@@ -0,0 +1,245 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::encoding::{read_string, read_u32, read_u64, write_string, write_u32, write_u64};
+use crate::error::{Error, Result};
+use crate::scene::adaptive::PixelVariance;
+use crate::scene::film::Film;
+use crate::scene::filter::Filter;
+
+const MAGIC: &[u8; 8] = b"RRAYCKPT";
+const VERSION: u32 = 3;
+
+// The accumulated state of an interrupted render. Samplers are deterministic
+// in the pixel and sample index, so the per-pixel sample counts double as
+// the random number state needed to continue where the render stopped, as
+// long as the sampler is laid out for the same sample count. A resumed
+// render can take more samples than that, which continue in new rounds of
+// strata.
+pub struct Checkpoint {
+    // seed the scene was generated from
+    pub seed: u64,
+    // description of the scene and camera parameters the samples belong to
+    pub fingerprint: String,
+    // samples per pixel the sampler was created for
+    pub sampler_samples: u32,
+    pub film: Film,
+    pub variances: Vec<PixelVariance>,
+}
+
+impl Checkpoint {
+    pub fn load(path: &Path, filter: Filter) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path).map_err(|err| Error::file(path, err))?);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::UnsupportedFormat(format!(
+                "{} is not a checkpoint file",
+                path.display()
+            )));
+        }
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            return Err(Error::UnsupportedFormat(format!(
+                "checkpoint version {}",
+                version
+            )));
+        }
+
+        let seed = read_u64(&mut reader)?;
+        let fingerprint = read_string(&mut reader)?;
+        let sampler_samples = read_u32(&mut reader)?;
+        let film = Film::read_state(&mut reader, filter)?;
+        let variances = (0..film.width() as usize * film.height() as usize)
+            .map(|_| PixelVariance::read_state(&mut reader))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            seed,
+            fingerprint,
+            sampler_samples,
+            film,
+            variances,
+        })
+    }
+}
+
+// Where and how often a render saves checkpoints.
+#[derive(Clone, Debug)]
+pub struct Checkpointing {
+    pub path: PathBuf,
+    pub interval: Duration,
+    pub seed: u64,
+    pub fingerprint: String,
+    pub sampler_samples: u32,
+}
+
+impl Checkpointing {
+    // Whether a checkpoint belongs to this render, which is what makes its
+    // samples worth continuing.
+    pub fn check(&self, checkpoint: &Checkpoint) -> Result<()> {
+        if checkpoint.seed != self.seed {
+            return Err(Error::InvalidParameter(format!(
+                "checkpoint was rendered with scene seed {}, not {}",
+                checkpoint.seed, self.seed
+            )));
+        }
+        if checkpoint.fingerprint != self.fingerprint {
+            return Err(Error::InvalidParameter(format!(
+                "checkpoint parameters do not match the render\n  checkpoint: {}\n  render:     {}",
+                checkpoint.fingerprint, self.fingerprint
+            )));
+        }
+        if checkpoint.sampler_samples != self.sampler_samples {
+            return Err(Error::InvalidParameter(format!(
+                "checkpoint sampler was laid out for {} samples per pixel, not {}",
+                checkpoint.sampler_samples, self.sampler_samples
+            )));
+        }
+        Ok(())
+    }
+
+    // Writes to a temporary file first so a render killed while saving
+    // still leaves the previous checkpoint intact.
+    pub fn save(&self, film: &Film, variances: &[PixelVariance]) -> io::Result<()> {
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        writer.write_all(MAGIC)?;
+        write_u32(&mut writer, VERSION)?;
+        write_u64(&mut writer, self.seed)?;
+        write_string(&mut writer, &self.fingerprint)?;
+        write_u32(&mut writer, self.sampler_samples)?;
+        film.write_state(&mut writer)?;
+        for variance in variances {
+            variance.write_state(&mut writer)?;
+        }
+        writer.into_inner()?.sync_all()?;
+
+        fs::rename(&temporary, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable_list::HittableList;
+    use crate::material::Lambertian;
+    use crate::point::Point3;
+    use crate::sampler::SamplerKind;
+    use crate::scene::camera::Camera;
+    use crate::sphere::Sphere;
+
+    fn temporary_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rray-test-{}-{}", std::process::id(), name))
+    }
+
+    // a stratified camera taking samples_per_pixel samples, saving its
+    // checkpoints to path
+    fn camera(samples_per_pixel: u32, path: &Path, fingerprint: &str) -> Camera {
+        Camera::builder()
+            .image_size(6, 4)
+            .samples_per_pixel(samples_per_pixel)
+            .max_depth(3)
+            .build()
+            .unwrap()
+            .with_sampler(SamplerKind::Stratified.create(4, 0))
+            .with_checkpointing(Checkpointing {
+                path: path.to_path_buf(),
+                interval: Duration::from_secs(3600),
+                seed: 7,
+                fingerprint: fingerprint.to_string(),
+                sampler_samples: 4,
+            })
+    }
+
+    #[test]
+    fn checkpoints_round_trip() {
+        let filter = Filter::box_filter(None);
+        let mut film = Film::new(4, 3, filter);
+        film.add_sample(1.5, 2.5, Color::new(0.25, 0.5, 1.0));
+        film.add_sample(3.5, 0.5, Color::new(2.0, 0.0, 0.125));
+        let mut variances = vec![PixelVariance::default(); 12];
+        variances[9].add(Color::new(0.25, 0.5, 1.0));
+        variances[9].add(Color::new(0.5, 0.5, 0.5));
+
+        let path = std::env::temp_dir().join(format!("rray-test-{}.ckpt", std::process::id()));
+        let checkpointing = Checkpointing {
+            path: path.clone(),
+            interval: Duration::from_secs(1),
+            seed: 42,
+            fingerprint: "size=4x3 depth=2".to_string(),
+            sampler_samples: 2,
+        };
+        checkpointing.save(&film, &variances).unwrap();
+        let checkpoint = Checkpoint::load(&path, filter);
+        fs::remove_file(&path).unwrap();
+        let checkpoint = checkpoint.unwrap();
+
+        assert_eq!(checkpoint.seed, 42);
+        assert_eq!(checkpoint.fingerprint, "size=4x3 depth=2");
+        assert_eq!(checkpoint.sampler_samples, 2);
+        let pixels = checkpoint.film.pixels();
+        assert_eq!(pixels.len(), 12);
+        for (read, written) in pixels.iter().zip(film.pixels()) {
+            assert_eq!(
+                (read.x(), read.y(), read.z()),
+                (written.x(), written.y(), written.z())
+            );
+        }
+        assert_eq!(checkpoint.variances.len(), 12);
+        assert_eq!(checkpoint.variances[9].count(), 2);
+        assert_eq!(
+            checkpoint.variances[9].relative_error(),
+            variances[9].relative_error()
+        );
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        let path = std::env::temp_dir().join(format!("rray-test-{}.txt", std::process::id()));
+        fs::write(&path, b"not a checkpoint at all").unwrap();
+        let loaded = Checkpoint::load(&path, Filter::box_filter(None));
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(loaded, Err(Error::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn resumed_renders_take_more_samples() {
+        let world: HittableList = vec![Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, -1.0),
+            0.5,
+            Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        ))];
+        let (path, output) = (temporary_path("resume.ckpt"), temporary_path("resume.ppm"));
+        camera(4, &path, "depth=3").render(&world, &output).unwrap();
+
+        let checkpoint = Checkpoint::load(&path, Filter::box_filter(None)).unwrap();
+        let resumed = camera(8, &path, "depth=3").resume(&world, &output, checkpoint);
+        let checkpoint = Checkpoint::load(&path, Filter::box_filter(None)).unwrap();
+        let mismatched = camera(8, &path, "depth=4").resume(&world, &output, checkpoint);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        // the same samples as rendering all of them at once
+        let resumed = resumed.unwrap();
+        let direct = Camera::builder()
+            .image_size(6, 4)
+            .samples_per_pixel(8)
+            .max_depth(3)
+            .build()
+            .unwrap()
+            .with_sampler(SamplerKind::Stratified.create(4, 0))
+            .render_film(&world)
+            .unwrap();
+        for (read, written) in resumed.pixels().iter().zip(direct.pixels()) {
+            assert!((*read - written).length() < 1e-9);
+        }
+        assert!(matches!(mismatched, Err(Error::InvalidParameter(_))));
+    }
+}
@@ -0,0 +1,261 @@
+use rayon::prelude::*;
+
+use crate::color::Color;
+use crate::error::{Error, Result};
+use crate::scene::aov::Aov;
+use crate::scene::image::Image;
+
+// B3 spline weights of the à-trous filter taps at offsets 0, 1 and 2
+const KERNEL: [f64; 3] = [3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// albedo channels below this are treated as 1 so dividing by them doesn't
+// blow up the noise
+const MIN_ALBEDO: f64 = 1e-3;
+
+// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010). Each
+// iteration blurs with a 5x5 kernel whose taps are spread twice as far apart
+// as in the previous one, and weighs every tap by how similar its color,
+// normal and depth are to the filtered pixel so that edges stay sharp.
+// Texture detail is kept by filtering the color divided by the albedo and
+// multiplying the albedo back in afterwards.
+#[derive(Clone, Copy, Debug)]
+pub struct Denoiser {
+    pub iterations: u32,
+    // color distance at which taps are weighted by 1/e, halved each iteration
+    pub color_sigma: f64,
+    // normal distance at which taps are weighted by 1/e
+    pub normal_sigma: f64,
+    // depth difference relative to the pixel depth and the tap spacing at
+    // which taps are weighted by 1/e
+    pub depth_sigma: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            color_sigma: 0.45,
+            normal_sigma: 0.3,
+            depth_sigma: 0.02,
+        }
+    }
+}
+
+impl Denoiser {
+    // the AOVs the denoiser is guided by
+    pub const FEATURES: [Aov; 3] = [Aov::Albedo, Aov::Normal, Aov::Depth];
+
+    pub fn denoise(
+        &self,
+        color: &Image,
+        albedo: &Image,
+        normal: &Image,
+        depth: &Image,
+    ) -> Result<Image> {
+        let (width, height) = (color.width(), color.height());
+        for feature in [albedo, normal, depth] {
+            if feature.width() != width || feature.height() != height {
+                return Err(Error::InvalidParameter(format!(
+                    "{}x{} feature image for a {}x{} image",
+                    feature.width(),
+                    feature.height(),
+                    width,
+                    height
+                )));
+            }
+        }
+
+        let albedo: Vec<Color> = albedo.pixels().iter().map(|&a| safe_albedo(a)).collect();
+        let mut irradiance: Vec<Color> = color
+            .pixels()
+            .iter()
+            .zip(&albedo)
+            .map(|(&c, &a)| c * reciprocal(a))
+            .collect();
+
+        let guide = Guide {
+            width,
+            height,
+            normal: normal.pixels(),
+            depth: depth.pixels(),
+        };
+        for iteration in 0..self.iterations {
+            irradiance = self.filter(&guide, &irradiance, iteration);
+        }
+
+        let pixels = irradiance
+            .iter()
+            .zip(&albedo)
+            .map(|(&e, &a)| e * a)
+            .collect();
+        Ok(Image::from_pixels(width, height, pixels))
+    }
+
+    fn filter(&self, guide: &Guide, input: &[Color], iteration: u32) -> Vec<Color> {
+        let step = 1i64 << iteration;
+        let color_variance = self.color_sigma * self.color_sigma / (1u64 << iteration) as f64;
+        let normal_variance = self.normal_sigma * self.normal_sigma;
+
+        (0..guide.height)
+            .into_par_iter()
+            .flat_map_iter(|j| (0..guide.width).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let p = guide.index(i as i64, j as i64).unwrap();
+                let (color_p, normal_p, depth_p) = (input[p], guide.normal[p], guide.depth[p].x());
+                let depth_scale = self.depth_sigma * depth_p.max(1e-3) * step as f64;
+
+                let mut sum = Color::new(0.0, 0.0, 0.0);
+                let mut total_weight = 0.0;
+                for dy in -2..=2i64 {
+                    for dx in -2..=2i64 {
+                        let Some(q) = guide.index(i as i64 + dx * step, j as i64 + dy * step)
+                        else {
+                            continue;
+                        };
+                        let color_distance = (input[q] - color_p).length_squared();
+                        let normal_distance = (guide.normal[q] - normal_p).length_squared();
+                        let depth_distance = (guide.depth[q].x() - depth_p).abs() / depth_scale;
+
+                        let weight = KERNEL[dx.unsigned_abs() as usize]
+                            * KERNEL[dy.unsigned_abs() as usize]
+                            * (-color_distance / color_variance
+                                - normal_distance / normal_variance
+                                - depth_distance)
+                                .exp();
+                        sum += weight * input[q];
+                        total_weight += weight;
+                    }
+                }
+                // the center tap always has a positive weight
+                sum / total_weight
+            })
+            .collect()
+    }
+}
+
+// The feature buffers the filter weights are computed from.
+struct Guide<'a> {
+    width: u32,
+    height: u32,
+    normal: &'a [Color],
+    depth: &'a [Color],
+}
+
+impl Guide<'_> {
+    fn index(&self, i: i64, j: i64) -> Option<usize> {
+        if i < 0 || j < 0 || i >= self.width as i64 || j >= self.height as i64 {
+            return None;
+        }
+        Some((j * self.width as i64 + i) as usize)
+    }
+}
+
+fn safe_albedo(albedo: Color) -> Color {
+    let channel = |a: f64| if a < MIN_ALBEDO { 1.0 } else { a };
+    Color::new(
+        channel(albedo.x()),
+        channel(albedo.y()),
+        channel(albedo.z()),
+    )
+}
+
+fn reciprocal(color: Color) -> Color {
+    Color::new(1.0 / color.x(), 1.0 / color.y(), 1.0 / color.z())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: u32 = 32;
+
+    fn image(pixel: impl Fn(u32, u32) -> Color) -> Image {
+        let pixels = (0..SIZE * SIZE)
+            .map(|k| pixel(k % SIZE, k / SIZE))
+            .collect();
+        Image::from_pixels(SIZE, SIZE, pixels)
+    }
+
+    fn flat(value: Color) -> Image {
+        image(|_, _| value)
+    }
+
+    // deterministic noise between -0.5 and 0.5
+    fn noise(i: u32, j: u32, channel: u32) -> f64 {
+        let hash = ((i * 73_856_093) ^ (j * 19_349_663) ^ (channel * 83_492_791))
+            .wrapping_mul(2_654_435_761);
+        (hash >> 8) as f64 / (1 << 24) as f64 - 0.5
+    }
+
+    fn variance(pixels: &[Color]) -> f64 {
+        let mean = pixels.iter().map(|c| c.y()).sum::<f64>() / pixels.len() as f64;
+        pixels.iter().map(|c| (c.y() - mean).powi(2)).sum::<f64>() / pixels.len() as f64
+    }
+
+    #[test]
+    fn constant_images_are_kept() {
+        let color = flat(Color::new(0.2, 0.5, 0.9));
+        let albedo = flat(Color::new(0.4, 0.5, 0.6));
+        let normal = flat(Color::new(0.0, 0.0, 1.0));
+        let depth = flat(Color::new(3.0, 3.0, 3.0));
+        let denoised = Denoiser::default()
+            .denoise(&color, &albedo, &normal, &depth)
+            .unwrap();
+        for &pixel in denoised.pixels() {
+            assert!((pixel - Color::new(0.2, 0.5, 0.9)).length() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn noise_is_reduced() {
+        let color = image(|i, j| {
+            Color::new(0.5, 0.5, 0.5)
+                + 0.2 * Color::new(noise(i, j, 0), noise(i, j, 1), noise(i, j, 2))
+        });
+        let albedo = flat(Color::new(0.8, 0.8, 0.8));
+        let normal = flat(Color::new(0.0, 0.0, 1.0));
+        let depth = flat(Color::new(3.0, 3.0, 3.0));
+        let denoised = Denoiser::default()
+            .denoise(&color, &albedo, &normal, &depth)
+            .unwrap();
+        let (before, after) = (variance(color.pixels()), variance(denoised.pixels()));
+        assert!(after < 0.1 * before, "variance {} to {}", before, after);
+    }
+
+    #[test]
+    fn edges_between_surfaces_are_kept() {
+        let left = |i: u32| i < SIZE / 2;
+        let color = image(|i, _| {
+            if left(i) {
+                Color::new(1.0, 0.0, 0.0)
+            } else {
+                Color::new(0.0, 0.0, 1.0)
+            }
+        });
+        let albedo = flat(Color::new(1.0, 1.0, 1.0));
+        let normal = image(|i, _| {
+            if left(i) {
+                Color::new(0.0, 0.0, 1.0)
+            } else {
+                Color::new(1.0, 0.0, 0.0)
+            }
+        });
+        let depth = flat(Color::new(3.0, 3.0, 3.0));
+        let denoised = Denoiser::default()
+            .denoise(&color, &albedo, &normal, &depth)
+            .unwrap();
+        for (k, pixel) in denoised.pixels().iter().enumerate() {
+            let expected = color.pixels()[k];
+            assert!((*pixel - expected).length() < 0.05, "pixel {}", k);
+        }
+    }
+
+    #[test]
+    fn mismatched_features_are_rejected() {
+        let small = Image::from_pixels(2, 2, vec![Color::new(0.0, 0.0, 0.0); 4]);
+        let color = flat(Color::new(0.5, 0.5, 0.5));
+        assert!(Denoiser::default()
+            .denoise(&color, &small, &color, &color)
+            .is_err());
+    }
+}
@@ -0,0 +1,625 @@
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use crate::color::Color;
+use crate::cutout::Cutout;
+use crate::encoding::{read_f64, read_u32, read_u64, write_f64, write_u32, write_u64};
+use crate::error::{Error, Result};
+use crate::hittable_list::HittableList;
+use crate::material::{
+    BumpMap, Dielectric, Lambertian, Layered, Material, Metal, MixMaterial, NormalMap,
+    RefractiveIndex, Subsurface, ThinFilm, Toon,
+};
+use crate::math::rng::{random_double, random_double_range, seed_thread_rng};
+use crate::mesh::Mesh;
+use crate::point::Point3;
+use crate::sampler::SamplerKind;
+use crate::scene::camera::{Camera, CameraBuilder};
+use crate::scene::filter::Filter;
+use crate::scene::image::Image;
+use crate::scene::lens::LensElement;
+use crate::sphere::Sphere;
+use crate::texture::{ImageTexture, Noise, Remap, Texture};
+use crate::vec3::Vec3;
+
+// Everything needed to rebuild the same world and camera in another
+// process. The world is generated from seed, so sending the seed is enough
+// to reproduce it.
+#[derive(Clone)]
+pub struct SceneDescription {
+    pub seed: u64,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    pub filter: Filter,
+    pub sampler: SamplerKind,
+    // lens prescription and film diagonal for the realistic camera
+    pub lens: Option<(Vec<LensElement>, f64)>,
+    // trace wavelengths instead of RGB
+    pub spectral: bool,
+    // refractive index and absorption coefficients of the glass spheres
+    pub glass: RefractiveIndex,
+    pub glass_absorption: Color,
+    // mean thickness in nm of the soap film coating the glass and metal
+    // spheres
+    pub thin_film: Option<f64>,
+    // mean free path of light in the large brown sphere, making it
+    // translucent
+    pub subsurface: Option<f64>,
+    // roughness of the varnish on the small diffuse spheres
+    pub clearcoat: Option<f64>,
+    // patches of rust on the metal spheres
+    pub rust: bool,
+    // height of the dents hammered into the large metal sphere
+    pub bump: Option<f64>,
+    // depth of the dents hammered into the large metal sphere by moving its
+    // surface
+    pub displacement: Option<f64>,
+    // tangent-space normal map of the large metal sphere
+    pub normal_map: Option<Image>,
+    // holes cut into the large brown sphere
+    pub cutout: bool,
+    // bands of cel shading on the diffuse spheres and the ground
+    pub toon: Option<u32>,
+}
+
+impl SceneDescription {
+    pub fn build_world(&self) -> Result<HittableList> {
+        // paths leaving the wax through a hole would stay inside of it
+        if self.cutout && self.subsurface.is_some() {
+            return Err(Error::InvalidParameter(
+                "the large brown sphere can't have holes cut into it when it is wax".to_string(),
+            ));
+        }
+        if let Some(depth) = self.displacement {
+            // deeper dents would turn the sphere inside out
+            if !(depth > 0.0 && depth < 1.0) {
+                return Err(Error::InvalidParameter(format!(
+                    "displacement depth {} must be between 0 and the radius 1 of the sphere",
+                    depth
+                )));
+            }
+        }
+        let thin_film = match self.thin_film {
+            Some(thickness) => Some(soap_film(self.seed, thickness)?),
+            None => None,
+        };
+        let subsurface = match self.subsurface {
+            Some(mean_free_path) => Some(wax(mean_free_path)?),
+            None => None,
+        };
+        let toon = match self.toon {
+            Some(bands) => Some(Toon::new(
+                Color::new(1.0, 1.0, 1.0),
+                Vec3::new(3.0, 4.0, 1.5),
+                bands,
+            )?),
+            None => None,
+        };
+        seed_thread_rng(self.seed);
+        random_spheres_with(SceneMaterials {
+            glass: self.glass.clone(),
+            glass_absorption: self.glass_absorption,
+            thin_film,
+            subsurface,
+            clearcoat: self.clearcoat,
+            rust: self.rust.then(|| rust_mask(self.seed)),
+            bump: self.bump.map(|height| (hammered(self.seed), height)),
+            displacement: self.displacement.map(|depth| (hammered(self.seed), depth)),
+            normal_map: self
+                .normal_map
+                .clone()
+                .map(|image| Arc::new(ImageTexture::new(image)) as Arc<dyn Texture>),
+            cutout: self.cutout.then(|| holes(self.seed)),
+            toon,
+        })
+    }
+
+    pub fn build_camera(&self) -> Result<Camera> {
+        self.build_camera_from(Camera::builder())
+    }
+
+    // Builds the camera starting from builder, which sets up what the
+    // description leaves to the process rendering it, like the tiles.
+    pub fn build_camera_from(&self, builder: CameraBuilder) -> Result<Camera> {
+        let camera = builder
+            .image_size(self.image_width, self.image_height)
+            .samples_per_pixel(self.samples_per_pixel)
+            .max_depth(self.max_depth)
+            .vertical_fov(20.0)
+            .look_from(Point3::new(13.0, 2.0, 3.0))
+            .look_at(Point3::new(0.0, 0.0, 0.0))
+            .up(Vec3::new(0.0, 1.0, 0.0))
+            .defocus_angle(0.6)
+            .focus_distance(10.0)
+            .build()?
+            .with_filter(self.filter)
+            .with_sampler(self.sampler.create(self.samples_per_pixel, 0));
+        let camera = if self.spectral {
+            camera.with_spectral()
+        } else {
+            camera
+        };
+
+        match &self.lens {
+            Some((elements, film_diagonal)) => {
+                camera.with_lens_system(elements.clone(), *film_diagonal)
+            }
+            None => Ok(camera),
+        }
+    }
+
+    pub fn write_state(&self, writer: &mut impl Write) -> io::Result<()> {
+        write_u64(writer, self.seed)?;
+        write_u32(writer, self.image_width)?;
+        write_u32(writer, self.image_height)?;
+        write_u32(writer, self.samples_per_pixel)?;
+        write_u32(writer, self.max_depth)?;
+        self.filter.write_state(writer)?;
+        write_u32(writer, self.sampler as u32)?;
+        write_u32(writer, self.spectral as u32)?;
+        self.glass.write_state(writer)?;
+        for channel in [
+            self.glass_absorption.x(),
+            self.glass_absorption.y(),
+            self.glass_absorption.z(),
+        ] {
+            write_f64(writer, channel)?;
+        }
+        write_u32(writer, self.rust as u32)?;
+        write_u32(writer, self.cutout as u32)?;
+        write_u32(writer, self.toon.unwrap_or(0))?;
+        for option in [
+            self.thin_film,
+            self.subsurface,
+            self.clearcoat,
+            self.bump,
+            self.displacement,
+        ] {
+            match option {
+                Some(value) => {
+                    write_u32(writer, 1)?;
+                    write_f64(writer, value)?;
+                }
+                None => write_u32(writer, 0)?,
+            }
+        }
+        match &self.normal_map {
+            Some(normal_map) => {
+                write_u32(writer, 1)?;
+                normal_map.write_state(writer)?;
+            }
+            None => write_u32(writer, 0)?,
+        }
+
+        match &self.lens {
+            Some((elements, film_diagonal)) => {
+                write_u32(writer, elements.len() as u32)?;
+                for element in elements {
+                    write_f64(writer, element.curvature_radius)?;
+                    write_f64(writer, element.thickness)?;
+                    write_f64(writer, element.ior)?;
+                    write_f64(writer, element.aperture_radius)?;
+                }
+                write_f64(writer, *film_diagonal)
+            }
+            None => write_u32(writer, 0),
+        }
+    }
+
+    pub fn read_state(reader: &mut impl Read) -> io::Result<Self> {
+        let seed = read_u64(reader)?;
+        let image_width = read_u32(reader)?;
+        let image_height = read_u32(reader)?;
+        let samples_per_pixel = read_u32(reader)?;
+        let max_depth = read_u32(reader)?;
+        let filter = Filter::read_state(reader)?;
+        let sampler = match read_u32(reader)? {
+            0 => SamplerKind::Independent,
+            1 => SamplerKind::Stratified,
+            2 => SamplerKind::Halton,
+            3 => SamplerKind::Sobol,
+            4 => SamplerKind::BlueNoise,
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown sampler {}", other),
+                ))
+            }
+        };
+
+        let spectral = read_u32(reader)? != 0;
+        let glass = RefractiveIndex::read_state(reader)?;
+        let glass_absorption = Color::new(read_f64(reader)?, read_f64(reader)?, read_f64(reader)?);
+        let rust = read_u32(reader)? != 0;
+        let cutout = read_u32(reader)? != 0;
+        let toon = Some(read_u32(reader)?).filter(|&bands| bands > 0);
+        let mut read_option = || -> io::Result<Option<f64>> {
+            match read_u32(reader)? {
+                0 => Ok(None),
+                _ => Ok(Some(read_f64(reader)?)),
+            }
+        };
+        let thin_film = read_option()?;
+        let subsurface = read_option()?;
+        let clearcoat = read_option()?;
+        let bump = read_option()?;
+        let displacement = read_option()?;
+        let normal_map = match read_u32(reader)? {
+            0 => None,
+            _ => Some(Image::read_state(reader)?),
+        };
+
+        let element_count = read_u32(reader)?;
+        let lens = if element_count > 0 {
+            let elements = (0..element_count)
+                .map(|_| {
+                    Ok(LensElement {
+                        curvature_radius: read_f64(reader)?,
+                        thickness: read_f64(reader)?,
+                        ior: read_f64(reader)?,
+                        aperture_radius: read_f64(reader)?,
+                    })
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            Some((elements, read_f64(reader)?))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            seed,
+            image_width,
+            image_height,
+            samples_per_pixel,
+            max_depth,
+            filter,
+            sampler,
+            lens,
+            spectral,
+            glass,
+            glass_absorption,
+            thin_film,
+            subsurface,
+            clearcoat,
+            rust,
+            bump,
+            displacement,
+            normal_map,
+            cutout,
+            toon,
+        })
+    }
+}
+
+// subdivisions of the large metal sphere when it is displaced, fine enough
+// for the dents to look round
+const DISPLACEMENT_SUBDIVISIONS: u32 = 6;
+
+// material IDs of the random spheres scene, by kind of material
+const GROUND_ID: u32 = 1;
+const DIFFUSE_ID: u32 = 2;
+const METAL_ID: u32 = 3;
+const GLASS_ID: u32 = 4;
+
+// The random spheres scene, generated from the thread random number
+// generator.
+pub fn random_spheres() -> Result<HittableList> {
+    random_spheres_with(SceneMaterials::default())
+}
+
+// Materials of the random spheres scene that can differ from the defaults.
+pub struct SceneMaterials {
+    // refractive index and absorption coefficients of the glass spheres
+    pub glass: RefractiveIndex,
+    pub glass_absorption: Color,
+    // film coating the glass and metal spheres
+    pub thin_film: Option<ThinFilm>,
+    // material of the large brown sphere
+    pub subsurface: Option<Subsurface>,
+    // roughness of a varnish coating the small diffuse spheres
+    pub clearcoat: Option<f64>,
+    // where the metal spheres are rusty, from 0 for bare metal to 1
+    pub rust: Option<Arc<dyn Texture>>,
+    // height texture and its scale bumping the large metal sphere
+    pub bump: Option<(Arc<dyn Texture>, f64)>,
+    // height texture and its scale displacing the surface of the large
+    // metal sphere
+    pub displacement: Option<(Arc<dyn Texture>, f64)>,
+    // tangent-space normal map of the large metal sphere
+    pub normal_map: Option<Arc<dyn Texture>>,
+    // opacity of the large brown sphere
+    pub cutout: Option<Arc<dyn Texture>>,
+    // cel shading, recolored for each diffuse surface
+    pub toon: Option<Toon>,
+}
+
+impl Default for SceneMaterials {
+    fn default() -> Self {
+        Self {
+            glass: RefractiveIndex::Constant(1.5),
+            glass_absorption: Color::new(0.0, 0.0, 0.0),
+            thin_film: None,
+            subsurface: None,
+            clearcoat: None,
+            rust: None,
+            bump: None,
+            displacement: None,
+            normal_map: None,
+            cutout: None,
+            toon: None,
+        }
+    }
+}
+
+// A film of soapy water whose thickness varies smoothly between half and
+// one and a half times thickness over the surfaces.
+pub fn soap_film(seed: u64, thickness: f64) -> Result<ThinFilm> {
+    let noise = Noise::new(seed, 2.0).with_octaves(3);
+    ThinFilm::new(thickness, 1.33)?.with_thickness_texture(
+        Arc::new(noise),
+        0.5 * thickness,
+        1.5 * thickness,
+    )
+}
+
+// Noise making about half of a surface rusty, in patches.
+pub fn rust_mask(seed: u64) -> Arc<dyn Texture> {
+    let noise = Noise::new(seed.wrapping_add(1), 3.0).with_octaves(4);
+    Arc::new(Remap::new(Arc::new(noise), 0.45, 0.55))
+}
+
+// Noise of small round dents, like hammered metal.
+pub fn hammered(seed: u64) -> Arc<dyn Texture> {
+    Arc::new(Noise::new(seed.wrapping_add(2), 12.0).with_octaves(2))
+}
+
+// Noise cutting round holes with soft edges into about half of a surface.
+pub fn holes(seed: u64) -> Arc<dyn Texture> {
+    let noise = Noise::new(seed.wrapping_add(3), 2.5).with_octaves(2);
+    Arc::new(Remap::new(Arc::new(noise), 0.47, 0.53))
+}
+
+// Brown wax letting light travel about mean_free_path between scattering.
+pub fn wax(mean_free_path: f64) -> Result<Subsurface> {
+    Subsurface::new(
+        Color::new(0.8, 0.45, 0.25),
+        Color::new(1.0, 0.5, 0.25) * mean_free_path,
+    )
+}
+
+// The random spheres scene made of the given materials, generated from the
+// thread random number generator.
+pub fn random_spheres_with(materials: SceneMaterials) -> Result<HittableList> {
+    let SceneMaterials {
+        glass,
+        glass_absorption,
+        thin_film,
+        subsurface,
+        clearcoat,
+        rust,
+        bump,
+        displacement,
+        normal_map,
+        cutout,
+        toon,
+    } = materials;
+    let diffuse_material = |albedo: Color| -> Box<dyn Material> {
+        match &toon {
+            Some(toon) => Box::new(toon.clone().with_color(albedo)),
+            None => Box::new(Lambertian::new(albedo)),
+        }
+    };
+    let glass_material = || {
+        let dielectric =
+            Dielectric::with_refractive_index(glass.clone()).with_absorption(glass_absorption);
+        Box::new(match &thin_film {
+            Some(film) => dielectric.with_thin_film(film.clone()),
+            None => dielectric,
+        })
+    };
+    let metal_material = |albedo: Color, fuzz: f64| -> Box<dyn Material> {
+        let metal = Metal::new(albedo, fuzz);
+        let metal = Box::new(match &thin_film {
+            Some(film) => metal.with_thin_film(film.clone()),
+            None => metal,
+        });
+        match &rust {
+            Some(mask) => {
+                let rust = Box::new(Lambertian::new(Color::new(0.45, 0.2, 0.08)));
+                Box::new(MixMaterial::with_texture(metal, rust, mask.clone()))
+            }
+            None => metal,
+        }
+    };
+
+    let mut world = HittableList::new();
+
+    world.push(Box::new(
+        Sphere::new(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            diffuse_material(Color::new(0.5, 0.5, 0.5)),
+        )
+        .with_material_id(GROUND_ID),
+    ));
+
+    for i in -9..9 {
+        for j in -9..9 {
+            let radius = random_double_range(0.0, 0.3);
+            let choose_mat = random_double();
+            let center = Point3::new(
+                i as f64 + 0.9 * random_double(),
+                radius,
+                j as f64 + 0.9 * random_double(),
+            );
+
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                let (sphere_material, material_id): (Box<dyn Material>, _) = if choose_mat < 0.8 {
+                    let albedo = Color::random() * Color::random();
+                    let diffuse = diffuse_material(albedo);
+                    match clearcoat {
+                        Some(roughness) => (
+                            Box::new(Layered::new(diffuse, 1.5)?.with_roughness(roughness)?),
+                            DIFFUSE_ID,
+                        ),
+                        None => (diffuse, DIFFUSE_ID),
+                    }
+                } else if choose_mat < 0.95 {
+                    let albedo = Color::random();
+                    (metal_material(albedo, 0.5 * random_double()), METAL_ID)
+                } else {
+                    (glass_material(), GLASS_ID)
+                };
+
+                let sphere =
+                    Sphere::new(center, radius, sphere_material).with_material_id(material_id);
+                world.push(Box::new(sphere));
+            }
+        }
+    }
+
+    world.push(Box::new(
+        Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, glass_material()).with_material_id(GLASS_ID),
+    ));
+
+    let brown = Box::new(
+        Sphere::new(
+            Point3::new(-4.0, 1.0, 0.0),
+            1.0,
+            match subsurface {
+                Some(subsurface) => Box::new(subsurface),
+                None => diffuse_material(Color::new(0.4, 0.2, 0.1)),
+            },
+        )
+        .with_material_id(DIFFUSE_ID),
+    );
+    match cutout {
+        Some(opacity) => world.push(Box::new(Cutout::new(brown, opacity))),
+        None => world.push(brown),
+    }
+
+    let metal = metal_material(Color::new(0.7, 0.6, 0.5), 0.0);
+    let metal: Box<dyn Material> = match bump {
+        Some((height, scale)) => Box::new(BumpMap::new(metal, height, scale)),
+        None => metal,
+    };
+    let metal: Box<dyn Material> = match normal_map {
+        Some(map) => Box::new(NormalMap::new(metal, map)),
+        None => metal,
+    };
+    let center = Point3::new(4.0, 1.0, 0.0);
+    match displacement {
+        // dents pushed in from the surface, so the sphere still rests on the
+        // ground
+        Some((height, depth)) => world.push(Box::new(
+            Mesh::sphere(center, 1.0, DISPLACEMENT_SUBDIVISIONS, metal)?
+                .displace(height.as_ref(), -depth)
+                .with_material_id(METAL_ID),
+        )),
+        None => world.push(Box::new(
+            Sphere::new(center, 1.0, metal).with_material_id(METAL_ID),
+        )),
+    }
+
+    Ok(world)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spectrum::SpectralCurve;
+
+    fn description() -> SceneDescription {
+        SceneDescription {
+            seed: 7,
+            image_width: 320,
+            image_height: 180,
+            samples_per_pixel: 64,
+            max_depth: 12,
+            filter: Filter::mitchell(Some(2.5)),
+            sampler: SamplerKind::BlueNoise,
+            lens: Some((
+                vec![
+                    LensElement {
+                        curvature_radius: 29.5,
+                        thickness: 3.76,
+                        ior: 1.67,
+                        aperture_radius: 12.5,
+                    },
+                    LensElement {
+                        curvature_radius: 0.0,
+                        thickness: 4.0,
+                        ior: 0.0,
+                        aperture_radius: 10.0,
+                    },
+                ],
+                35.0,
+            )),
+            spectral: true,
+            glass: RefractiveIndex::Sellmeier {
+                b: [1.04, 0.23, 1.01],
+                c: [0.006, 0.02, 103.56],
+            },
+            glass_absorption: Color::new(0.1, 0.02, 0.3),
+            thin_film: Some(420.0),
+            subsurface: None,
+            clearcoat: Some(0.2),
+            rust: true,
+            bump: Some(0.01),
+            displacement: Some(0.02),
+            normal_map: Some(Image::from_pixels(
+                2,
+                1,
+                vec![Color::new(0.5, 0.5, 1.0), Color::new(0.25, 0.5, 0.75)],
+            )),
+            cutout: false,
+            toon: Some(3),
+        }
+    }
+
+    // a description read back is written out again byte for byte
+    fn assert_round_trips(description: &SceneDescription) {
+        let mut data = Vec::new();
+        description.write_state(&mut data).unwrap();
+        let mut reader = data.as_slice();
+        let read = SceneDescription::read_state(&mut reader).unwrap();
+        assert!(reader.is_empty(), "{} bytes left over", reader.len());
+        let mut rewritten = Vec::new();
+        read.write_state(&mut rewritten).unwrap();
+        assert_eq!(rewritten, data);
+    }
+
+    #[test]
+    fn descriptions_round_trip() {
+        assert_round_trips(&description());
+    }
+
+    #[test]
+    fn descriptions_without_options_round_trip() {
+        let mut description = description();
+        description.lens = None;
+        description.spectral = false;
+        description.glass =
+            RefractiveIndex::Curve(SpectralCurve::new(vec![(400.0, 1.53), (700.0, 1.51)]).unwrap());
+        description.thin_film = None;
+        description.subsurface = Some(0.05);
+        description.clearcoat = None;
+        description.bump = None;
+        description.displacement = None;
+        description.normal_map = None;
+        description.cutout = true;
+        description.toon = None;
+        assert_round_trips(&description);
+    }
+
+    #[test]
+    fn truncated_descriptions_are_an_error() {
+        let mut data = Vec::new();
+        description().write_state(&mut data).unwrap();
+        for len in [0, 4, data.len() / 2, data.len() - 1] {
+            assert!(SceneDescription::read_state(&mut &data[..len]).is_err());
+        }
+    }
+}
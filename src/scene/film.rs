@@ -0,0 +1,353 @@
+use std::io::{self, Read, Write};
+
+use crate::color::Color;
+use crate::encoding::{read_f64, read_u32, write_f64, write_u32};
+use crate::scene::aov::{Aov, AovBuffers, AovSample};
+use crate::scene::filter::Filter;
+use crate::scene::tile::Region;
+
+// largest film read from checkpoints and workers, 16384 x 16384 pixels
+const MAX_STATE_PIXELS: usize = 1 << 28;
+
+// heatmap gradient from few to many samples
+const HEATMAP_COLORS: [(f64, f64, f64); 5] = [
+    (0.0, 0.0, 0.5),
+    (0.0, 0.5, 1.0),
+    (0.0, 1.0, 0.0),
+    (1.0, 1.0, 0.0),
+    (1.0, 0.0, 0.0),
+];
+
+// Accumulates filtered samples. Every sample is splatted onto all pixels
+// within the filter radius, weighted by the filter. A film can cover only
+// part of the image, in which case splats outside of its bounds are dropped.
+#[derive(Clone)]
+pub struct Film {
+    width: u32,
+    height: u32,
+    bounds: Region,
+    filter: Filter,
+    sum: Vec<Color>,
+    weight: Vec<f64>,
+    // number of samples taken inside each pixel
+    samples: Vec<u32>,
+    aovs: Option<AovBuffers>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32, filter: Filter) -> Self {
+        Self::with_bounds(width, height, Region::new(0, 0, width, height), filter)
+    }
+
+    pub fn with_bounds(width: u32, height: u32, bounds: Region, filter: Filter) -> Self {
+        let len = bounds.width() as usize * bounds.height() as usize;
+        Self {
+            width,
+            height,
+            bounds,
+            filter,
+            sum: vec![Color::new(0.0, 0.0, 0.0); len],
+            weight: vec![0.0; len],
+            samples: vec![0; len],
+            aovs: None,
+        }
+    }
+
+    // Also records the given AOVs. They are not part of the serialized
+    // state of a film.
+    pub fn with_aovs(mut self, aovs: &[Aov]) -> Self {
+        if !aovs.is_empty() {
+            self.aovs = Some(AovBuffers::new(aovs, self.sum.len()));
+        }
+        self
+    }
+
+    pub fn aovs(&self) -> &[Aov] {
+        self.aovs.as_ref().map_or(&[], AovBuffers::aovs)
+    }
+
+    // A film for the samples taken inside tile, which also covers the
+    // pixels they are splatted onto.
+    pub fn for_tile(width: u32, height: u32, tile: Region, filter: Filter) -> Self {
+        let padding = filter.radius().ceil() as u32;
+        let bounds = Region::new(
+            tile.x0.saturating_sub(padding),
+            tile.y0.saturating_sub(padding),
+            tile.x1 + padding,
+            tile.y1 + padding,
+        )
+        .intersect(&Region::new(0, 0, width, height));
+        Self::with_bounds(width, height, bounds, filter)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn index(&self, i: u32, j: u32) -> usize {
+        ((j - self.bounds.y0) * self.bounds.width() + (i - self.bounds.x0)) as usize
+    }
+
+    // x and y are continuous raster coordinates, pixel (i, j) covers
+    // [i, i + 1) x [j, j + 1)
+    pub fn add_sample(&mut self, x: f64, y: f64, color: Color) {
+        let pixel_x = (x as u32).min(self.width - 1);
+        let pixel_y = (y as u32).min(self.height - 1);
+        if self.bounds.contains(pixel_x, pixel_y) {
+            let index = self.index(pixel_x, pixel_y);
+            self.samples[index] += 1;
+        }
+
+        let radius = self.filter.radius();
+
+        let x0 = ((x - 0.5 - radius).ceil().max(self.bounds.x0 as f64)) as u32;
+        let y0 = ((y - 0.5 - radius).ceil().max(self.bounds.y0 as f64)) as u32;
+        let x1 = ((x - 0.5 + radius).floor()).min(self.bounds.x1 as f64 - 1.0);
+        let y1 = ((y - 0.5 + radius).floor()).min(self.bounds.y1 as f64 - 1.0);
+        if x1 < 0.0 || y1 < 0.0 {
+            return;
+        }
+
+        for j in y0..=y1 as u32 {
+            for i in x0..=x1 as u32 {
+                let weight = self
+                    .filter
+                    .evaluate(x - (i as f64 + 0.5), y - (j as f64 + 0.5));
+                if weight != 0.0 {
+                    let index = self.index(i, j);
+                    self.sum[index] += weight * color;
+                    self.weight[index] += weight;
+                }
+            }
+        }
+    }
+
+    // Records the AOVs of the sample_index-th sample of the pixel containing
+    // (x, y). Does nothing if the film has no AOVs.
+    pub fn add_aov_sample(&mut self, x: f64, y: f64, sample_index: u32, sample: &AovSample) {
+        let pixel_x = (x as u32).min(self.width - 1);
+        let pixel_y = (y as u32).min(self.height - 1);
+        if !self.bounds.contains(pixel_x, pixel_y) {
+            return;
+        }
+        let index = self.index(pixel_x, pixel_y);
+        if let Some(aovs) = &mut self.aovs {
+            aovs.add(index, sample_index, sample);
+        }
+    }
+
+    pub fn merge(mut self, other: Film) -> Film {
+        self.add(&other);
+        self
+    }
+
+    // adds the samples of other where it overlaps this film
+    pub fn add(&mut self, other: &Film) {
+        for (i, j) in self.bounds.intersect(&other.bounds).pixels() {
+            let index = self.index(i, j);
+            let other_index = other.index(i, j);
+            self.sum[index] += other.sum[other_index];
+            self.weight[index] += other.weight[other_index];
+            self.samples[index] += other.samples[other_index];
+            if let (Some(aovs), Some(other_aovs)) = (&mut self.aovs, &other.aovs) {
+                aovs.add_pixel(index, other_aovs, other_index);
+            }
+        }
+    }
+
+    // Writes the per-pixel sample counts inside region as a PPM image,
+    // scaled from the fewest to the most samples taken by any of its pixels.
+    pub fn write_sample_heatmap(&self, file: &mut impl Write, region: Region) -> io::Result<()> {
+        let region = region.intersect(&self.bounds);
+        writeln!(file, "P3\n{} {}\n255", region.width(), region.height())?;
+
+        let counts: Vec<u32> = region
+            .pixels()
+            .map(|(i, j)| self.samples[self.index(i, j)])
+            .collect();
+        let min = counts.iter().copied().min().unwrap_or(0);
+        let max = counts.iter().copied().max().unwrap_or(0);
+        let range = (max - min).max(1) as f64;
+
+        for count in counts {
+            let (r, g, b) = heatmap_color((count - min) as f64 / range);
+            writeln!(
+                file,
+                "{} {} {}",
+                (255.0 * r) as u8,
+                (255.0 * g) as u8,
+                (255.0 * b) as u8
+            )?;
+        }
+        Ok(())
+    }
+
+    // Serializes the accumulated samples (but not the filter) for checkpoints
+    // and for sending rendered tiles between processes.
+    pub fn write_state(&self, writer: &mut impl Write) -> io::Result<()> {
+        write_u32(writer, self.width)?;
+        write_u32(writer, self.height)?;
+        write_u32(writer, self.bounds.x0)?;
+        write_u32(writer, self.bounds.y0)?;
+        write_u32(writer, self.bounds.x1)?;
+        write_u32(writer, self.bounds.y1)?;
+        for ((sum, &weight), &samples) in self.sum.iter().zip(&self.weight).zip(&self.samples) {
+            write_f64(writer, sum.x())?;
+            write_f64(writer, sum.y())?;
+            write_f64(writer, sum.z())?;
+            write_f64(writer, weight)?;
+            write_u32(writer, samples)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_state(reader: &mut impl Read, filter: Filter) -> io::Result<Self> {
+        let width = read_u32(reader)?;
+        let height = read_u32(reader)?;
+        let bounds = Region::new(
+            read_u32(reader)?,
+            read_u32(reader)?,
+            read_u32(reader)?,
+            read_u32(reader)?,
+        );
+        if bounds.x0 > bounds.x1 || bounds.y0 > bounds.y1 || bounds.x1 > width || bounds.y1 > height
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "film bounds {} exceed the {}x{} image",
+                    bounds, width, height
+                ),
+            ));
+        }
+        let len = (bounds.width() as usize).checked_mul(bounds.height() as usize);
+        if len.is_none_or(|len| len > MAX_STATE_PIXELS) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("film bounds {} are too large", bounds),
+            ));
+        }
+
+        let mut film = Film::with_bounds(width, height, bounds, filter);
+        for index in 0..film.sum.len() {
+            let sum = Color::new(read_f64(reader)?, read_f64(reader)?, read_f64(reader)?);
+            film.sum[index] = sum;
+            film.weight[index] = read_f64(reader)?;
+            film.samples[index] = read_u32(reader)?;
+        }
+        Ok(film)
+    }
+
+    // the values of aov in row-major order over the bounds, if it is recorded
+    pub fn aov_pixels(&self, aov: Aov) -> Option<Vec<Color>> {
+        self.aovs.as_ref()?.pixels(aov)
+    }
+
+    // Resolves the film to pixel colors in row-major order over its bounds.
+    // Filters with negative lobes can leave a pixel with no positive weight,
+    // which is black.
+    pub fn pixels(&self) -> Vec<Color> {
+        self.sum
+            .iter()
+            .zip(&self.weight)
+            .map(|(&sum, &weight)| {
+                if weight > 0.0 {
+                    sum / weight
+                } else {
+                    Color::new(0.0, 0.0, 0.0)
+                }
+            })
+            .collect()
+    }
+}
+
+fn heatmap_color(t: f64) -> (f64, f64, f64) {
+    let scaled = t.clamp(0.0, 1.0) * (HEATMAP_COLORS.len() - 1) as f64;
+    let index = (scaled as usize).min(HEATMAP_COLORS.len() - 2);
+    let f = scaled - index as f64;
+
+    let (r0, g0, b0) = HEATMAP_COLORS[index];
+    let (r1, g1, b1) = HEATMAP_COLORS[index + 1];
+    (r0 + (r1 - r0) * f, g0 + (g1 - g0) * f, b0 + (b1 - b0) * f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(width: u32, height: u32, bounds: [u32; 4]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for value in [width, height].into_iter().chain(bounds) {
+            write_u32(&mut data, value).unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let filter = Filter::gaussian(None);
+        let mut film = Film::for_tile(10, 8, Region::new(2, 2, 6, 5), filter);
+        film.add_sample(3.2, 3.7, Color::new(1.0, 0.5, 0.25));
+        let mut data = Vec::new();
+        film.write_state(&mut data).unwrap();
+
+        let read = Film::read_state(&mut data.as_slice(), filter).unwrap();
+        assert_eq!((read.width(), read.height()), (10, 8));
+        for (a, b) in read.pixels().iter().zip(film.pixels()) {
+            assert_eq!((a.x(), a.y(), a.z()), (b.x(), b.y(), b.z()));
+        }
+    }
+
+    #[test]
+    fn huge_films_are_rejected_before_allocating() {
+        let data = header(u32::MAX, u32::MAX, [0, 0, u32::MAX, u32::MAX]);
+        let read = Film::read_state(&mut data.as_slice(), Filter::box_filter(None));
+        assert_eq!(read.err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn inverted_bounds_are_rejected() {
+        let data = header(8, 8, [5, 0, 2, 8]);
+        let read = Film::read_state(&mut data.as_slice(), Filter::box_filter(None));
+        assert_eq!(read.err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_state_is_an_error() {
+        let data = header(4, 4, [0, 0, 4, 4]);
+        let read = Film::read_state(&mut data.as_slice(), Filter::box_filter(None));
+        assert!(read.is_err());
+    }
+
+    #[test]
+    fn heatmaps_cover_the_region() {
+        let mut film = Film::new(6, 4, Filter::box_filter(None));
+        for _ in 0..2 {
+            film.add_sample(2.5, 1.5, Color::new(1.0, 1.0, 1.0));
+        }
+        film.add_sample(3.5, 1.5, Color::new(1.0, 1.0, 1.0));
+        let mut data = Vec::new();
+        film.write_sample_heatmap(&mut data, Region::new(2, 1, 4, 2))
+            .unwrap();
+
+        // scaled from the pixel with one sample to the one with two, leaving
+        // out the pixels with none outside of the region
+        let heatmap = String::from_utf8(data).unwrap();
+        let lines: Vec<&str> = heatmap.lines().collect();
+        assert_eq!(lines[1], "2 1");
+        let color = |t| {
+            let (r, g, b) = heatmap_color(t);
+            format!(
+                "{} {} {}",
+                (255.0 * r) as u8,
+                (255.0 * g) as u8,
+                (255.0 * b) as u8
+            )
+        };
+        assert_eq!(lines[3..], [color(1.0), color(0.0)]);
+    }
+}
@@ -0,0 +1,150 @@
+use std::f64::consts::PI;
+use std::io::{self, Read, Write};
+
+use crate::encoding::{read_f64, read_u32, write_f64, write_u32};
+
+// Pixel reconstruction filters. Offsets are in pixels from the pixel center
+// and each filter is zero outside of its radius.
+#[derive(Clone, Copy, Debug)]
+pub enum Filter {
+    Box { radius: f64 },
+    Tent { radius: f64 },
+    Gaussian { radius: f64, sigma: f64 },
+    Mitchell { radius: f64, b: f64, c: f64 },
+    Lanczos { radius: f64 },
+}
+
+impl Filter {
+    pub fn box_filter(radius: Option<f64>) -> Self {
+        Filter::Box {
+            radius: radius.unwrap_or(0.5),
+        }
+    }
+
+    pub fn tent(radius: Option<f64>) -> Self {
+        Filter::Tent {
+            radius: radius.unwrap_or(1.0),
+        }
+    }
+
+    pub fn gaussian(radius: Option<f64>) -> Self {
+        let radius = radius.unwrap_or(1.5);
+        Filter::Gaussian {
+            radius,
+            sigma: radius / 3.0,
+        }
+    }
+
+    // B = C = 1/3 as recommended by Mitchell and Netravali
+    pub fn mitchell(radius: Option<f64>) -> Self {
+        Filter::Mitchell {
+            radius: radius.unwrap_or(2.0),
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        }
+    }
+
+    pub fn lanczos(radius: Option<f64>) -> Self {
+        Filter::Lanczos {
+            radius: radius.unwrap_or(3.0),
+        }
+    }
+
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius } => radius,
+        }
+    }
+
+    pub fn write_state(&self, writer: &mut impl Write) -> io::Result<()> {
+        let (tag, parameters) = match *self {
+            Filter::Box { radius } => (0, [radius, 0.0, 0.0]),
+            Filter::Tent { radius } => (1, [radius, 0.0, 0.0]),
+            Filter::Gaussian { radius, sigma } => (2, [radius, sigma, 0.0]),
+            Filter::Mitchell { radius, b, c } => (3, [radius, b, c]),
+            Filter::Lanczos { radius } => (4, [radius, 0.0, 0.0]),
+        };
+        write_u32(writer, tag)?;
+        for parameter in parameters {
+            write_f64(writer, parameter)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_state(reader: &mut impl Read) -> io::Result<Self> {
+        let tag = read_u32(reader)?;
+        let [radius, p1, p2] = [read_f64(reader)?, read_f64(reader)?, read_f64(reader)?];
+        match tag {
+            0 => Ok(Filter::Box { radius }),
+            1 => Ok(Filter::Tent { radius }),
+            2 => Ok(Filter::Gaussian { radius, sigma: p1 }),
+            3 => Ok(Filter::Mitchell {
+                radius,
+                b: p1,
+                c: p2,
+            }),
+            4 => Ok(Filter::Lanczos { radius }),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown filter {}", tag),
+            )),
+        }
+    }
+
+    pub fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        let radius = self.radius();
+        if dx.abs() > radius || dy.abs() > radius {
+            return 0.0;
+        }
+
+        match *self {
+            Filter::Box { .. } => 1.0,
+            Filter::Tent { radius } => (radius - dx.abs()) * (radius - dy.abs()),
+            Filter::Gaussian { radius, sigma } => {
+                gaussian(dx, radius, sigma) * gaussian(dy, radius, sigma)
+            }
+            Filter::Mitchell { radius, b, c } => {
+                mitchell(2.0 * dx / radius, b, c) * mitchell(2.0 * dy / radius, b, c)
+            }
+            Filter::Lanczos { radius } => lanczos(dx, radius) * lanczos(dy, radius),
+        }
+    }
+}
+
+// gaussian shifted down so it reaches zero at the radius
+fn gaussian(x: f64, radius: f64, sigma: f64) -> f64 {
+    let g = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+    (g(x) - g(radius)).max(0.0)
+}
+
+// x is in [-2, 2]
+fn mitchell(x: f64, b: f64, c: f64) -> f64 {
+    let x = x.abs();
+    let value = if x > 1.0 {
+        (-b - 6.0 * c) * x.powi(3)
+            + (6.0 * b + 30.0 * c) * x.powi(2)
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c)
+    } else {
+        (12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+            + (-18.0 + 12.0 * b + 6.0 * c) * x.powi(2)
+            + (6.0 - 2.0 * b)
+    };
+    value / 6.0
+}
+
+fn lanczos(x: f64, radius: f64) -> f64 {
+    sinc(x) * sinc(x / radius)
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
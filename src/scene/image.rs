@@ -0,0 +1,406 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::color::{write_color, Color};
+use crate::display::DisplayTransform;
+use crate::encoding::{read_f64, read_u32, write_f64, write_u32};
+use crate::error::{Error, Result};
+use crate::exr::{write_exr, ExrChannel};
+use crate::scene::aov::Aov;
+
+// largest image read from another process, 16384 x 16384 pixels
+const MAX_STATE_PIXELS: usize = 1 << 28;
+
+// File formats images can be written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Ppm,
+    // linear 32-bit float OpenEXR, which can also hold AOV layers
+    Exr,
+}
+
+impl ImageFormat {
+    // Picks the format from the file extension, files without one are
+    // written as PPM.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+        match extension.as_deref() {
+            None | Some("ppm") => Ok(ImageFormat::Ppm),
+            Some("exr") => Ok(ImageFormat::Exr),
+            Some(other) => Err(Error::UnsupportedFormat(format!(
+                "cannot write .{} images, use .ppm or .exr",
+                other
+            ))),
+        }
+    }
+}
+
+// A rendered image in linear color, with its pixels in row-major order.
+#[derive(Clone)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::new(0.0, 0.0, 0.0); width as usize * height as usize],
+        }
+    }
+
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width as usize * height as usize);
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        self.pixels[y as usize * self.width as usize + x as usize]
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
+        self.pixels[y as usize * self.width as usize + x as usize] = color;
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    // Replaces the file at path with the image, in the format given by the
+    // extension, encoding 8-bit formats as sRGB.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        save_with_aovs(path, self, &[], &DisplayTransform::default())
+    }
+
+    // Reads an ASCII (P3) or binary (P6) PPM, keeping the encoded values
+    // scaled to [0, 1].
+    pub fn load_ppm(path: &Path) -> Result<Self> {
+        Self::read_ppm(&fs::read(path).map_err(|err| Error::file(path, err))?)
+    }
+
+    pub fn read_ppm(data: &[u8]) -> Result<Self> {
+        let mut tokens = PpmTokens { data, position: 0 };
+        let magic = tokens.next_token();
+        let binary = match magic {
+            Some(b"P3") => false,
+            Some(b"P6") => true,
+            _ => {
+                return Err(Error::UnsupportedFormat(
+                    "only P3 and P6 PPM images can be read".to_string(),
+                ))
+            }
+        };
+        let width = tokens.next_number()?;
+        let height = tokens.next_number()?;
+        let max_value = tokens.next_number()?;
+        if width == 0 || height == 0 || !(1..=65535).contains(&max_value) {
+            return Err(Error::UnsupportedFormat(format!(
+                "PPM of {}x{} pixels with maximum value {}",
+                width, height, max_value
+            )));
+        }
+
+        let too_large =
+            || Error::UnsupportedFormat(format!("PPM of {}x{} pixels is too large", width, height));
+        let count = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .ok_or_else(too_large)?;
+        let values: Vec<u32> = if binary {
+            // a single whitespace byte separates the header from the samples
+            let start = tokens.position + 1;
+            let bytes_per_value = if max_value < 256 { 1 } else { 2 };
+            let end = count
+                .checked_mul(bytes_per_value)
+                .and_then(|size| size.checked_add(start))
+                .ok_or_else(too_large)?;
+            let samples = data
+                .get(start..end)
+                .ok_or_else(|| Error::UnsupportedFormat("truncated PPM".to_string()))?;
+            samples
+                .chunks(bytes_per_value)
+                .map(|bytes| bytes.iter().fold(0, |value, &b| value << 8 | b as u32))
+                .collect()
+        } else {
+            (0..count)
+                .map(|_| tokens.next_number())
+                .collect::<Result<_>>()?
+        };
+
+        let scale = 1.0 / max_value as f64;
+        let pixels = values
+            .chunks(3)
+            .map(|rgb| {
+                Color::new(
+                    rgb[0] as f64 * scale,
+                    rgb[1] as f64 * scale,
+                    rgb[2] as f64 * scale,
+                )
+            })
+            .collect();
+        Ok(Self::from_pixels(width, height, pixels))
+    }
+
+    // Writes the size and the linear pixels, for sending the image to
+    // another process.
+    pub fn write_state(&self, writer: &mut impl Write) -> io::Result<()> {
+        write_u32(writer, self.width)?;
+        write_u32(writer, self.height)?;
+        for pixel in &self.pixels {
+            for channel in [pixel.x(), pixel.y(), pixel.z()] {
+                write_f64(writer, channel)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_state(reader: &mut impl Read) -> io::Result<Self> {
+        let width = read_u32(reader)?;
+        let height = read_u32(reader)?;
+        let len = (width as usize).checked_mul(height as usize);
+        if len.is_none_or(|len| len > MAX_STATE_PIXELS) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("image of {}x{} pixels is too large", width, height),
+            ));
+        }
+        let pixels = (0..width as usize * height as usize)
+            .map(|_| {
+                Ok(Color::new(
+                    read_f64(reader)?,
+                    read_f64(reader)?,
+                    read_f64(reader)?,
+                ))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self::from_pixels(width, height, pixels))
+    }
+
+    // Writes the image as an ASCII PPM encoded with display.
+    pub fn write_ppm(&self, writer: &mut impl Write, display: &DisplayTransform) -> io::Result<()> {
+        writeln!(writer, "P3\n{} {}\n255", self.width, self.height)?;
+        for &color in &self.pixels {
+            write_color(writer, color, display)?;
+        }
+        Ok(())
+    }
+
+    // the color channels of the image, for writing it as an EXR layer
+    fn exr_channels(&self, layer: Option<&str>, names: &[&str]) -> Vec<ExrChannel> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(channel, name)| ExrChannel {
+                name: match layer {
+                    Some(layer) => format!("{}.{}", layer, name),
+                    None => name.to_string(),
+                },
+                values: self.pixels.iter().map(|p| p[channel] as f32).collect(),
+            })
+            .collect()
+    }
+}
+
+// Whitespace separated tokens of a PPM header, skipping comments.
+struct PpmTokens<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PpmTokens<'a> {
+    fn next_token(&mut self) -> Option<&'a [u8]> {
+        loop {
+            match self.data.get(self.position)? {
+                b'#' => {
+                    while self.data.get(self.position).is_some_and(|&b| b != b'\n') {
+                        self.position += 1;
+                    }
+                }
+                b if b.is_ascii_whitespace() => self.position += 1,
+                _ => break,
+            }
+        }
+        let start = self.position;
+        while self
+            .data
+            .get(self.position)
+            .is_some_and(|b| !b.is_ascii_whitespace())
+        {
+            self.position += 1;
+        }
+        Some(&self.data[start..self.position])
+    }
+
+    fn next_number(&mut self) -> Result<u32> {
+        let token = self
+            .next_token()
+            .ok_or_else(|| Error::UnsupportedFormat("truncated PPM".to_string()))?;
+        std::str::from_utf8(token)
+            .ok()
+            .and_then(|token| token.parse().ok())
+            .ok_or_else(|| {
+                Error::UnsupportedFormat(format!(
+                    "invalid PPM number {}",
+                    String::from_utf8_lossy(token)
+                ))
+            })
+    }
+}
+
+// Replaces the file at path with image, and writes the AOV images of the
+// same size along with it. EXR files hold the AOVs as layers, for other
+// formats they are written as previews next to the image, with the name of
+// the AOV added to the file name (image.albedo.ppm for image.ppm). PPMs of
+// the image and the light AOVs are encoded with display, EXR files stay
+// linear sRGB.
+pub fn save_with_aovs(
+    path: &Path,
+    image: &Image,
+    aovs: &[(Aov, Image)],
+    display: &DisplayTransform,
+) -> Result<()> {
+    match ImageFormat::from_path(path)? {
+        ImageFormat::Ppm => {
+            write_atomically(path, |writer| image.write_ppm(writer, display))?;
+            for (aov, aov_image) in aovs {
+                let preview = Image::from_pixels(
+                    aov_image.width,
+                    aov_image.height,
+                    aov.preview(&aov_image.pixels),
+                );
+                let preview_display = if aov.is_light() {
+                    *display
+                } else {
+                    DisplayTransform::default()
+                };
+                write_atomically(&sibling_path(path, aov.name()), |writer| {
+                    preview.write_ppm(writer, &preview_display)
+                })?;
+            }
+        }
+        ImageFormat::Exr => {
+            let mut channels = image.exr_channels(None, &["R", "G", "B"]);
+            for (aov, aov_image) in aovs {
+                channels.extend(aov_image.exr_channels(Some(aov.name()), aov.channel_names()));
+            }
+            write_atomically(path, |writer| {
+                write_exr(writer, image.width, image.height, channels)
+            })?;
+        }
+    }
+    Ok(())
+}
+
+// path with name added before the extension, image.ppm becomes
+// image.<name>.ppm
+pub fn sibling_path(path: &Path, name: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}.{}", stem, name);
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+// Writes to a temporary file first so readers never see a partially
+// written image.
+fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+
+    let result = File::create(&temporary).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&temporary, path)
+    });
+    result.map_err(|err| Error::file(path, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channels(image: &Image) -> Vec<f64> {
+        image
+            .pixels()
+            .iter()
+            .flat_map(|p| [p.x(), p.y(), p.z()])
+            .collect()
+    }
+
+    #[test]
+    fn ascii_ppms_are_read_with_comments() {
+        let data = b"P3\n# a comment\n2 1\n# another\n255\n255 0 51\n0 255 102\n";
+        let image = Image::read_ppm(data).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_eq!(channels(&image), vec![1.0, 0.0, 0.2, 0.0, 1.0, 0.4]);
+    }
+
+    #[test]
+    fn binary_ppms_are_read_in_8_and_16_bits() {
+        let image = Image::read_ppm(b"P6 1 1 255\n\xff\x00\x33").unwrap();
+        assert_eq!(channels(&image), vec![1.0, 0.0, 0.2]);
+        let image = Image::read_ppm(b"P6 1 1 1000\n\x03\xe8\x00\x00\x01\xf4").unwrap();
+        assert_eq!(channels(&image), vec![1.0, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn invalid_ppms_are_an_error() {
+        for data in [
+            &b"P5 1 1 255\n\x00"[..],
+            b"P3 0 1 255\n",
+            b"P3 1 1 70000\n0 0 0",
+            b"P3 1 1 255\n0 0",
+            b"P3 1 1 255\n0 x 0",
+            b"P6 2 1 255\n\x00\x00\x00\x00\x00",
+            b"P6 4294967295 4294967295 65535\n\x00",
+            b"P3 4294967295 4294967295 255\n0 0 0",
+        ] {
+            assert!(Image::read_ppm(data).is_err());
+        }
+    }
+
+    #[test]
+    fn images_round_trip_through_their_state() {
+        let mut image = Image::new(3, 2);
+        image.set_pixel(2, 1, Color::new(0.25, -1.5, 1e6));
+        let mut data = Vec::new();
+        image.write_state(&mut data).unwrap();
+        let read = Image::read_state(&mut data.as_slice()).unwrap();
+        assert_eq!((read.width(), read.height()), (3, 2));
+        assert_eq!(channels(&read), channels(&image));
+    }
+
+    #[test]
+    fn oversized_image_states_are_rejected() {
+        let mut data = Vec::new();
+        write_u32(&mut data, 1 << 16).unwrap();
+        write_u32(&mut data, 1 << 16).unwrap();
+        let read = Image::read_state(&mut data.as_slice());
+        assert_eq!(read.err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,529 @@
+use std::fs;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::point::Point3;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+// number of radial bins used for the exit pupil bounds
+const PUPIL_BINS: usize = 64;
+// film positions and rear element grid resolution used per bin
+const PUPIL_FILM_SAMPLES: usize = 4;
+const PUPIL_GRID: usize = 32;
+
+// A single refracting surface (or the aperture stop) of a lens prescription.
+// Units are millimeters, ordered front (object side) to rear (film side).
+#[derive(Clone, Debug)]
+pub struct LensElement {
+    pub curvature_radius: f64,
+    pub thickness: f64,
+    pub ior: f64,
+    pub aperture_radius: f64,
+}
+
+impl LensElement {
+    pub fn is_stop(&self) -> bool {
+        self.curvature_radius == 0.0
+    }
+
+    // an index of refraction of 0 is used in prescriptions to denote air
+    fn medium_ior(&self) -> f64 {
+        if self.ior == 0.0 {
+            1.0
+        } else {
+            self.ior
+        }
+    }
+}
+
+// Parses a lens prescription in the tabular format used by lens patents:
+// one surface per line with radius, thickness, index of refraction and
+// aperture diameter. Blank lines and lines starting with '#' are ignored.
+pub fn parse_prescription(source: &str) -> Result<Vec<LensElement>> {
+    let mut elements = Vec::new();
+
+    for (line_number, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let values = line
+            .split_whitespace()
+            .map(|value| value.parse::<f64>())
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|err| Error::SceneParse(format!("line {}: {}", line_number + 1, err)))?;
+
+        if values.len() != 4 {
+            return Err(Error::SceneParse(format!(
+                "line {}: expected 4 columns (radius thickness ior aperture), found {}",
+                line_number + 1,
+                values.len()
+            )));
+        }
+
+        elements.push(LensElement {
+            curvature_radius: values[0],
+            thickness: values[1],
+            ior: values[2],
+            aperture_radius: values[3] / 2.0,
+        });
+    }
+
+    if elements.is_empty() {
+        return Err(Error::SceneParse(
+            "lens prescription has no elements".to_string(),
+        ));
+    }
+
+    Ok(elements)
+}
+
+pub fn load_prescription(path: &Path) -> Result<Vec<LensElement>> {
+    parse_prescription(&fs::read_to_string(path).map_err(|err| Error::file(path, err))?)
+}
+
+#[derive(Clone, Copy, Debug)]
+struct PupilBounds {
+    min_x: f64,
+    min_y: f64,
+    max_x: f64,
+    max_y: f64,
+}
+
+impl PupilBounds {
+    fn empty() -> Self {
+        Self {
+            min_x: f64::INFINITY,
+            min_y: f64::INFINITY,
+            max_x: f64::NEG_INFINITY,
+            max_y: f64::NEG_INFINITY,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.min_x > self.max_x || self.min_y > self.max_y
+    }
+
+    fn include(&mut self, x: f64, y: f64) {
+        self.min_x = self.min_x.min(x);
+        self.min_y = self.min_y.min(y);
+        self.max_x = self.max_x.max(x);
+        self.max_y = self.max_y.max(y);
+    }
+
+    fn expand(&mut self, delta: f64) {
+        self.min_x -= delta;
+        self.min_y -= delta;
+        self.max_x += delta;
+        self.max_y += delta;
+    }
+
+    fn area(&self) -> f64 {
+        if self.is_empty() {
+            0.0
+        } else {
+            (self.max_x - self.min_x) * (self.max_y - self.min_y)
+        }
+    }
+}
+
+// A stack of lens elements in front of the film. The lens space has the film
+// centered at the origin and the optical axis along +z towards the scene.
+pub struct LensSystem {
+    elements: Vec<LensElement>,
+    // z of each surface vertex, measured from the film
+    vertex_z: Vec<f64>,
+    film_width: f64,
+    film_height: f64,
+    exit_pupils: Vec<PupilBounds>,
+    axial_pupil_area: f64,
+}
+
+impl LensSystem {
+    pub fn new(
+        elements: Vec<LensElement>,
+        film_diagonal: f64,
+        aspect_ratio: f64,
+        focus_distance: f64,
+    ) -> Result<Self> {
+        if elements.is_empty() {
+            return Err(invalid_lens(
+                "lens prescription has no elements".to_string(),
+            ));
+        }
+
+        let film_height = film_diagonal / (1.0 + aspect_ratio * aspect_ratio).sqrt();
+        let film_width = film_height * aspect_ratio;
+
+        let mut lens = Self {
+            elements,
+            vertex_z: Vec::new(),
+            film_width,
+            film_height,
+            exit_pupils: Vec::new(),
+            axial_pupil_area: 0.0,
+        };
+
+        let rear_distance = lens.elements[lens.elements.len() - 1].thickness;
+        lens.place_elements(rear_distance);
+        lens.focus(focus_distance)?;
+        lens.compute_exit_pupils();
+
+        lens.axial_pupil_area = lens.exit_pupils[0].area();
+        if lens.axial_pupil_area == 0.0 {
+            return Err(invalid_lens(
+                "no light reaches the center of the film through the lens".to_string(),
+            ));
+        }
+
+        Ok(lens)
+    }
+
+    // Returns a ray leaving the front element for a point on the film, given
+    // in normalized [0, 1] image coordinates, and its radiometric weight.
+    // pupil_sample selects the point on the exit pupil.
+    pub fn generate_ray(&self, s: f64, t: f64, pupil_sample: (f64, f64)) -> Option<(Ray, f64)> {
+        // the lens inverts the image, so the film is sampled flipped
+        let film_x = (0.5 - s) * self.film_width;
+        let film_y = (t - 0.5) * self.film_height;
+        let film_point = Point3::new(film_x, film_y, 0.0);
+
+        let (pupil_point, pupil_area) = self.sample_exit_pupil(film_x, film_y, pupil_sample)?;
+        let direction = (pupil_point - film_point).unit_vector();
+
+        let ray = self.trace_from_film(&Ray::new(film_point, direction))?;
+
+        // cos^4 falloff and pupil size, normalized so the film center has weight 1
+        let cos_theta = direction.z();
+        let weight = cos_theta.powi(4) * pupil_area / self.axial_pupil_area;
+        Some((ray, weight))
+    }
+
+    fn rear_z(&self) -> f64 {
+        self.vertex_z[self.vertex_z.len() - 1]
+    }
+
+    fn rear_aperture_radius(&self) -> f64 {
+        self.elements[self.elements.len() - 1].aperture_radius
+    }
+
+    fn place_elements(&mut self, rear_distance: f64) {
+        let n = self.elements.len();
+        self.vertex_z = vec![0.0; n];
+        self.vertex_z[n - 1] = rear_distance;
+        for i in (0..n - 1).rev() {
+            self.vertex_z[i] = self.vertex_z[i + 1] + self.elements[i].thickness;
+        }
+    }
+
+    // Moves the element stack so a point on the axis at focus_distance from
+    // the film is imaged onto the film. The image position depends slightly
+    // on the object distance measured from the film, so this is iterated.
+    fn focus(&mut self, focus_distance: f64) -> Result<()> {
+        for _ in 0..8 {
+            let object = Point3::new(0.0, 0.0, focus_distance);
+            if object.z() <= self.vertex_z[0] {
+                return Err(invalid_lens(
+                    "focus distance lies inside the lens system".to_string(),
+                ));
+            }
+
+            // a paraxial ray through a small height on the front element
+            let height = 0.01 * self.elements[0].aperture_radius;
+            let target = Point3::new(height, 0.0, self.vertex_z[0]);
+            let exiting = self
+                .trace_from_scene(&Ray::new(object, (target - object).unit_vector()))
+                .ok_or_else(|| invalid_lens("paraxial focusing ray is blocked".to_string()))?;
+
+            let t = -exiting.origin().x() / exiting.direction().x();
+            if !t.is_finite() || t <= 0.0 {
+                return Err(invalid_lens(
+                    "lens system does not form a real image".to_string(),
+                ));
+            }
+            let image_z = exiting.at(t).z();
+
+            let rear_distance = self.rear_z() - image_z;
+            if rear_distance <= 0.0 {
+                return Err(invalid_lens(
+                    "focus distance too close for the lens system".to_string(),
+                ));
+            }
+            self.place_elements(rear_distance);
+
+            if image_z.abs() < 1e-6 {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn compute_exit_pupils(&mut self) {
+        let film_radius = 0.5 * (self.film_width.powi(2) + self.film_height.powi(2)).sqrt();
+        let rear_radius = self.rear_aperture_radius();
+        let rear_z = self.rear_z();
+
+        // the search region is somewhat larger than the rear element
+        let extent = 1.5 * rear_radius;
+        let cell = 2.0 * extent / PUPIL_GRID as f64;
+
+        self.exit_pupils = (0..PUPIL_BINS)
+            .map(|bin| {
+                let r0 = film_radius * bin as f64 / PUPIL_BINS as f64;
+                let r1 = film_radius * (bin + 1) as f64 / PUPIL_BINS as f64;
+
+                let mut bounds = PupilBounds::empty();
+                for f in 0..PUPIL_FILM_SAMPLES {
+                    let film_x = r0 + (r1 - r0) * f as f64 / (PUPIL_FILM_SAMPLES - 1) as f64;
+                    let film_point = Point3::new(film_x, 0.0, 0.0);
+
+                    for gy in 0..PUPIL_GRID {
+                        for gx in 0..PUPIL_GRID {
+                            let x = -extent + (gx as f64 + 0.5) * cell;
+                            let y = -extent + (gy as f64 + 0.5) * cell;
+                            if x * x + y * y > rear_radius * rear_radius {
+                                continue;
+                            }
+
+                            let direction = (Point3::new(x, y, rear_z) - film_point).unit_vector();
+                            if self
+                                .trace_from_film(&Ray::new(film_point, direction))
+                                .is_some()
+                            {
+                                bounds.include(x, y);
+                            }
+                        }
+                    }
+                }
+
+                if !bounds.is_empty() {
+                    bounds.expand(cell);
+                }
+                bounds
+            })
+            .collect();
+    }
+
+    // Samples a point on the rear element plane inside the exit pupil bounds
+    // for a film point, returning the point and the area it was chosen from.
+    fn sample_exit_pupil(&self, film_x: f64, film_y: f64, u: (f64, f64)) -> Option<(Point3, f64)> {
+        let film_radius = 0.5 * (self.film_width.powi(2) + self.film_height.powi(2)).sqrt();
+        let r = (film_x * film_x + film_y * film_y).sqrt();
+        let bin = ((r / film_radius * PUPIL_BINS as f64) as usize).min(PUPIL_BINS - 1);
+
+        let bounds = self.exit_pupils[bin];
+        if bounds.is_empty() {
+            return None;
+        }
+
+        let x = bounds.min_x + (bounds.max_x - bounds.min_x) * u.0;
+        let y = bounds.min_y + (bounds.max_y - bounds.min_y) * u.1;
+
+        // bounds were computed along +x, rotate them to the film point
+        let (sin_phi, cos_phi) = if r > 0.0 {
+            (film_y / r, film_x / r)
+        } else {
+            (0.0, 1.0)
+        };
+        let point = Point3::new(
+            cos_phi * x - sin_phi * y,
+            sin_phi * x + cos_phi * y,
+            self.rear_z(),
+        );
+
+        Some((point, bounds.area()))
+    }
+
+    fn trace_from_film(&self, ray: &Ray) -> Option<Ray> {
+        let mut origin = ray.origin();
+        let mut direction = ray.direction();
+
+        for i in (0..self.elements.len()).rev() {
+            let (point, normal) = self.intersect_element(i, origin, direction)?;
+            origin = point;
+
+            if let Some(normal) = normal {
+                let eta_i = self.elements[i].medium_ior();
+                let eta_t = if i > 0 {
+                    self.elements[i - 1].medium_ior()
+                } else {
+                    1.0
+                };
+                direction = refract(direction, normal, eta_i / eta_t)?;
+            }
+        }
+
+        Some(Ray::new(origin, direction))
+    }
+
+    fn trace_from_scene(&self, ray: &Ray) -> Option<Ray> {
+        let mut origin = ray.origin();
+        let mut direction = ray.direction();
+
+        for i in 0..self.elements.len() {
+            let (point, normal) = self.intersect_element(i, origin, direction)?;
+            origin = point;
+
+            if let Some(normal) = normal {
+                let eta_i = if i > 0 {
+                    self.elements[i - 1].medium_ior()
+                } else {
+                    1.0
+                };
+                let eta_t = self.elements[i].medium_ior();
+                direction = refract(direction, normal, eta_i / eta_t)?;
+            }
+        }
+
+        Some(Ray::new(origin, direction))
+    }
+
+    // Intersects a surface, returning the hit point and, for refracting
+    // surfaces, the normal facing against the incoming direction.
+    fn intersect_element(
+        &self,
+        i: usize,
+        origin: Point3,
+        direction: Vec3,
+    ) -> Option<(Point3, Option<Vec3>)> {
+        let element = &self.elements[i];
+        let vertex_z = self.vertex_z[i];
+
+        let (point, normal) = if element.is_stop() {
+            let t = (vertex_z - origin.z()) / direction.z();
+            if !t.is_finite() || t <= 0.0 {
+                return None;
+            }
+            (origin + t * direction, None)
+        } else {
+            // positive radii have their center of curvature towards the film
+            let radius = element.curvature_radius;
+            let center = Point3::new(0.0, 0.0, vertex_z - radius);
+
+            let oc = origin - center;
+            let half_b = oc.dot(direction);
+            let c = oc.length_squared() - radius * radius;
+            let discriminant = half_b * half_b - c;
+            if discriminant < 0.0 {
+                return None;
+            }
+
+            // only the cap of the sphere around the vertex is part of the lens
+            let sqrtd = discriminant.sqrt();
+            let point = [-half_b - sqrtd, -half_b + sqrtd]
+                .into_iter()
+                .filter(|&t| t > 1e-9)
+                .map(|t| origin + t * direction)
+                .find(|p| (p.z() - center.z()) * (vertex_z - center.z()) > 0.0)?;
+
+            let mut normal = (point - center) / radius.abs();
+            if normal.dot(direction) > 0.0 {
+                normal = -normal;
+            }
+            (point, Some(normal))
+        };
+
+        let r2 = point.x() * point.x() + point.y() * point.y();
+        if r2 > element.aperture_radius * element.aperture_radius {
+            return None;
+        }
+
+        Some((point, normal))
+    }
+}
+
+// Refracts a unit direction through a surface whose normal faces against it,
+// returning None on total internal reflection.
+fn refract(direction: Vec3, normal: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = -direction.dot(normal);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i).max(0.0);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some((eta * direction + (eta * cos_i - cos_t) * normal).unit_vector())
+}
+
+fn invalid_lens(message: String) -> Error {
+    Error::InvalidParameter(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn double_gauss() -> LensSystem {
+        let elements = parse_prescription(include_str!("../../lenses/dgauss.50mm.dat")).unwrap();
+        LensSystem::new(elements, 35.0, 1.5, 10_000.0).unwrap()
+    }
+
+    #[test]
+    fn pupil_samples_pass_inside_the_apertures() {
+        let lens = double_gauss();
+        let front_radius = lens.elements[0].aperture_radius;
+        let n = 32;
+        for (s, t) in [(0.5, 0.5), (0.8, 0.3), (0.05, 0.95)] {
+            let mut origins = Vec::new();
+            for i in 0..n {
+                for j in 0..n {
+                    let u = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                    let Some((ray, weight)) = lens.generate_ray(s, t, u) else {
+                        continue;
+                    };
+                    assert!(weight > 0.0 && weight <= 1.0 + 1e-9, "weight {}", weight);
+                    origins.push(ray.origin());
+                }
+            }
+            for origin in &origins {
+                assert!(origin.x().hypot(origin.y()) <= front_radius + 1e-9);
+            }
+            // the pupil bounds are tight enough that most samples get through
+            assert!(origins.len() > n * n / 2, "{} of {}", origins.len(), n * n);
+        }
+    }
+
+    #[test]
+    fn pupil_samples_are_centered_on_the_axis() {
+        let lens = double_gauss();
+        let n = 32;
+        let origins: Vec<Point3> = (0..n * n)
+            .filter_map(|k| {
+                let u = ((k % n) as f64 + 0.5, (k / n) as f64 + 0.5);
+                lens.generate_ray(0.5, 0.5, (u.0 / n as f64, u.1 / n as f64))
+            })
+            .map(|(ray, _)| ray.origin())
+            .collect();
+        let count = origins.len() as f64;
+        let mean_x = origins.iter().map(|origin| origin.x()).sum::<f64>() / count;
+        let mean_y = origins.iter().map(|origin| origin.y()).sum::<f64>() / count;
+        assert!(mean_x.abs() < 1e-6 && mean_y.abs() < 1e-6);
+        // a disk: a quarter of the rays within half of the radius
+        let radius = origins
+            .iter()
+            .map(|origin| origin.x().hypot(origin.y()))
+            .fold(0.0, f64::max);
+        let inner = origins
+            .iter()
+            .filter(|origin| origin.x().hypot(origin.y()) < 0.5 * radius)
+            .count() as f64;
+        assert!((inner / count - 0.25).abs() < 0.05, "{}", inner / count);
+    }
+
+    #[test]
+    fn malformed_prescriptions_are_rejected() {
+        for source in [
+            "",
+            "# only a comment",
+            "29.5 3.76 1.67",
+            "29.5 3.76 1.67 glass",
+        ] {
+            assert!(matches!(
+                parse_prescription(source),
+                Err(Error::SceneParse(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use crate::hittable::HitRecord;
+use crate::material::{Material, Medium, RefractiveIndex};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+
+// The dielectric media a path is inside of, for nested dielectrics (ice in
+// water in glass) following Schmidt and Budge's interface tracking. The
+// medium with the highest priority, the latest entered among equals, is the
+// one the path travels through.
+#[derive(Default)]
+pub struct MediumStack<'a> {
+    entered: Vec<(&'a dyn Material, Medium<'a>)>,
+}
+
+// How a path crosses a dielectric surface.
+pub enum Interface {
+    // the surface bounds the current medium, or a medium of at least its
+    // priority is being entered
+    Real,
+    // the surface lies inside a medium of higher priority and is skipped
+    False,
+}
+
+impl<'a> MediumStack<'a> {
+    pub fn current(&self) -> Option<&Medium<'a>> {
+        self.current_index(None).map(|index| &self.entered[index].1)
+    }
+
+    // the current medium if the one at excluded was left
+    fn current_index(&self, excluded: Option<usize>) -> Option<usize> {
+        // max_by_key picks the last of equal priorities
+        let (index, _) = self
+            .entered
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| Some(*index) != excluded)
+            .max_by_key(|(_, (_, medium))| medium.priority)?;
+        Some(index)
+    }
+
+    fn refraction_index(&self, index: Option<usize>) -> Option<&'a RefractiveIndex> {
+        index.map(|index| self.entered[index].1.refraction_index)
+    }
+
+    fn position(&self, material: &dyn Material) -> Option<usize> {
+        self.entered
+            .iter()
+            .position(|(entered, _)| std::ptr::addr_eq(*entered, material))
+    }
+
+    // Classifies the hit, and for real interfaces sets the refractive index
+    // on the outside of the surface.
+    pub fn interface(&self, hit_record: &mut HitRecord<'a>) -> Interface {
+        let Some(medium) = hit_record.material.medium() else {
+            return Interface::Real;
+        };
+
+        if hit_record.front_face {
+            match self.current() {
+                Some(current) if current.priority > medium.priority => Interface::False,
+                _ => {
+                    hit_record.outside_ior = self.refraction_index(self.current_index(None));
+                    Interface::Real
+                }
+            }
+        } else {
+            let Some(index) = self.position(hit_record.material) else {
+                // leaving a medium the path never entered, as for camera
+                // rays starting inside of it
+                hit_record.outside_ior = self.refraction_index(self.current_index(None));
+                return Interface::Real;
+            };
+            if self.current_index(None) != Some(index) {
+                return Interface::False;
+            }
+            hit_record.outside_ior = self.refraction_index(self.current_index(Some(index)));
+            Interface::Real
+        }
+    }
+
+    // Updates the media after the path continued in direction from the hit.
+    pub fn cross(&mut self, hit_record: &HitRecord<'a>, direction: Vec3) {
+        let Some(medium) = hit_record.material.medium() else {
+            return;
+        };
+        // the hit normal faces the incoming ray, so transmitted rays leave
+        // on the other side
+        if direction.dot(hit_record.normal) >= 0.0 {
+            return;
+        }
+
+        if hit_record.front_face {
+            self.entered.push((hit_record.material, medium));
+        } else if let Some(index) = self.position(hit_record.material) {
+            self.entered.remove(index);
+        }
+    }
+}
+
+// the ray continuing past a false interface
+pub fn continue_ray(ray: &Ray, hit_record: &HitRecord) -> Ray {
+    let continued = Ray::new(hit_record.point, ray.direction());
+    match ray.wavelength() {
+        Some(wavelength) => continued.with_wavelength(wavelength),
+        None => continued,
+    }
+}
+
+// Where a path travelling towards a surface through a medium went.
+pub struct Flight<const N: usize> {
+    // distance along the ray at which the path scattered, None if it reached
+    // the surface
+    pub scatter_distance: Option<f64>,
+    // factor of the throughput of each channel
+    pub weight: [f64; N],
+}
+
+// Samples how far a path gets towards a surface at distance through a
+// medium with the given extinction and scattering coefficients per channel.
+// Media that only absorb attenuate the path by the Beer-Lambert law. In
+// scattering media the distance is sampled for a channel picked in
+// proportion to the throughput of the path, and all channels are weighted by
+// the combined probability of the channels (one-sample MIS), which keeps
+// long walks through chromatic media from piling up weight in one channel.
+pub fn sample_flight<const N: usize>(
+    extinction: [f64; N],
+    scattering: [f64; N],
+    throughput: [f64; N],
+    distance: f64,
+    sampler: &mut dyn Sampler,
+) -> Flight<N> {
+    let transmittance = |t: f64| extinction.map(|sigma| (-sigma * t).exp());
+    if scattering.iter().all(|&sigma| sigma == 0.0) {
+        return Flight {
+            scatter_distance: None,
+            weight: transmittance(distance),
+        };
+    }
+
+    let total: f64 = throughput.iter().sum();
+    let probabilities = if total > 0.0 {
+        throughput.map(|t| t / total)
+    } else {
+        [1.0 / N as f64; N]
+    };
+    let u = sampler.get_1d();
+    let mut channel = N - 1;
+    let mut cumulative = 0.0;
+    for (i, probability) in probabilities.iter().enumerate() {
+        cumulative += probability;
+        if u < cumulative {
+            channel = i;
+            break;
+        }
+    }
+
+    let t = -(1.0 - sampler.get_1d()).ln() / extinction[channel];
+    if t < distance {
+        let at_t = transmittance(t);
+        let pdf: f64 = (0..N)
+            .map(|i| probabilities[i] * extinction[i] * at_t[i])
+            .sum();
+        Flight {
+            scatter_distance: Some(t),
+            weight: std::array::from_fn(|i| scattering[i] * at_t[i] / pdf),
+        }
+    } else {
+        let at_surface = transmittance(distance);
+        let probability: f64 = (0..N).map(|i| probabilities[i] * at_surface[i]).sum();
+        Flight {
+            scatter_distance: None,
+            weight: at_surface.map(|t| t / probability),
+        }
+    }
+}
+
+// Samples the direction a path travelling in direction continues in after
+// scattering, following the Henyey-Greenstein phase function with
+// asymmetry g.
+pub fn sample_phase(direction: Vec3, g: f64, u: (f64, f64)) -> Vec3 {
+    let cos_theta = if g.abs() < 1e-3 {
+        1.0 - 2.0 * u.0
+    } else {
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u.0);
+        ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * std::f64::consts::PI * u.1;
+
+    let w = direction.unit_vector();
+    let a = if w.x().abs() > 0.9 {
+        Vec3::new(0.0, 1.0, 0.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let v = Vec3::cross(w, a).unit_vector();
+    let u = Vec3::cross(w, v);
+    sin_theta * phi.cos() * u + sin_theta * phi.sin() * v + cos_theta * w
+}
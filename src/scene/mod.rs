@@ -1 +1,18 @@
+pub mod adaptive;
+pub mod aov;
 pub mod camera;
+pub mod cancel;
+pub mod checkpoint;
+pub mod denoise;
+pub mod description;
+pub mod film;
+pub mod filter;
+pub mod image;
+pub mod lens;
+pub mod media;
+pub mod observer;
+pub mod outline;
+pub mod progress;
+pub mod progressive;
+pub mod stats;
+pub mod tile;
@@ -0,0 +1,13 @@
+use crate::scene::film::Film;
+use crate::scene::tile::Region;
+
+// Receives the progress of a render along with the film as filled so far,
+// e.g. to display the image while it refines. Calls come from the render
+// threads while they hold the film, so they should return quickly.
+pub trait RenderObserver: Send + Sync {
+    // a tile of the current pass has been added to film
+    fn tile_finished(&self, _tile: Region, _film: &Film) {}
+
+    // every tile of pass (counted from 0) has been added to film
+    fn pass_finished(&self, _pass: u32, _film: &Film) {}
+}
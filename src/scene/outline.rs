@@ -0,0 +1,112 @@
+use rayon::prelude::*;
+
+use crate::color::Color;
+use crate::error::{Error, Result};
+use crate::scene::aov::Aov;
+use crate::scene::image::Image;
+
+// Ink lines drawn over an image where what the camera sees first changes
+// abruptly: at the silhouettes of objects, between different objects and
+// along creases. Pixels are compared with their neighbors within the line
+// width, and a line is drawn on the nearer side of an edge so that it hugs
+// the outline of the object in front. Pixels only partly within the width
+// of a line are partly covered, which keeps lines from looking jagged.
+#[derive(Clone, Copy)]
+pub struct Outline {
+    // width of the lines in pixels
+    pub width: f64,
+    pub color: Color,
+    // depth difference relative to the nearer depth and the pixel distance
+    // that makes an edge
+    pub depth_threshold: f64,
+    // distance between normals that makes an edge
+    pub normal_threshold: f64,
+}
+
+impl Default for Outline {
+    fn default() -> Self {
+        Self {
+            width: 1.0,
+            color: Color::new(0.0, 0.0, 0.0),
+            depth_threshold: 0.05,
+            normal_threshold: 0.6,
+        }
+    }
+}
+
+impl Outline {
+    // the AOVs edges are found in
+    pub const FEATURES: [Aov; 3] = [Aov::Normal, Aov::Depth, Aov::ObjectId];
+
+    pub fn draw(
+        &self,
+        color: &Image,
+        normal: &Image,
+        depth: &Image,
+        object_id: &Image,
+    ) -> Result<Image> {
+        let (width, height) = (color.width(), color.height());
+        for feature in [normal, depth, object_id] {
+            if feature.width() != width || feature.height() != height {
+                return Err(Error::InvalidParameter(format!(
+                    "{}x{} feature image for a {}x{} image",
+                    feature.width(),
+                    feature.height(),
+                    width,
+                    height
+                )));
+            }
+        }
+
+        let (normal, depth, object_id) = (normal.pixels(), depth.pixels(), object_id.pixels());
+        // the background has depth 0 and lies behind everything
+        let depth_at = |p: usize| {
+            if object_id[p].x() > 0.0 {
+                depth[p].x()
+            } else {
+                f64::INFINITY
+            }
+        };
+        let reach = (self.width + 1.0).ceil() as i64;
+
+        let pixels = (0..height)
+            .into_par_iter()
+            .flat_map_iter(|j| (0..width).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let p = (j * width + i) as usize;
+                let depth_p = depth_at(p);
+                let mut coverage: f64 = 0.0;
+                for dy in -reach..=reach {
+                    for dx in -reach..=reach {
+                        let (x, y) = (i as i64 + dx, j as i64 + dy);
+                        if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+                            continue;
+                        }
+                        let distance = ((dx * dx + dy * dy) as f64).sqrt();
+                        // the edge lies halfway to the neighbor, so the
+                        // pixel spans distance - 1 to distance from it
+                        let weight = (self.width + 1.0 - distance).clamp(0.0, 1.0);
+                        if weight <= coverage {
+                            continue;
+                        }
+                        let q = (y * width as i64 + x) as usize;
+                        let depth_q = depth_at(q);
+                        // the farther side of an edge is left to the nearer
+                        if depth_q < depth_p {
+                            continue;
+                        }
+                        let edge = object_id[q].x() != object_id[p].x()
+                            || (depth_q - depth_p) > self.depth_threshold * depth_p * distance
+                            || (normal[q] - normal[p]).length() > self.normal_threshold;
+                        if edge {
+                            coverage = weight;
+                        }
+                    }
+                }
+                let c = color.pixels()[p];
+                c + coverage * (self.color - c)
+            })
+            .collect();
+        Ok(Image::from_pixels(width, height, pixels))
+    }
+}
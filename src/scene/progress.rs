@@ -0,0 +1,142 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// minimum time between two progress lines
+const REPORT_INTERVAL: Duration = Duration::from_millis(250);
+
+// Prints the fraction of samples taken and the estimated time remaining to
+// stderr, rewriting a single line. Threads report the samples of every tile
+// they finish, and at most one line is printed per REPORT_INTERVAL. The total
+// is the number of samples the render is still set to take, which shrinks
+// as adaptive sampling finds pixels that have converged.
+pub struct ProgressReporter {
+    total_samples: AtomicU64,
+    done_samples: AtomicU64,
+    // samples taken before this render started, e.g. in a checkpoint
+    initial_samples: u64,
+    time_limit: Option<Duration>,
+    start: Instant,
+    last_report: Mutex<Option<Instant>>,
+}
+
+impl ProgressReporter {
+    pub fn new(total_samples: u64, initial_samples: u64, time_limit: Option<Duration>) -> Self {
+        Self {
+            total_samples: AtomicU64::new(total_samples.max(1)),
+            done_samples: AtomicU64::new(initial_samples),
+            initial_samples,
+            time_limit,
+            start: Instant::now(),
+            last_report: Mutex::new(None),
+        }
+    }
+
+    pub fn advance(&self, samples: u64) {
+        self.done_samples.fetch_add(samples, Ordering::Relaxed);
+
+        // threads that find another one printing skip their report
+        let Ok(mut last_report) = self.last_report.try_lock() else {
+            return;
+        };
+        if last_report.is_some_and(|last| last.elapsed() < REPORT_INTERVAL) {
+            return;
+        }
+        *last_report = Some(Instant::now());
+        self.print(false);
+    }
+
+    pub fn set_total(&self, total_samples: u64) {
+        self.total_samples
+            .store(total_samples.max(1), Ordering::Relaxed);
+    }
+
+    pub fn finish(&self) {
+        self.print(true);
+    }
+
+    fn fraction(&self) -> f64 {
+        let done = self.done_samples.load(Ordering::Relaxed);
+        let total = self.total_samples.load(Ordering::Relaxed);
+        let by_samples = (done as f64 / total as f64).min(1.0);
+        let by_time = self.time_limit.map_or(0.0, |limit| {
+            (self.start.elapsed().as_secs_f64() / limit.as_secs_f64()).min(1.0)
+        });
+        by_samples.max(by_time)
+    }
+
+    // Estimates the remaining time from the rate of this render, ignoring
+    // samples that were already taken when it started.
+    fn eta(&self) -> Option<Duration> {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let done = self.done_samples.load(Ordering::Relaxed) - self.initial_samples;
+        let remaining = self
+            .total_samples
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.done_samples.load(Ordering::Relaxed));
+        let mut eta = if done == 0 {
+            None
+        } else {
+            Some(elapsed * remaining as f64 / done as f64)
+        };
+        if let Some(limit) = self.time_limit {
+            let left = (limit.as_secs_f64() - elapsed).max(0.0);
+            eta = Some(eta.map_or(left, |eta| eta.min(left)));
+        }
+        eta.map(Duration::from_secs_f64)
+    }
+
+    fn print(&self, finished: bool) {
+        let (percent, eta) = if finished {
+            (100.0, Some(Duration::ZERO))
+        } else {
+            (100.0 * self.fraction(), self.eta())
+        };
+
+        let mut stderr = io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "\rrendering {:5.1}%  elapsed {}  eta {}   ",
+            percent,
+            format_duration(self.start.elapsed()),
+            eta.map_or("--:--".to_string(), format_duration)
+        );
+        if finished {
+            let _ = writeln!(stderr);
+        }
+        let _ = stderr.flush();
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converged_pixels_shrink_the_total() {
+        let progress = ProgressReporter::new(1000, 200, None);
+        progress.done_samples.fetch_add(300, Ordering::Relaxed);
+        assert_eq!(progress.fraction(), 0.5);
+        // pixels holding 400 samples converged with 100 of them taken
+        progress.set_total(700);
+        assert!((progress.fraction() - 5.0 / 7.0).abs() < 1e-12);
+        progress.set_total(500);
+        assert_eq!(progress.fraction(), 1.0);
+        assert_eq!(progress.eta(), Some(Duration::ZERO));
+    }
+}
@@ -0,0 +1,48 @@
+use std::time::{Duration, Instant};
+
+// Renders in passes of pass_samples per pixel, rewriting the output image
+// after every pass (or at most every snapshot_interval) until the target
+// sample count is reached or time_limit has passed. The time limit is
+// checked between passes, so a render can run over it by up to one pass.
+#[derive(Clone, Copy, Debug)]
+pub struct Progressive {
+    pub pass_samples: u32,
+    pub snapshot_interval: Option<Duration>,
+    pub time_limit: Option<Duration>,
+}
+
+// Tracks when a progressive render should write snapshots and stop.
+pub struct ProgressiveClock {
+    progressive: Progressive,
+    start: Instant,
+    last_snapshot: Instant,
+}
+
+impl ProgressiveClock {
+    pub fn start(progressive: Progressive) -> Self {
+        let now = Instant::now();
+        Self {
+            progressive,
+            start: now,
+            last_snapshot: now,
+        }
+    }
+
+    pub fn out_of_time(&self) -> bool {
+        self.progressive
+            .time_limit
+            .is_some_and(|limit| self.start.elapsed() >= limit)
+    }
+
+    // returns whether a snapshot is due, restarting the interval if so
+    pub fn snapshot_due(&mut self) -> bool {
+        let due = self
+            .progressive
+            .snapshot_interval
+            .is_none_or(|interval| self.last_snapshot.elapsed() >= interval);
+        if due {
+            self.last_snapshot = Instant::now();
+        }
+        due
+    }
+}
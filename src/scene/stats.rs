@@ -0,0 +1,93 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Rays traced by one thread. Threads count into their own copy and add it to
+// the shared totals once per tile, so counting costs no synchronization in
+// the path tracing loop.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RayCounts {
+    // paths started from the camera
+    pub camera_rays: u64,
+    // every ray traced through the scene, camera rays included
+    pub rays: u64,
+    // paths cut off by the maximum depth
+    pub max_depth_paths: u64,
+}
+
+impl RayCounts {
+    pub fn add(&mut self, other: &RayCounts) {
+        self.camera_rays += other.camera_rays;
+        self.rays += other.rays;
+        self.max_depth_paths += other.max_depth_paths;
+    }
+}
+
+// The totals of a render, shared between its threads.
+#[derive(Debug, Default)]
+pub struct RayCounters {
+    camera_rays: AtomicU64,
+    rays: AtomicU64,
+    max_depth_paths: AtomicU64,
+}
+
+impl RayCounters {
+    pub fn add(&self, counts: &RayCounts) {
+        self.camera_rays
+            .fetch_add(counts.camera_rays, Ordering::Relaxed);
+        self.rays.fetch_add(counts.rays, Ordering::Relaxed);
+        self.max_depth_paths
+            .fetch_add(counts.max_depth_paths, Ordering::Relaxed);
+    }
+
+    pub fn load(&self) -> RayCounts {
+        RayCounts {
+            camera_rays: self.camera_rays.load(Ordering::Relaxed),
+            rays: self.rays.load(Ordering::Relaxed),
+            max_depth_paths: self.max_depth_paths.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// Summary of a finished render.
+#[derive(Clone, Copy, Debug)]
+pub struct RenderStatistics {
+    pub wall_time: Duration,
+    pub counts: RayCounts,
+}
+
+impl RenderStatistics {
+    pub fn rays_per_second(&self) -> f64 {
+        self.counts.rays as f64 / self.wall_time.as_secs_f64().max(1e-9)
+    }
+
+    // average number of rays traced per camera path
+    pub fn average_depth(&self) -> f64 {
+        if self.counts.camera_rays == 0 {
+            0.0
+        } else {
+            self.counts.rays as f64 / self.counts.camera_rays as f64
+        }
+    }
+}
+
+impl fmt::Display for RenderStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let max_depth_share = if self.counts.camera_rays == 0 {
+            0.0
+        } else {
+            100.0 * self.counts.max_depth_paths as f64 / self.counts.camera_rays as f64
+        };
+
+        writeln!(f, "wall time:        {:.2}s", self.wall_time.as_secs_f64())?;
+        writeln!(f, "camera rays:      {}", self.counts.camera_rays)?;
+        writeln!(f, "total rays:       {}", self.counts.rays)?;
+        writeln!(f, "rays per second:  {:.2}M", self.rays_per_second() / 1e6)?;
+        writeln!(f, "average depth:    {:.2}", self.average_depth())?;
+        write!(
+            f,
+            "max depth paths:  {} ({:.1}%)",
+            self.counts.max_depth_paths, max_depth_share
+        )
+    }
+}
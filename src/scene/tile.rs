@@ -0,0 +1,237 @@
+use std::fmt;
+use std::str::FromStr;
+
+// A rectangle of pixels, from (x0, y0) inclusive to (x1, y1) exclusive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl Region {
+    pub fn new(x0: u32, y0: u32, x1: u32, y1: u32) -> Self {
+        Self { x0, y0, x1, y1 }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.x1.saturating_sub(self.x0)
+    }
+
+    pub fn height(&self) -> u32 {
+        self.y1.saturating_sub(self.y0)
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        self.x0 <= x && x < self.x1 && self.y0 <= y && y < self.y1
+    }
+
+    pub fn intersect(&self, other: &Region) -> Region {
+        Region {
+            x0: self.x0.max(other.x0),
+            y0: self.y0.max(other.y0),
+            x1: self.x1.min(other.x1),
+            y1: self.y1.min(other.y1),
+        }
+    }
+
+    // pixel coordinates in row-major order
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        (self.y0..self.y1).flat_map(move |y| (self.x0..self.x1).map(move |x| (x, y)))
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    // parses "x0,y0,x1,y1"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| format!("invalid region {:?}: {}", s, err))?;
+
+        match values[..] {
+            [x0, y0, x1, y1] if x0 < x1 && y0 < y1 => Ok(Region::new(x0, y0, x1, y1)),
+            [_, _, _, _] => Err(format!("region {:?} is empty", s)),
+            _ => Err(format!("region {:?} must be x0,y0,x1,y1", s)),
+        }
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.x0, self.y0, self.x1, self.y1)
+    }
+}
+
+// The order tiles are handed out to the render threads in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileOrder {
+    Scanline,
+    // outwards from the center of the image
+    Spiral,
+    // along a Hilbert curve, keeping consecutive tiles close together
+    Hilbert,
+}
+
+// Splits region into square tiles of tile_size pixels (smaller at the right
+// and bottom edges) and sorts them into the requested order.
+pub fn tiles(region: Region, tile_size: u32, order: TileOrder) -> Vec<Region> {
+    assert!(tile_size > 0, "tiles need to be at least 1 pixel wide");
+    let tiles_x = region.width().div_ceil(tile_size);
+    let tiles_y = region.height().div_ceil(tile_size);
+
+    let mut coordinates: Vec<(u32, u32)> = (0..tiles_y)
+        .flat_map(|ty| (0..tiles_x).map(move |tx| (tx, ty)))
+        .collect();
+    if coordinates.is_empty() {
+        return Vec::new();
+    }
+
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            let mut rank = vec![usize::MAX; coordinates.len()];
+            for (index, (tx, ty)) in spiral(tiles_x, tiles_y).into_iter().enumerate() {
+                rank[(ty * tiles_x + tx) as usize] = index;
+            }
+            coordinates.sort_by_key(|&(tx, ty)| rank[(ty * tiles_x + tx) as usize]);
+        }
+        TileOrder::Hilbert => {
+            let side = tiles_x.max(tiles_y).next_power_of_two();
+            coordinates.sort_by_key(|&(tx, ty)| hilbert_index(side, tx, ty));
+        }
+    }
+
+    coordinates
+        .into_iter()
+        .map(|(tx, ty)| {
+            let x0 = region.x0 + tx * tile_size;
+            let y0 = region.y0 + ty * tile_size;
+            Region::new(
+                x0,
+                y0,
+                (x0 + tile_size).min(region.x1),
+                (y0 + tile_size).min(region.y1),
+            )
+        })
+        .collect()
+}
+
+// Walks a square spiral around the center tile, keeping the positions that
+// are inside the grid, until every tile has been visited.
+fn spiral(tiles_x: u32, tiles_y: u32) -> Vec<(u32, u32)> {
+    let total = (tiles_x * tiles_y) as usize;
+    let mut visited = Vec::with_capacity(total);
+
+    let (mut x, mut y) = (((tiles_x - 1) / 2) as i64, ((tiles_y - 1) / 2) as i64);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut leg_length = 1;
+    let mut direction = 0;
+
+    let visit = |x: i64, y: i64, visited: &mut Vec<(u32, u32)>| {
+        if 0 <= x && x < tiles_x as i64 && 0 <= y && y < tiles_y as i64 {
+            visited.push((x as u32, y as u32));
+        }
+    };
+
+    visit(x, y, &mut visited);
+    while visited.len() < total {
+        // every leg length is walked twice before growing
+        for _ in 0..2 {
+            let (dx, dy) = directions[direction % 4];
+            for _ in 0..leg_length {
+                x += dx;
+                y += dy;
+                visit(x, y, &mut visited);
+            }
+            direction += 1;
+        }
+        leg_length += 1;
+    }
+
+    visited
+}
+
+// distance of (x, y) along the Hilbert curve filling a side x side grid
+fn hilbert_index(side: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut index = 0u64;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = u32::from(x & s > 0);
+        let ry = u32::from(y & s > 0);
+        index += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+
+        // rotate the quadrant so the curve stays continuous
+        if ry == 0 {
+            if rx == 1 {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::scene::camera::Camera;
+
+    #[test]
+    fn cameras_reject_empty_tiles() {
+        let camera = Camera::builder().tiles(0, TileOrder::Scanline).build();
+        assert!(matches!(camera, Err(Error::InvalidParameter(_))));
+    }
+
+    #[test]
+    fn every_order_covers_every_pixel_once() {
+        // odd sizes leave partial tiles at the right and bottom edges
+        let regions = [
+            Region::new(0, 0, 100, 70),
+            Region::new(13, 7, 50, 90),
+            Region::new(0, 0, 16, 16),
+            Region::new(5, 5, 6, 6),
+        ];
+        for region in regions {
+            for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+                let tiles = tiles(region, 16, order);
+                let mut covered = vec![0; region.width() as usize * region.height() as usize];
+                for tile in &tiles {
+                    assert!(tile.width() > 0 && tile.height() > 0);
+                    assert!(tile.width() <= 16 && tile.height() <= 16);
+                    for (x, y) in tile.pixels() {
+                        assert!(region.contains(x, y), "{} outside of {}", tile, region);
+                        let index = (y - region.y0) * region.width() + (x - region.x0);
+                        covered[index as usize] += 1;
+                    }
+                }
+                assert!(covered.iter().all(|&count| count == 1), "{:?}", order);
+                let tiles_x = region.width().div_ceil(16);
+                let tiles_y = region.height().div_ceil(16);
+                assert_eq!(tiles.len() as u32, tiles_x * tiles_y);
+            }
+        }
+    }
+
+    #[test]
+    fn spirals_start_at_the_center() {
+        let tiles = tiles(Region::new(0, 0, 80, 48), 16, TileOrder::Spiral);
+        assert_eq!(tiles[0], Region::new(32, 16, 48, 32));
+    }
+
+    #[test]
+    fn hilbert_curves_step_to_neighbouring_tiles() {
+        let tiles = tiles(Region::new(0, 0, 128, 128), 16, TileOrder::Hilbert);
+        for pair in tiles.windows(2) {
+            let distance = pair[0].x0.abs_diff(pair[1].x0) + pair[0].y0.abs_diff(pair[1].y0);
+            assert_eq!(distance, 16, "{} to {}", pair[0], pair[1]);
+        }
+    }
+}
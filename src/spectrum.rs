@@ -0,0 +1,325 @@
+use std::ops;
+use std::sync::OnceLock;
+
+use crate::color::Color;
+use crate::error::{Error, Result};
+
+// wavelengths, in nm, that paths are traced at in spectral mode
+pub const WAVELENGTH_MIN: f64 = 360.0;
+pub const WAVELENGTH_MAX: f64 = 830.0;
+
+// the sodium d-line, at which constant refractive indices are usually given
+pub const D_LINE: f64 = 587.56;
+
+// number of wavelengths carried by each path
+pub const SPECTRUM_SAMPLES: usize = 4;
+
+// linear sRGB from CIE XYZ, for a D65 white point
+const XYZ_TO_SRGB: [[f64; 3]; 3] = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.9692660, 1.8760108, 0.0415560],
+    [0.0556434, -0.2040259, 1.0572252],
+];
+
+// CIE standard illuminant D65 from 360 to 830 nm in 10 nm steps
+const D65: [f64; 48] = [
+    46.6383, 52.0891, 49.9755, 54.6482, 82.7549, 91.486, 93.4318, 86.6823, 104.865, 117.008,
+    117.812, 114.861, 115.923, 108.811, 109.354, 107.802, 104.79, 107.689, 104.405, 104.046, 100.0,
+    96.3342, 95.788, 88.6856, 90.0062, 89.5991, 87.6987, 83.2886, 83.6992, 80.0268, 80.2146,
+    82.2778, 78.2842, 69.7213, 71.6091, 74.349, 61.604, 69.8856, 75.087, 63.5927, 46.4182, 66.8054,
+    63.3828, 64.304, 59.4519, 51.959, 57.4406, 60.3125,
+];
+
+// Smits' basis spectra for upsampling reflectances, in 10 equal bins from
+// 380 to 720 nm
+const SMITS_MIN: f64 = 380.0;
+const SMITS_MAX: f64 = 720.0;
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+// Values of a spectrum at the wavelengths of a path.
+#[derive(Clone, Copy, Debug)]
+pub struct SampledSpectrum([f64; SPECTRUM_SAMPLES]);
+
+impl SampledSpectrum {
+    pub fn splat(value: f64) -> Self {
+        Self([value; SPECTRUM_SAMPLES])
+    }
+
+    pub fn from_fn(f: impl Fn(usize) -> f64) -> Self {
+        Self(std::array::from_fn(f))
+    }
+}
+
+impl ops::Mul for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self::from_fn(|i| self.0[i] * other.0[i])
+    }
+}
+
+impl ops::Index<usize> for SampledSpectrum {
+    type Output = f64;
+
+    fn index(&self, i: usize) -> &f64 {
+        &self.0[i]
+    }
+}
+
+// The wavelengths a path is traced at. The first, hero wavelength is
+// sampled and the others are spaced evenly from it over the visible range,
+// so every path covers the whole spectrum.
+#[derive(Clone, Copy, Debug)]
+pub struct SampledWavelengths {
+    lambda: [f64; SPECTRUM_SAMPLES],
+    pdf: [f64; SPECTRUM_SAMPLES],
+}
+
+impl SampledWavelengths {
+    // importance samples the wavelengths the eye is most sensitive to
+    pub fn sample_visible(u: f64) -> Self {
+        let mut lambda = [0.0; SPECTRUM_SAMPLES];
+        let mut pdf = [0.0; SPECTRUM_SAMPLES];
+        for i in 0..SPECTRUM_SAMPLES {
+            let up = (u + i as f64 / SPECTRUM_SAMPLES as f64).fract();
+            lambda[i] = sample_visible_wavelength(up);
+            pdf[i] = visible_wavelength_pdf(lambda[i]);
+        }
+        Self { lambda, pdf }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn get(&self, i: usize) -> f64 {
+        self.lambda[i]
+    }
+
+    // Keeps only the hero wavelength, for paths that scattered in a
+    // direction only valid for it.
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        for pdf in &mut self.pdf[1..] {
+            *pdf = 0.0;
+        }
+        self.pdf[0] /= SPECTRUM_SAMPLES as f64;
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|&pdf| pdf == 0.0)
+    }
+
+    // evaluates f at every wavelength
+    pub fn sample(&self, f: impl Fn(f64) -> f64) -> SampledSpectrum {
+        SampledSpectrum::from_fn(|i| f(self.lambda[i]))
+    }
+
+    // the Monte Carlo estimate of the color of radiance, as XYZ
+    pub fn to_xyz(&self, radiance: SampledSpectrum) -> Color {
+        let mut xyz = Color::new(0.0, 0.0, 0.0);
+        for i in 0..SPECTRUM_SAMPLES {
+            if self.pdf[i] > 0.0 {
+                xyz += (radiance[i] / self.pdf[i]) * cie_xyz(self.lambda[i]);
+            }
+        }
+        xyz / SPECTRUM_SAMPLES as f64
+    }
+
+    pub fn to_rgb(&self, radiance: SampledSpectrum) -> Color {
+        xyz_to_srgb(self.to_xyz(radiance))
+    }
+}
+
+// tabulated curve over wavelength, interpolated linearly and clamped to
+// the end points outside of it
+#[derive(Clone, Debug)]
+pub struct SpectralCurve {
+    samples: Vec<(f64, f64)>,
+}
+
+impl SpectralCurve {
+    // samples are (wavelength in nm, value) pairs in increasing wavelength
+    pub fn new(samples: Vec<(f64, f64)>) -> Result<Self> {
+        if samples.is_empty() {
+            return Err(Error::InvalidParameter(
+                "spectral curve without samples".to_string(),
+            ));
+        }
+        if samples
+            .iter()
+            .any(|(l, v)| !l.is_finite() || !v.is_finite())
+        {
+            return Err(Error::InvalidParameter(
+                "spectral curve samples must be finite".to_string(),
+            ));
+        }
+        if samples.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+            return Err(Error::InvalidParameter(
+                "spectral curve wavelengths must increase".to_string(),
+            ));
+        }
+        Ok(Self { samples })
+    }
+
+    pub fn samples(&self) -> &[(f64, f64)] {
+        &self.samples
+    }
+
+    pub fn evaluate(&self, wavelength: f64) -> f64 {
+        let (first, last) = (self.samples[0], self.samples[self.samples.len() - 1]);
+        if wavelength <= first.0 {
+            return first.1;
+        }
+        if wavelength >= last.0 {
+            return last.1;
+        }
+        let upper = self.samples.partition_point(|&(l, _)| l <= wavelength);
+        let (l0, v0) = self.samples[upper - 1];
+        let (l1, v1) = self.samples[upper];
+        v0 + (v1 - v0) * (wavelength - l0) / (l1 - l0)
+    }
+}
+
+// Smits' reflectance spectrum for a linear sRGB color, which stays within
+// [0, 1] for colors within [0, 1].
+pub fn rgb_reflectance(rgb: Color, wavelength: f64) -> f64 {
+    let bin =
+        (((wavelength - SMITS_MIN) / (SMITS_MAX - SMITS_MIN) * 10.0) as isize).clamp(0, 9) as usize;
+    let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+    let mix = |basis: [(&[f64; 10], f64); 3]| {
+        basis
+            .iter()
+            .map(|(spectrum, weight)| spectrum[bin] * weight)
+            .sum::<f64>()
+    };
+
+    if r <= g && r <= b {
+        if g <= b {
+            mix([
+                (&SMITS_WHITE, r),
+                (&SMITS_CYAN, g - r),
+                (&SMITS_BLUE, b - g),
+            ])
+        } else {
+            mix([
+                (&SMITS_WHITE, r),
+                (&SMITS_CYAN, b - r),
+                (&SMITS_GREEN, g - b),
+            ])
+        }
+    } else if g <= r && g <= b {
+        if r <= b {
+            mix([
+                (&SMITS_WHITE, g),
+                (&SMITS_MAGENTA, r - g),
+                (&SMITS_BLUE, b - r),
+            ])
+        } else {
+            mix([
+                (&SMITS_WHITE, g),
+                (&SMITS_MAGENTA, b - g),
+                (&SMITS_RED, r - b),
+            ])
+        }
+    } else if r <= g {
+        mix([
+            (&SMITS_WHITE, b),
+            (&SMITS_YELLOW, r - b),
+            (&SMITS_GREEN, g - r),
+        ])
+    } else {
+        mix([
+            (&SMITS_WHITE, b),
+            (&SMITS_YELLOW, g - b),
+            (&SMITS_RED, r - g),
+        ])
+    }
+}
+
+// Emission spectrum for a linear sRGB color, the reflectance spectrum lit by
+// D65 so that white light has the sRGB white point and a luminance of 1.
+pub fn rgb_illuminant(rgb: Color, wavelength: f64) -> f64 {
+    rgb_reflectance(rgb, wavelength) * d65(wavelength) * d65_normalization()
+}
+
+fn d65(wavelength: f64) -> f64 {
+    let x = ((wavelength - WAVELENGTH_MIN) / 10.0).clamp(0.0, (D65.len() - 1) as f64);
+    let i = (x as usize).min(D65.len() - 2);
+    let t = x - i as f64;
+    D65[i] * (1.0 - t) + D65[i + 1] * t
+}
+
+// scale giving D65 a luminance of 1
+fn d65_normalization() -> f64 {
+    static NORMALIZATION: OnceLock<f64> = OnceLock::new();
+    *NORMALIZATION.get_or_init(|| {
+        let luminance: f64 = (WAVELENGTH_MIN as u32..=WAVELENGTH_MAX as u32)
+            .map(|l| d65(l as f64) * cie_xyz(l as f64).y())
+            .sum();
+        1.0 / luminance
+    })
+}
+
+// the CIE 1931 color matching functions, using the multi-lobe Gaussian fit
+// of Wyman, Sloan and Shirley
+pub fn cie_xyz(wavelength: f64) -> Color {
+    let g = |mu: f64, sigma_below: f64, sigma_above: f64| {
+        let sigma = if wavelength < mu {
+            sigma_below
+        } else {
+            sigma_above
+        };
+        let t = (wavelength - mu) / sigma;
+        (-0.5 * t * t).exp()
+    };
+    let x =
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2);
+    let y = 0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1);
+    let z = 1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8);
+    Color::new(x, y, z)
+}
+
+pub fn xyz_to_srgb(xyz: Color) -> Color {
+    let row = |r: &[f64; 3]| r[0] * xyz.x() + r[1] * xyz.y() + r[2] * xyz.z();
+    Color::new(
+        row(&XYZ_TO_SRGB[0]),
+        row(&XYZ_TO_SRGB[1]),
+        row(&XYZ_TO_SRGB[2]),
+    )
+}
+
+// sampling density roughly following the luminous efficiency of the eye,
+// from pbrt
+fn sample_visible_wavelength(u: f64) -> f64 {
+    538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh()
+}
+
+fn visible_wavelength_pdf(wavelength: f64) -> f64 {
+    if !(WAVELENGTH_MIN..=WAVELENGTH_MAX).contains(&wavelength) {
+        return 0.0;
+    }
+    0.0039398042 / (0.0072 * (wavelength - 538.0)).cosh().powi(2)
+}
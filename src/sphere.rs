@@ -1,13 +1,15 @@
 use crate::hittable::{HitRecord, Hittable};
-use crate::material::{Material};
+use crate::material::Material;
 use crate::math::interval::Interval;
 use crate::point::Point3;
 use crate::ray::Ray;
+use crate::vec3::Vec3;
 
 pub struct Sphere {
     center: Point3,
     radius: f64,
     material: Box<dyn Material>,
+    material_id: u32,
 }
 
 impl Sphere {
@@ -16,12 +18,19 @@ impl Sphere {
             center,
             radius: radius.max(0.0),
             material,
+            material_id: 0,
         }
     }
+
+    // the ID reported for the material in the material ID output
+    pub fn with_material_id(mut self, material_id: u32) -> Self {
+        self.material_id = material_id;
+        self
+    }
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord<'_>> {
         let oc = ray.origin() - self.center;
         let a = ray.direction().length_squared();
         let half_b = oc.dot(ray.direction());
@@ -46,23 +55,53 @@ impl Hittable for Sphere {
         let outward_normal = (point - self.center) / self.radius;
         let normal = HitRecord::calculate_face_normal(ray, outward_normal);
         let front_face = ray.direction().dot(outward_normal) < 0.0;
-
-        let hit_record = HitRecord {
-            point,
-            normal,
-            t: root,
-            front_face,
-            scatter_result: None,
-        };
-
-        let scatter_result = self.material.scatter(ray, &hit_record);
+        let (u, v) = sphere_uv(outward_normal);
+        let (dpdu, dpdv) = sphere_tangents(point - self.center);
 
         Some(HitRecord {
             point,
             normal,
             t: root,
+            u,
+            v,
+            dpdu,
+            dpdv,
             front_face,
-            scatter_result,
+            material: self.material.as_ref(),
+            object_id: 0,
+            material_id: self.material_id,
+            outside_ior: None,
         })
     }
 }
+
+// Longitude and latitude of a point on the unit sphere, both in [0, 1]. u
+// grows around the Y axis starting from -X, and v from the south pole at -Y
+// up to the north pole.
+pub fn sphere_uv(point: Point3) -> (f64, f64) {
+    let theta = (-point.y()).clamp(-1.0, 1.0).acos();
+    let phi = (-point.z()).atan2(point.x()) + std::f64::consts::PI;
+    (
+        phi / (2.0 * std::f64::consts::PI),
+        theta / std::f64::consts::PI,
+    )
+}
+
+// Derivatives by u and v of the point at offset from the center following
+// sphere_uv. At the poles, where u is undefined, dpdv is picked
+// perpendicular to a dpdu along X.
+fn sphere_tangents(offset: Vec3) -> (Vec3, Vec3) {
+    let (x, y, z) = (offset.x(), offset.y(), offset.z());
+    let pi = std::f64::consts::PI;
+    let ring_radius = (x * x + z * z).sqrt();
+    if ring_radius < 1e-9 * offset.length() {
+        let dpdu = Vec3::new(2.0 * pi * offset.length(), 0.0, 0.0);
+        return (
+            dpdu,
+            Vec3::new(0.0, 0.0, -y.signum() * pi * offset.length()),
+        );
+    }
+    let dpdu = 2.0 * pi * Vec3::new(z, 0.0, -x);
+    let dpdv = pi * Vec3::new(-x * y / ring_radius, ring_radius, -y * z / ring_radius);
+    (dpdu, dpdv)
+}
@@ -0,0 +1,253 @@
+use std::sync::Arc;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::color::Color;
+use crate::display::srgb_inverse_transfer;
+use crate::point::Point3;
+use crate::scene::image::Image;
+use crate::vec3::Vec3;
+
+const PERLIN_POINTS: usize = 256;
+
+// Values varying over a surface, looked up by the surface coordinates (u, v)
+// of a hit and its position. Scalar parameters use the first channel.
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, point: Point3) -> Color;
+}
+
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _point: Point3) -> Color {
+        self.color
+    }
+}
+
+// Alternating cubes of two textures filling space.
+pub struct Checker {
+    inverse_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl Checker {
+    // scale is the edge length of the cubes
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        Self {
+            inverse_scale: 1.0 / scale,
+            even,
+            odd,
+        }
+    }
+
+    pub fn from_colors(scale: f64, even: Color, odd: Color) -> Self {
+        Self::new(
+            scale,
+            Arc::new(SolidColor::new(even)),
+            Arc::new(SolidColor::new(odd)),
+        )
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, point: Point3) -> Color {
+        let cell = |c: f64| (self.inverse_scale * c).floor() as i64;
+        let sum = cell(point.x()) + cell(point.y()) + cell(point.z());
+        if sum.rem_euclid(2) == 0 {
+            self.even.value(u, v, point)
+        } else {
+            self.odd.value(u, v, point)
+        }
+    }
+}
+
+// An image wrapped around a surface, with u growing to the right and v
+// upwards, repeating outside of [0, 1] and filtered bilinearly.
+pub struct ImageTexture {
+    image: Image,
+}
+
+impl ImageTexture {
+    // uses the values of image as they are, as needed for normal and bump
+    // maps
+    pub fn new(image: Image) -> Self {
+        Self { image }
+    }
+
+    // decodes the sRGB transfer function of color images
+    pub fn from_srgb(image: Image) -> Self {
+        let decode = |c: Color| {
+            Color::new(
+                srgb_inverse_transfer(c.x()),
+                srgb_inverse_transfer(c.y()),
+                srgb_inverse_transfer(c.z()),
+            )
+        };
+        let pixels = image.pixels().iter().map(|&c| decode(c)).collect();
+        Self::new(Image::from_pixels(image.width(), image.height(), pixels))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _point: Point3) -> Color {
+        let (width, height) = (self.image.width(), self.image.height());
+        // pixel centers are at half integer coordinates
+        let x = u.rem_euclid(1.0) * width as f64 - 0.5;
+        let y = (1.0 - v.rem_euclid(1.0)) * height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        let pixel = |x: f64, y: f64| {
+            let x = (x as i64).rem_euclid(width as i64) as u32;
+            let y = (y as i64).rem_euclid(height as i64) as u32;
+            self.image.pixel(x, y)
+        };
+        let top = (1.0 - fx) * pixel(x0, y0) + fx * pixel(x0 + 1.0, y0);
+        let bottom = (1.0 - fx) * pixel(x0, y0 + 1.0) + fx * pixel(x0 + 1.0, y0 + 1.0);
+        (1.0 - fy) * top + fy * bottom
+    }
+}
+
+// Stretches the range [low, high] of a texture to [0, 1], clamping values
+// outside of it, which turns smooth noise into patches with soft edges.
+pub struct Remap {
+    texture: Arc<dyn Texture>,
+    low: f64,
+    high: f64,
+}
+
+impl Remap {
+    pub fn new(texture: Arc<dyn Texture>, low: f64, high: f64) -> Self {
+        Self { texture, low, high }
+    }
+}
+
+impl Texture for Remap {
+    fn value(&self, u: f64, v: f64, point: Point3) -> Color {
+        let value = self.texture.value(u, v, point);
+        let remap = |c: f64| {
+            if self.high > self.low {
+                ((c - self.low) / (self.high - self.low)).clamp(0.0, 1.0)
+            } else if c >= self.low {
+                1.0
+            } else {
+                0.0
+            }
+        };
+        Color::new(remap(value.x()), remap(value.y()), remap(value.z()))
+    }
+}
+
+// Gray fractal Perlin noise in [0, 1], smooth over distances of about
+// 1 / scale.
+pub struct Noise {
+    perlin: Perlin,
+    scale: f64,
+    octaves: u32,
+}
+
+impl Noise {
+    // The noise pattern is generated from seed, independently of the thread
+    // random number generator.
+    pub fn new(seed: u64, scale: f64) -> Self {
+        Self {
+            perlin: Perlin::new(seed),
+            scale,
+            octaves: 1,
+        }
+    }
+
+    // Adds octaves of noise at doubled frequencies and halved amplitudes.
+    pub fn with_octaves(mut self, octaves: u32) -> Self {
+        self.octaves = octaves.max(1);
+        self
+    }
+}
+
+impl Texture for Noise {
+    fn value(&self, _u: f64, _v: f64, point: Point3) -> Color {
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut p = self.scale * point;
+        for _ in 0..self.octaves {
+            sum += amplitude * self.perlin.noise(p);
+            total += amplitude;
+            amplitude *= 0.5;
+            p = 2.0 * p;
+        }
+        let value = (0.5 + 0.5 * sum / total).clamp(0.0, 1.0);
+        Color::new(value, value, value)
+    }
+}
+
+// Ken Perlin's gradient noise, in [-1, 1].
+struct Perlin {
+    gradients: Vec<Vec3>,
+    permutations: [Vec<usize>; 3],
+}
+
+impl Perlin {
+    fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let gradients = (0..PERLIN_POINTS)
+            .map(|_| Vec3::sample_unit_vector((rng.gen(), rng.gen())))
+            .collect();
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..PERLIN_POINTS).collect();
+            for i in (1..PERLIN_POINTS).rev() {
+                p.swap(i, rng.gen_range(0..=i));
+            }
+            p
+        };
+        let permutations = [permutation(), permutation(), permutation()];
+        Self {
+            gradients,
+            permutations,
+        }
+    }
+
+    fn noise(&self, p: Point3) -> f64 {
+        let floor = [p.x().floor(), p.y().floor(), p.z().floor()];
+        let fraction = [p.x() - floor[0], p.y() - floor[1], p.z() - floor[2]];
+        // Hermite smoothing hides the grid
+        let smooth = fraction.map(|f| f * f * (3.0 - 2.0 * f));
+        let cell = floor.map(|f| f as i64);
+
+        let mut sum = 0.0;
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let hash = (0..3).fold(0, |hash, axis| {
+                let index = (cell[axis] + offset[axis] as i64).rem_euclid(PERLIN_POINTS as i64);
+                hash ^ self.permutations[axis][index as usize]
+            });
+            let weight = Vec3::new(
+                fraction[0] - offset[0] as f64,
+                fraction[1] - offset[1] as f64,
+                fraction[2] - offset[2] as f64,
+            );
+            let blend = (0..3)
+                .map(|axis| {
+                    if offset[axis] == 1 {
+                        smooth[axis]
+                    } else {
+                        1.0 - smooth[axis]
+                    }
+                })
+                .product::<f64>();
+            sum += blend * self.gradients[hash].dot(weight);
+        }
+        // gradient noise stays within about ±√3/2
+        (sum / 0.866).clamp(-1.0, 1.0)
+    }
+}
@@ -0,0 +1,162 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+use crate::hittable::HitRecord;
+use crate::texture::Texture;
+
+// A transparent layer a few hundred nanometers thick on top of a surface, as
+// in soap bubbles, oil slicks and lens coatings. Light reflected off its top
+// and bottom interferes, so the reflectance oscillates with the wavelength
+// and the angle and the surface shows iridescent colors.
+#[derive(Clone)]
+pub struct ThinFilm {
+    // in nm
+    thickness: f64,
+    refraction_index: f64,
+    // texture whose first channel blends the thickness between a minimum and
+    // a maximum
+    thickness_texture: Option<(Arc<dyn Texture>, f64, f64)>,
+}
+
+// What lies below a thin film.
+#[derive(Clone, Copy, Debug)]
+pub enum Substrate {
+    // a dielectric with this refractive index
+    Dielectric(f64),
+    // a conductor reflecting this fraction of the light at every angle
+    Conductor(f64),
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f64, refraction_index: f64) -> Result<Self> {
+        validate_thickness(thickness)?;
+        if refraction_index.is_nan() || refraction_index < 1.0 || refraction_index.is_infinite() {
+            return Err(Error::InvalidParameter(format!(
+                "thin film refractive index {} needs to be at least 1",
+                refraction_index
+            )));
+        }
+        Ok(Self {
+            thickness,
+            refraction_index,
+            thickness_texture: None,
+        })
+    }
+
+    // Varies the thickness over the surface from min where the first
+    // channel of texture is 0 to max where it is 1.
+    pub fn with_thickness_texture(
+        mut self,
+        texture: Arc<dyn Texture>,
+        min: f64,
+        max: f64,
+    ) -> Result<Self> {
+        validate_thickness(min)?;
+        validate_thickness(max)?;
+        self.thickness_texture = Some((texture, min, max));
+        Ok(self)
+    }
+
+    pub fn refraction_index(&self) -> f64 {
+        self.refraction_index
+    }
+
+    // thickness in nm at the hit point
+    pub fn thickness_at(&self, hit_record: &HitRecord) -> f64 {
+        match &self.thickness_texture {
+            Some((texture, min, max)) => {
+                let t = texture
+                    .value(hit_record.u, hit_record.v, hit_record.point)
+                    .x()
+                    .clamp(0.0, 1.0);
+                min + t * (max - min)
+            }
+            None => self.thickness,
+        }
+    }
+
+    // Reflectance of unpolarized light of wavelength in nm arriving at
+    // cos_theta to the normal from a medium of index outside_index, summing
+    // all the reflections inside a film of the given thickness (Airy).
+    pub fn reflectance(
+        &self,
+        thickness: f64,
+        wavelength: f64,
+        cos_theta: f64,
+        outside_index: f64,
+        substrate: Substrate,
+    ) -> f64 {
+        let (n1, n2) = (outside_index, self.refraction_index);
+        let cos1 = cos_theta.clamp(0.0, 1.0);
+        let sin2_1 = 1.0 - cos1 * cos1;
+
+        let sin2_2 = sin2_1 * (n1 / n2).powi(2);
+        if sin2_2 >= 1.0 {
+            // light can't enter the film, and tunnels through it when it is
+            // thin, so the film is left out
+            return match substrate {
+                Substrate::Dielectric(n3) => fresnel(n1, n3, cos1),
+                Substrate::Conductor(reflectance) => reflectance,
+            };
+        }
+        let cos2 = (1.0 - sin2_2).sqrt();
+        let (r12s, r12p) = amplitudes(n1, cos1, n2, cos2);
+
+        let (r23s, r23p) = match substrate {
+            Substrate::Dielectric(n3) => {
+                let sin2_3 = sin2_1 * (n1 / n3).powi(2);
+                if sin2_3 >= 1.0 {
+                    // total internal reflection below the film
+                    return 1.0;
+                }
+                amplitudes(n2, cos2, n3, (1.0 - sin2_3).sqrt())
+            }
+            // conductors reflect with about half a wave of phase shift
+            Substrate::Conductor(reflectance) => {
+                let r = -reflectance.clamp(0.0, 1.0).sqrt();
+                (r, r)
+            }
+        };
+
+        // phase difference between the light reflected at the top and at
+        // the bottom of the film
+        let phase = 4.0 * PI * n2 * thickness * cos2 / wavelength;
+        0.5 * (airy(r12s, r23s, phase) + airy(r12p, r23p, phase))
+    }
+}
+
+fn validate_thickness(thickness: f64) -> Result<()> {
+    if thickness.is_nan() || thickness < 0.0 || thickness.is_infinite() {
+        return Err(Error::InvalidParameter(format!(
+            "thin film thickness {} nm needs to be finite and not negative",
+            thickness
+        )));
+    }
+    Ok(())
+}
+
+// Fresnel amplitude reflection coefficients for s and p polarized light
+// going from index n_i to n_t
+fn amplitudes(n_i: f64, cos_i: f64, n_t: f64, cos_t: f64) -> (f64, f64) {
+    let s = (n_i * cos_i - n_t * cos_t) / (n_i * cos_i + n_t * cos_t);
+    let p = (n_t * cos_i - n_i * cos_t) / (n_t * cos_i + n_i * cos_t);
+    (s, p)
+}
+
+// reflectance of a bare interface between dielectrics
+fn fresnel(n1: f64, n3: f64, cos1: f64) -> f64 {
+    let sin2_3 = (1.0 - cos1 * cos1) * (n1 / n3).powi(2);
+    if sin2_3 >= 1.0 {
+        return 1.0;
+    }
+    let (s, p) = amplitudes(n1, cos1, n3, (1.0 - sin2_3).sqrt());
+    0.5 * (s * s + p * p)
+}
+
+// |r|² of the film with real amplitude coefficients r12 at its top and r23
+// at its bottom
+fn airy(r12: f64, r23: f64, phase: f64) -> f64 {
+    let interference = 2.0 * r12 * r23 * phase.cos();
+    (r12 * r12 + r23 * r23 + interference) / (1.0 + r12 * r12 * r23 * r23 + interference)
+}
@@ -1,4 +1,4 @@
-use std::{fmt, ops};
+use std::{f64::consts::PI, fmt, ops};
 
 use crate::math::rng::{random_double, random_double_range};
 
@@ -143,11 +143,16 @@ impl Vec3 {
     }
 
     pub fn random_unit_vector() -> Self {
-        loop {
-            let p = Self::random_range(-1.0, 1.0);
-            if 1e-160 <= p.length_squared() && p.length_squared() <= 1.0 {
-                return p.unit_vector();
-            }
+        Self::sample_unit_vector((random_double(), random_double()))
+    }
+
+    // maps a uniform 2D sample to a uniformly distributed unit vector
+    pub fn sample_unit_vector(u: (f64, f64)) -> Self {
+        let z = 1.0 - 2.0 * u.0;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.1;
+        Self {
+            e: [r * phi.cos(), r * phi.sin(), z],
         }
     }
 
@@ -160,18 +165,22 @@ impl Vec3 {
         }
     }
 
-    pub fn random_in_unit_disk() -> Self {
-        loop {
-            let p = Self {
-                e: [
-                    random_double_range(-1.0, 1.0),
-                    random_double_range(-1.0, 1.0),
-                    0.0,
-                ],
-            };
-            if p.length_squared() < 1.0 {
-                return p;
-            }
+    // maps a uniform 2D sample to the unit disk with the concentric mapping,
+    // which keeps stratified samples stratified
+    pub fn sample_in_unit_disk(u: (f64, f64)) -> Self {
+        let a = 2.0 * u.0 - 1.0;
+        let b = 2.0 * u.1 - 1.0;
+        if a == 0.0 && b == 0.0 {
+            return Self { e: [0.0, 0.0, 0.0] };
+        }
+
+        let (r, theta) = if a.abs() > b.abs() {
+            (a, PI / 4.0 * (b / a))
+        } else {
+            (b, PI / 2.0 - PI / 4.0 * (a / b))
+        };
+        Self {
+            e: [r * theta.cos(), r * theta.sin(), 0.0],
         }
     }
 